@@ -34,8 +34,7 @@ fn test() {
     let handle = thread::spawn(move || {
         print!("\n  child thread started");
         flush();
-        loop {
-            let t = share1.de_q();
+        while let Some(t) = share1.de_q() {
             print!("\n  dequeued {} on child thread", t);
             flush();
             if &t == "quit" {
@@ -48,7 +47,7 @@ fn test() {
 
     /*-- main thread enqueues messages --*/
     for i in 0..5 {
-        let msg = format!("msg #{}", i.to_string());
+        let msg = format!("msg #{}", i);
         print!("\n  enqueued {:?} on main thread", msg);
         flush();
         share2.en_q(msg);
@@ -14,54 +14,395 @@
    
    There is another alternative, based on Rust channels, which 
    are essentially blocking queues.
+
+   peak_len() and total_enqueued() report the deepest the
+   queue has been and how many elements have passed
+   through it, read from atomics without taking the lock.
+   waiting() reports how many threads are blocked in de_q
+   or de_q_timeout, the same way.
+
+   close() ends the queue's use: waiting threads wake, de_q
+   returns what is left, then None, and en_q discards its
+   input.
+
+   de_q_while(run) is de_q for a consumer with its own
+   stopping signal: once run is cleared, and wake_all()
+   called, it returns None though the queue stays open,
+   so one of several consumers can be retired while it
+   waits.
+
+   fair() makes a queue serve threads waiting in de_q in
+   the order they arrived, each taking a ticket and waiting
+   for its turn, so no consumer starves under contention.
+   Each en_q then wakes every waiter, so it costs more
+   than the default.
+
+   PriorityBlockingQueue, in module priority, dequeues
+   higher priority elements first, FIFO within a level.
+
+   Feature "yield_hook" adds set_yield_hook, in module
+   yield_hook, for tests that force interleavings.
 */
 #![allow(dead_code)]
+mod priority;
+pub use priority::*;
+#[cfg(feature = "yield_hook")]
+mod yield_hook;
+#[cfg(feature = "yield_hook")]
+pub use yield_hook::{YieldHook, YieldPoint};
 use std::sync::*;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::collections::*;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 /// Thread-safe queue that blocks de_q on empty
+/// and, if bounded, blocks en_q on full
 pub struct BlockingQueue<T> {
     q: Mutex<VecDeque<T>>,
     cv: Condvar,
+    not_full: Condvar,
+    cap: Option<usize>,
+    closed: AtomicBool,
+    peak: AtomicUsize,
+    total: AtomicUsize,
+    waiting: AtomicUsize,
+    fair: bool,
+    line: Mutex<VecDeque<u64>>,  // tickets of fair waiters, locked under q
+    next_ticket: AtomicU64,
+    #[cfg(feature = "yield_hook")]
+    hook: yield_hook::HookSlot,
 }
 impl<T> std::default::Default for BlockingQueue<T> {
     fn default() -> BlockingQueue<T> {
-        BlockingQueue {
-            q: Mutex::new(VecDeque::new()),
-            cv: Condvar::new(),
-        }
+        BlockingQueue::new()
     }
 }
 impl<T> BlockingQueue<T> {
-    /// Create empty blocking queue
+    /// Create empty, unbounded blocking queue
     pub fn new() -> Self {
         Self {
             q: Mutex::new(VecDeque::new()),
             cv: Condvar::new(),
+            not_full: Condvar::new(),
+            cap: None,
+            closed: AtomicBool::new(false),
+            peak: AtomicUsize::new(0),
+            total: AtomicUsize::new(0),
+            waiting: AtomicUsize::new(0),
+            fair: false,
+            line: Mutex::new(VecDeque::new()),
+            next_ticket: AtomicU64::new(0),
+            #[cfg(feature = "yield_hook")]
+            hook: yield_hook::HookSlot::default(),
         }
     }
+    /// Create empty blocking queue holding at most cap elements
+    pub fn with_capacity(cap: usize) -> Self {
+        assert!(cap > 0);
+        Self {
+            q: Mutex::new(VecDeque::with_capacity(cap)),
+            cv: Condvar::new(),
+            not_full: Condvar::new(),
+            cap: Some(cap),
+            closed: AtomicBool::new(false),
+            peak: AtomicUsize::new(0),
+            total: AtomicUsize::new(0),
+            waiting: AtomicUsize::new(0),
+            fair: false,
+            line: Mutex::new(VecDeque::new()),
+            next_ticket: AtomicU64::new(0),
+            #[cfg(feature = "yield_hook")]
+            hook: yield_hook::HookSlot::default(),
+        }
+    }
+    /// serve de_q and de_q_timeout callers in arrival order
+    /// - try_de_q leaves elements to waiting threads
+    pub fn fair(mut self) -> Self {
+        self.fair = true;
+        self
+    }
+    /// true if made with fair
+    pub fn is_fair(&self) -> bool {
+        self.fair
+    }
+    /// capacity of bounded queue, None if unbounded
+    pub fn capacity(&self) -> Option<usize> {
+        self.cap
+    }
+    /// close queue, waking all waiting threads
+    /// - elements already queued can still be dequeued
+    /// - flag is set under the lock so no waiter misses it
+    pub fn close(&self) {
+        let _lq = self.q.lock().unwrap();
+        self.closed.store(true, Ordering::SeqCst);
+        self.cv.notify_all();
+        self.not_full.notify_all();
+    }
+    /// true once close has been called
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
     /// push input on back of queue
+    /// - blocks while a bounded queue is full
+    /// - discards input if queue is, or becomes, closed
     /// - unrecoverable if lock fails so just unwrap
     pub fn en_q(&self, t:T) {
+        #[cfg(feature = "yield_hook")]
+        self.yield_at(YieldPoint::EnQ);
         let mut lq = self.q.lock().unwrap();
+        while self.is_full(&lq) && !self.is_closed() {
+            lq = self.not_full.wait(lq).unwrap();
+        }
+        if self.is_closed() {
+            return;
+        }
         lq.push_back(t);
-        self.cv.notify_one();
+        self.count_en_q(lq.len());
+        self.wake_consumer();
+    }
+    /// push input on back of queue if there is room
+    /// - never waits, returns input if bounded queue is full
+    ///   or queue is closed
+    pub fn try_en_q(&self, t:T) -> Result<(), T> {
+        #[cfg(feature = "yield_hook")]
+        self.yield_at(YieldPoint::EnQ);
+        let mut lq = self.q.lock().unwrap();
+        if self.is_full(&lq) || self.is_closed() {
+            return Err(t);
+        }
+        lq.push_back(t);
+        self.count_en_q(lq.len());
+        self.wake_consumer();
+        Ok(())
+    }
+    /*-- caller holds lock, len is depth after push --*/
+    fn count_en_q(&self, len: usize) {
+        self.peak.fetch_max(len, Ordering::Relaxed);
+        self.total.fetch_add(1, Ordering::Relaxed);
+    }
+    /*-- a fair queue wakes all, so the thread whose turn it is sees it --*/
+    fn wake_consumer(&self) {
+        if self.fair {
+            self.cv.notify_all();
+        } else {
+            self.cv.notify_one();
+        }
+    }
+    fn is_full(&self, lq: &VecDeque<T>) -> bool {
+        match self.cap {
+            Some(cap) => lq.len() >= cap,
+            None => false,
+        }
     }
     /// pop element from front of queue
+    /// - blocks while queue is empty
+    /// - returns None once queue is closed and empty
     /// - unrecoverable if lock fails so just unwrap
     /// - same for condition variable
-    pub fn de_q(&self) -> T {
+    pub fn de_q(&self) -> Option<T> {
+        #[cfg(feature = "yield_hook")]
+        self.yield_at(YieldPoint::DeQ);
+        let mut lq = self.q.lock().unwrap();
+        if self.fair {
+            return self.de_q_in_turn(lq, None, None);
+        }
+        if lq.is_empty() {
+            self.waiting.fetch_add(1, Ordering::Relaxed);
+            while lq.is_empty() && !self.is_closed() {
+                lq = self.cv.wait(lq).unwrap();
+            }
+            self.waiting.fetch_sub(1, Ordering::Relaxed);
+        }
+        let t = lq.pop_front();
+        if t.is_some() {
+            self.not_full.notify_one();
+        }
+        t
+    }
+    /// pop element from front of queue while run is set
+    /// - blocks while queue is empty, as de_q does
+    /// - returns None, leaving elements queued, if run is
+    ///   clear and the queue isn't closed, checked on entry
+    ///   and at each wakeup, so clear run then call wake_all
+    /// - a closed queue is drained as for de_q, whatever run
+    ///   holds
+    pub fn de_q_while(&self, run: &AtomicBool) -> Option<T> {
+        #[cfg(feature = "yield_hook")]
+        self.yield_at(YieldPoint::DeQ);
+        let mut lq = self.q.lock().unwrap();
+        if self.fair {
+            return self.de_q_in_turn(lq, None, Some(run));
+        }
+        let stopped = || !run.load(Ordering::SeqCst) && !self.is_closed();
+        if lq.is_empty() && !stopped() {
+            self.waiting.fetch_add(1, Ordering::Relaxed);
+            while lq.is_empty() && !self.is_closed() && !stopped() {
+                lq = self.cv.wait(lq).unwrap();
+            }
+            self.waiting.fetch_sub(1, Ordering::Relaxed);
+        }
+        if stopped() {
+            return None;
+        }
+        let t = lq.pop_front();
+        if t.is_some() {
+            self.not_full.notify_one();
+        }
+        t
+    }
+    /// wake every waiting thread, so de_q_while callers
+    /// recheck their run signals, others wait on
+    /// - taken under the lock, so a caller that cleared run
+    ///   first can't be missed
+    pub fn wake_all(&self) {
+        let _lq = self.q.lock().unwrap();
+        self.cv.notify_all();
+    }
+    /// pop element from front of queue if there is one
+    /// - never waits, returns None if queue is empty
+    /// - a fair queue leaves one element for each thread
+    ///   waiting in de_q, so takes only those to spare
+    pub fn try_de_q(&self) -> Option<T> {
+        #[cfg(feature = "yield_hook")]
+        self.yield_at(YieldPoint::DeQ);
         let mut lq = self.q.lock().unwrap();
-        while lq.len() == 0 {
-            lq = self.cv.wait(lq).unwrap();
+        if self.fair && lq.len() <= self.line.lock().unwrap().len() {
+            return None;
+        }
+        let t = lq.pop_front();
+        if t.is_some() {
+            self.not_full.notify_one();
+        }
+        t
+    }
+    /// pop element from front of queue, waiting at most dur
+    /// - returns None if queue is still empty at deadline,
+    ///   or is closed and empty
+    /// - rechecks queue after every wakeup, so spurious
+    ///   wakeups neither return early nor extend the wait
+    /// - a dur too long to add to now, e.g., Duration::MAX,
+    ///   waits as de_q does
+    pub fn de_q_timeout(&self, dur: Duration) -> Option<T> {
+        #[cfg(feature = "yield_hook")]
+        self.yield_at(YieldPoint::DeQ);
+        let deadline = Instant::now().checked_add(dur);
+        let mut lq = self.q.lock().unwrap();
+        if self.fair {
+            return self.de_q_in_turn(lq, deadline, None);
+        }
+        if lq.is_empty() {
+            self.waiting.fetch_add(1, Ordering::Relaxed);
+            while lq.is_empty() && !self.is_closed() {
+                let Some(deadline) = deadline else {
+                    lq = self.cv.wait(lq).unwrap();
+                    continue;
+                };
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                lq = self.cv.wait_timeout(lq, deadline - now).unwrap().0;
+            }
+            self.waiting.fetch_sub(1, Ordering::Relaxed);
+        }
+        let t = lq.pop_front();
+        if t.is_some() {
+            self.not_full.notify_one();
+        }
+        t
+    }
+    /*-----------------------------------------------------
+      fair de_q, waiting until deadline, if any
+      - served once its ticket heads the line and an
+        element is queued, or once the queue is closed
+      - gives up, as for de_q_while, once run, if any, is
+        clear on an open queue
+      - leaves the line when served or timed out, waking
+        the rest so the next ticket is seen
+    */
+    fn de_q_in_turn(
+        &self, mut lq: MutexGuard<'_, VecDeque<T>>, deadline: Option<Instant>,
+        run: Option<&AtomicBool>
+    ) -> Option<T> 
+    {
+        let stopped = || {
+            run.map(|r| !r.load(Ordering::SeqCst)).unwrap_or(false) && !self.is_closed()
+        };
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        self.line.lock().unwrap().push_back(ticket);
+        let my_turn = |lq: &VecDeque<T>| {
+            !lq.is_empty() && self.line.lock().unwrap().front() == Some(&ticket)
+        };
+        let waits = !my_turn(&lq) && !self.is_closed() && !stopped();
+        if waits {
+            self.waiting.fetch_add(1, Ordering::Relaxed);
+        }
+        while !my_turn(&lq) && !self.is_closed() && !stopped() {
+            match deadline {
+                None => lq = self.cv.wait(lq).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    lq = self.cv.wait_timeout(lq, deadline - now).unwrap().0;
+                }
+            }
+        }
+        if waits {
+            self.waiting.fetch_sub(1, Ordering::Relaxed);
+        }
+        let served = !stopped() && (my_turn(&lq) || self.is_closed());
+        let mut line = self.line.lock().unwrap();
+        line.retain(|t| *t != ticket);
+        let t = if served { lq.pop_front() } else { None };
+        if t.is_some() {
+            self.not_full.notify_one();
+        }
+        if !line.is_empty() {
+            self.cv.notify_all();
+        }
+        t
+    }
+    /// clone of front element, left in the queue, None if
+    /// queue is empty, never waits
+    /// - peek then de_q isn't atomic, another consumer may
+    ///   take the element in between, so it suits queues
+    ///   with a single consumer
+    pub fn peek(&self) -> Option<T> where T: Clone {
+        self.q.lock().unwrap().front().cloned()
+    }
+    /// remove and return all queued elements, front first
+    /// - one lock, so no element enqueued meanwhile is
+    ///   split from the rest, never waits
+    pub fn drain(&self) -> Vec<T> {
+        let items: Vec<T> = self.q.lock().unwrap().drain(..).collect();
+        if !items.is_empty() {
+            self.not_full.notify_all();
         }
-        lq.pop_front().unwrap()
+        items
     }
     /// return number of elements in queue
     pub fn len(&self) -> usize {
         self.q.lock().unwrap().len()
     }
+    /// true if queue holds no elements
+    pub fn is_empty(&self) -> bool {
+        self.q.lock().unwrap().is_empty()
+    }
+    /// largest number of elements queue has held
+    pub fn peak_len(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+    /// number of elements ever enqueued
+    pub fn total_enqueued(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+    /// number of threads waiting for an element
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::Relaxed)
+    }
 }
 
 #[cfg(test)]
@@ -82,7 +423,262 @@ mod tests {
     fn bq_de_queue() {
         let bq = BlockingQueue::<f64>::new();
         bq.en_q(3.5);
-        assert_eq!(bq.de_q(), 3.5);
+        assert_eq!(bq.de_q(), Some(3.5));
         assert_eq!(bq.len(), 0);
     }
+    #[test]
+    fn bq_try_en_queue_bounded() {
+        let bq = BlockingQueue::<f64>::with_capacity(1);
+        assert_eq!(bq.capacity(), Some(1));
+        assert_eq!(bq.try_en_q(1.5), Ok(()));
+        assert_eq!(bq.try_en_q(2.5), Err(2.5));
+        assert_eq!(bq.de_q(), Some(1.5));
+        assert_eq!(bq.try_en_q(2.5), Ok(()));
+    }
+    #[test]
+    fn bq_bounded_en_queue_blocks_until_drained() {
+        let bq = Arc::new(BlockingQueue::<usize>::with_capacity(2));
+        bq.en_q(1);
+        bq.en_q(2);
+        let bq1 = Arc::clone(&bq);
+        let producer = std::thread::spawn(move || bq1.en_q(3));
+        std::thread::sleep(Duration::from_millis(50));
+        /*-- producer is parked on not_full --*/
+        assert!(!producer.is_finished());
+        assert_eq!(bq.len(), 2);
+
+        assert_eq!(bq.de_q(), Some(1));
+        let _ = producer.join();
+        assert_eq!(bq.len(), 2);
+        assert_eq!(bq.de_q(), Some(2));
+        assert_eq!(bq.de_q(), Some(3));
+    }
+    #[test]
+    fn bq_new_is_unbounded() {
+        let bq = BlockingQueue::<usize>::new();
+        assert_eq!(bq.capacity(), None);
+        for i in 0..1000 {
+            assert_eq!(bq.try_en_q(i), Ok(()));
+        }
+        assert_eq!(bq.len(), 1000);
+    }
+    #[test]
+    fn bq_try_de_queue() {
+        let bq = BlockingQueue::<f64>::new();
+        assert_eq!(bq.try_de_q(), None);
+        bq.en_q(3.5);
+        assert_eq!(bq.try_de_q(), Some(3.5));
+        assert_eq!(bq.len(), 0);
+    }
+    #[test]
+    fn bq_de_queue_timeout() {
+        let bq = Arc::new(BlockingQueue::<f64>::new());
+        let start = Instant::now();
+        assert_eq!(bq.de_q_timeout(Duration::from_millis(50)), None);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let bq1 = Arc::clone(&bq);
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            bq1.en_q(3.5);
+        });
+        assert_eq!(bq.de_q_timeout(Duration::from_secs(5)), Some(3.5));
+        let _ = handle.join();
+
+        /*-- too long for a deadline, so waits as de_q does --*/
+        for bq in [bq, Arc::new(BlockingQueue::<f64>::new().fair())] {
+            let bq1 = Arc::clone(&bq);
+            let handle = std::thread::spawn(move || bq1.de_q_timeout(Duration::MAX));
+            while bq.waiting() == 0 {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            bq.en_q(4.5);
+            assert_eq!(handle.join().unwrap(), Some(4.5));
+        }
+    }
+    #[test]
+    fn bq_peak_and_total() {
+        let bq = BlockingQueue::<usize>::new();
+        for i in 0..5 {
+            bq.en_q(i);
+        }
+        for _ in 0..3 {
+            let _ = bq.de_q();
+        }
+        bq.en_q(5);
+        assert_eq!(bq.try_en_q(6), Ok(()));
+        assert_eq!(bq.len(), 4);
+        assert_eq!(bq.peak_len(), 5);
+        assert_eq!(bq.total_enqueued(), 7);
+    }
+    #[test]
+    fn bq_waiting_counts_blocked_de_q() {
+        let bq = Arc::new(BlockingQueue::<usize>::new());
+        assert_eq!(bq.waiting(), 0);
+        let bq1 = Arc::clone(&bq);
+        let handle = std::thread::spawn(move || bq1.de_q());
+        while bq.waiting() == 0 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        bq.en_q(7);
+        assert_eq!(handle.join().unwrap(), Some(7));
+        assert_eq!(bq.waiting(), 0);
+    }
+    #[test]
+    fn bq_de_q_while_gives_up_when_woken() {
+        for bq in [BlockingQueue::<usize>::new(), BlockingQueue::<usize>::new().fair()] {
+            let bq = Arc::new(bq);
+            let stay = Arc::new(AtomicBool::new(true));
+            let retire = Arc::new(AtomicBool::new(true));
+            let consumer = |run: &Arc<AtomicBool>| {
+                let (bq, run) = (Arc::clone(&bq), Arc::clone(run));
+                std::thread::spawn(move || bq.de_q_while(&run))
+            };
+            let (staying, retiring) = (consumer(&stay), consumer(&retire));
+            while bq.waiting() < 2 {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            retire.store(false, Ordering::SeqCst);
+            bq.wake_all();
+            assert_eq!(retiring.join().unwrap(), None);
+            assert_eq!(bq.waiting(), 1);
+            bq.en_q(1);
+            assert_eq!(staying.join().unwrap(), Some(1));
+
+            /*-- a cleared signal leaves work for others, unless closed --*/
+            bq.en_q(2);
+            assert_eq!(bq.de_q_while(&retire), None);
+            bq.close();
+            assert_eq!(bq.de_q_while(&retire), Some(2));
+            assert_eq!(bq.de_q_while(&stay), None);
+        }
+    }
+    #[test]
+    fn bq_peek_leaves_front_in_place() {
+        let bq = BlockingQueue::<usize>::new();
+        assert_eq!(bq.peek(), None);
+        bq.en_q(1);
+        bq.en_q(2);
+        assert_eq!(bq.peek(), Some(1));
+        assert_eq!(bq.peek(), Some(1));
+        assert_eq!(bq.len(), 2);
+        assert_eq!(bq.de_q(), Some(1));
+        assert_eq!(bq.peek(), Some(2));
+    }
+    #[test]
+    fn bq_drain() {
+        let bq = BlockingQueue::<usize>::with_capacity(3);
+        assert!(bq.drain().is_empty());
+        for i in 0..3 {
+            bq.en_q(i);
+        }
+        assert_eq!(bq.drain(), vec![0, 1, 2]);
+        assert!(bq.is_empty());
+        assert_eq!(bq.try_en_q(3), Ok(()));
+    }
+    #[test]
+    fn bq_fair_serves_waiters_in_arrival_order() {
+        /*-- true if waiters are served first come, first served --*/
+        let in_order = |bq: BlockingQueue<usize>| {
+            let bq = Arc::new(bq);
+            /*-- waiters line up one at a time, so arrival order is known --*/
+            let waiters: Vec<_> = (0..4).map(|n| {
+                let bq1 = Arc::clone(&bq);
+                let waiter = std::thread::spawn(move || bq1.de_q());
+                while bq.waiting() <= n {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                waiter
+            }).collect();
+            /*-- one item each lands before any waiter is woken, then a late consumer asks --*/
+            bq.q.lock().unwrap().extend(0..4);
+            let late = bq.try_de_q();
+            /*-- one more, so every waiter is served whoever took the front --*/
+            bq.en_q(4);
+            bq.cv.notify_all();
+            let served: Vec<_> = waiters.into_iter().map(|w| w.join().unwrap()).collect();
+            late.is_none() && served == [Some(0), Some(1), Some(2), Some(3)]
+        };
+        let fair = BlockingQueue::<usize>::new().fair();
+        assert!(fair.is_fair());
+        assert!(in_order(fair));
+        /*-- the default queue lets the late consumer take the front element --*/
+        assert!(!in_order(BlockingQueue::<usize>::new()));
+    }
+    #[test]
+    fn bq_fair_try_de_q_takes_spare_elements() {
+        let bq = Arc::new(BlockingQueue::<usize>::new().fair());
+        let bq1 = Arc::clone(&bq);
+        let waiter = std::thread::spawn(move || bq1.de_q());
+        while bq.waiting() == 0 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        /*-- front element is the waiter's, the second is spare --*/
+        bq.q.lock().unwrap().extend(0..2);
+        assert_eq!(bq.try_de_q(), Some(0));
+        assert_eq!(bq.try_de_q(), None);
+        bq.cv.notify_all();
+        assert_eq!(waiter.join().unwrap(), Some(1));
+    }
+    #[test]
+    fn bq_fair_serves_consumers_evenly() {
+        const CONSUMERS: usize = 4;
+        const ROUNDS: usize = 500;
+        let bq = Arc::new(BlockingQueue::<usize>::new().fair());
+        let consumers: Vec<_> = (0..CONSUMERS).map(|_| {
+            let bq = Arc::clone(&bq);
+            std::thread::spawn(move || {
+                let mut served = 0usize;
+                while bq.de_q().is_some() {
+                    served += 1;
+                }
+                served
+            })
+        }).collect();
+        /*-----------------------------------------------
+          sustained load, paced by queue state, not time:
+          each round a burst of one item per consumer
+          arrives once all are back in line
+        */
+        for round in 0..ROUNDS {
+            while bq.waiting() < CONSUMERS || !bq.is_empty() {
+                std::thread::sleep(Duration::from_micros(50));
+            }
+            for i in 0..CONSUMERS {
+                bq.en_q(round * CONSUMERS + i);
+            }
+        }
+        while !bq.is_empty() {
+            std::thread::sleep(Duration::from_micros(50));
+        }
+        bq.close();
+        let counts: Vec<usize> = consumers.into_iter().map(|c| c.join().unwrap()).collect();
+        assert_eq!(counts.iter().sum::<usize>(), CONSUMERS * ROUNDS);
+        for count in counts.iter() {
+            assert!(count.abs_diff(ROUNDS) <= ROUNDS / 5, "uneven service: {:?}", counts);
+        }
+    }
+    #[test]
+    fn bq_close_wakes_waiters() {
+        let bq = Arc::new(BlockingQueue::<f64>::with_capacity(1));
+        bq.en_q(1.5);
+        let bq1 = Arc::clone(&bq);
+        let producer = std::thread::spawn(move || bq1.en_q(2.5));
+        let bq2 = Arc::new(BlockingQueue::<f64>::new());
+        let bq3 = Arc::clone(&bq2);
+        let consumer = std::thread::spawn(move || bq3.de_q());
+        std::thread::sleep(Duration::from_millis(20));
+
+        bq.close();
+        bq2.close();
+        let _ = producer.join();
+        assert_eq!(consumer.join().unwrap(), None);
+        /*-- queued element survives close, blocked en_q's doesn't --*/
+        assert!(bq.is_closed());
+        assert_eq!(bq.de_q(), Some(1.5));
+        assert_eq!(bq.de_q(), None);
+        bq.en_q(3.5);
+        assert_eq!(bq.try_en_q(3.5), Err(3.5));
+        assert!(bq.is_empty());
+    }
 }
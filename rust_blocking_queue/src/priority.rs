@@ -0,0 +1,239 @@
+/////////////////////////////////////////////////////////////
+// rust_blocking_queue::priority.rs - priority queue       //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   PriorityBlockingQueue is a BlockingQueue whose elements
+   each carry a priority level, a u8, higher first.
+
+   de_q always returns an element of the highest level
+   queued, and elements of equal level leave in the order
+   they arrived, so en_q, at DEFAULT_PRIORITY, behaves as
+   BlockingQueue's does until something is queued at a
+   higher level.
+
+   A bounded queue counts elements of every level against
+   its capacity, so a high priority en_q waits, like any
+   other, while the queue is full.
+*/
+use std::sync::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::*;
+use std::time::{Duration, Instant};
+
+/// level used by en_q and try_en_q
+pub const DEFAULT_PRIORITY: u8 = 0;
+
+#[derive(Debug)]
+struct Levels<T> {
+    queues: BTreeMap<u8, VecDeque<T>>,
+    len: usize,
+}
+impl<T> Levels<T> {
+    fn push(&mut self, t: T, level: u8) {
+        self.queues.entry(level).or_default().push_back(t);
+        self.len += 1;
+    }
+    fn pop(&mut self) -> Option<T> {
+        let mut top = self.queues.last_entry()?;
+        let t = top.get_mut().pop_front();
+        if top.get().is_empty() {
+            top.remove();
+        }
+        self.len -= 1;
+        t
+    }
+}
+
+#[derive(Debug)]
+/// Thread-safe queue that blocks de_q on empty, and, if
+/// bounded, blocks en_q on full, dequeuing the highest
+/// priority element first
+pub struct PriorityBlockingQueue<T> {
+    q: Mutex<Levels<T>>,
+    cv: Condvar,
+    not_full: Condvar,
+    cap: Option<usize>,
+    closed: AtomicBool,
+}
+impl<T> std::default::Default for PriorityBlockingQueue<T> {
+    fn default() -> PriorityBlockingQueue<T> {
+        PriorityBlockingQueue::new()
+    }
+}
+impl<T> PriorityBlockingQueue<T> {
+    /// Create empty, unbounded priority queue
+    pub fn new() -> Self {
+        Self {
+            q: Mutex::new(Levels { queues: BTreeMap::new(), len: 0 }),
+            cv: Condvar::new(),
+            not_full: Condvar::new(),
+            cap: None,
+            closed: AtomicBool::new(false),
+        }
+    }
+    /// Create empty priority queue holding at most cap elements
+    pub fn with_capacity(cap: usize) -> Self {
+        assert!(cap > 0);
+        Self { cap: Some(cap), ..Self::new() }
+    }
+    /// capacity of bounded queue, None if unbounded
+    pub fn capacity(&self) -> Option<usize> {
+        self.cap
+    }
+    /// close queue, waking all waiting threads
+    /// - elements already queued can still be dequeued
+    pub fn close(&self) {
+        let _lq = self.q.lock().unwrap();
+        self.closed.store(true, Ordering::SeqCst);
+        self.cv.notify_all();
+        self.not_full.notify_all();
+    }
+    /// true once close has been called
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+    /// queue input at DEFAULT_PRIORITY
+    pub fn en_q(&self, t: T) {
+        self.en_q_priority(t, DEFAULT_PRIORITY);
+    }
+    /// queue input behind elements of its level, ahead of
+    /// all lower levels
+    /// - blocks while a bounded queue is full
+    /// - discards input if queue is, or becomes, closed
+    pub fn en_q_priority(&self, t: T, level: u8) {
+        let mut lq = self.q.lock().unwrap();
+        while self.is_full(&lq) && !self.is_closed() {
+            lq = self.not_full.wait(lq).unwrap();
+        }
+        if self.is_closed() {
+            return;
+        }
+        lq.push(t, level);
+        self.cv.notify_one();
+    }
+    /// queue input at DEFAULT_PRIORITY if there is room
+    pub fn try_en_q(&self, t: T) -> Result<(), T> {
+        self.try_en_q_priority(t, DEFAULT_PRIORITY)
+    }
+    /// queue input at level if there is room
+    /// - never waits, returns input if bounded queue is full
+    ///   or queue is closed
+    pub fn try_en_q_priority(&self, t: T, level: u8) -> Result<(), T> {
+        let mut lq = self.q.lock().unwrap();
+        if self.is_full(&lq) || self.is_closed() {
+            return Err(t);
+        }
+        lq.push(t, level);
+        self.cv.notify_one();
+        Ok(())
+    }
+    fn is_full(&self, lq: &Levels<T>) -> bool {
+        match self.cap {
+            Some(cap) => lq.len >= cap,
+            None => false,
+        }
+    }
+    /// pop oldest element of the highest level queued
+    /// - blocks while queue is empty
+    /// - returns None once queue is closed and empty
+    pub fn de_q(&self) -> Option<T> {
+        let mut lq = self.q.lock().unwrap();
+        while lq.len == 0 && !self.is_closed() {
+            lq = self.cv.wait(lq).unwrap();
+        }
+        let t = lq.pop();
+        if t.is_some() {
+            self.not_full.notify_one();
+        }
+        t
+    }
+    /// same as de_q, but never waits, returns None if queue
+    /// is empty
+    pub fn try_de_q(&self) -> Option<T> {
+        let t = self.q.lock().unwrap().pop();
+        if t.is_some() {
+            self.not_full.notify_one();
+        }
+        t
+    }
+    /// same as de_q, waiting at most dur
+    /// - returns None if queue is still empty at deadline,
+    ///   or is closed and empty
+    /// - a dur too long to add to now, e.g., Duration::MAX,
+    ///   waits as de_q does
+    pub fn de_q_timeout(&self, dur: Duration) -> Option<T> {
+        let deadline = Instant::now().checked_add(dur);
+        let mut lq = self.q.lock().unwrap();
+        while lq.len == 0 && !self.is_closed() {
+            let Some(deadline) = deadline else {
+                lq = self.cv.wait(lq).unwrap();
+                continue;
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            lq = self.cv.wait_timeout(lq, deadline - now).unwrap().0;
+        }
+        let t = lq.pop();
+        if t.is_some() {
+            self.not_full.notify_one();
+        }
+        t
+    }
+    /// return number of elements in queue, of all levels
+    pub fn len(&self) -> usize {
+        self.q.lock().unwrap().len
+    }
+    /// true if queue holds no elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn pbq_highest_level_first_fifo_within_level() {
+        let pq = PriorityBlockingQueue::<&str>::new();
+        pq.en_q("data 1");
+        pq.en_q("data 2");
+        pq.en_q_priority("urgent 1", 9);
+        pq.en_q_priority("ping", 5);
+        pq.en_q_priority("urgent 2", 9);
+        assert_eq!(pq.len(), 5);
+        let order: Vec<&str> = std::iter::from_fn(|| pq.try_de_q()).collect();
+        assert_eq!(order, vec!["urgent 1", "urgent 2", "ping", "data 1", "data 2"]);
+        assert!(pq.is_empty());
+    }
+    #[test]
+    fn pbq_bounded_counts_every_level() {
+        let pq = Arc::new(PriorityBlockingQueue::<usize>::with_capacity(2));
+        pq.en_q(1);
+        assert_eq!(pq.try_en_q_priority(2, 7), Ok(()));
+        assert_eq!(pq.try_en_q_priority(3, 9), Err(3));
+        let pq1 = Arc::clone(&pq);
+        let consumer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            pq1.de_q()
+        });
+        pq.en_q_priority(3, 9);  // waits for consumer
+        assert_eq!(consumer.join().unwrap(), Some(2));
+        assert_eq!(pq.de_q(), Some(3));
+        pq.close();
+        assert_eq!(pq.de_q(), Some(1));
+        assert_eq!(pq.de_q_timeout(Duration::from_secs(5)), None);
+    }
+    #[test]
+    fn pbq_de_q_timeout_too_long_for_a_deadline() {
+        let pq = Arc::new(PriorityBlockingQueue::<usize>::new());
+        let pq1 = Arc::clone(&pq);
+        let consumer = std::thread::spawn(move || pq1.de_q_timeout(Duration::MAX));
+        std::thread::sleep(Duration::from_millis(20));
+        pq.en_q_priority(4, 3);
+        assert_eq!(consumer.join().unwrap(), Some(4));
+    }
+}
@@ -0,0 +1,97 @@
+/////////////////////////////////////////////////////////////
+// rust_blocking_queue::yield_hook.rs - scheduling hook    //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   Feature "yield_hook", for tests only:
+   - set_yield_hook(f) has BlockingQueue call f on entry
+     to each en_q and de_q, before it takes its lock, so f
+     may block the calling thread until another has run,
+     forcing the interleaving a test needs
+   - f is told which operation is calling, and may use
+     thread::current() to tell callers apart
+   - without the feature, neither the hook nor its calls
+     are compiled, so queues cost nothing extra
+*/
+use crate::BlockingQueue;
+use std::sync::{Arc, Mutex};
+
+/// operation calling the yield hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YieldPoint {
+    /// en_q or try_en_q
+    EnQ,
+    /// de_q, try_de_q, de_q_timeout, or de_q_while
+    DeQ,
+}
+
+/// called at each YieldPoint, see set_yield_hook
+pub type YieldHook = Arc<dyn Fn(YieldPoint) + Send + Sync>;
+
+/*-- hook, if set, with a Debug the closure lacks --*/
+#[derive(Default)]
+pub(crate) struct HookSlot(Mutex<Option<YieldHook>>);
+impl std::fmt::Debug for HookSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HookSlot({})", if self.0.lock().unwrap().is_some() { "set" } else { "unset" })
+    }
+}
+
+impl<T> BlockingQueue<T> {
+    /// call f at each YieldPoint, replacing any hook set
+    /// - f runs on the calling thread, outside the queue's
+    ///   lock, so it may block while other threads use
+    ///   the queue
+    pub fn set_yield_hook<F>(&self, f: F)
+    where F: Fn(YieldPoint) + Send + Sync + 'static
+    {
+        *self.hook.0.lock().unwrap() = Some(Arc::new(f));
+    }
+    /// stop calling the yield hook
+    pub fn clear_yield_hook(&self) {
+        *self.hook.0.lock().unwrap() = None;
+    }
+    /*-- slot unlocked before the call, so f may reenter the queue --*/
+    pub(crate) fn yield_at(&self, at: YieldPoint) {
+        let hook = self.hook.0.lock().unwrap().clone();
+        if let Some(hook) = hook {
+            hook(at);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Barrier, mpsc};
+    use std::thread;
+
+    /*-- peek then de_q, with a second consumer taking the element in between --*/
+    #[test]
+    fn hook_forces_peek_de_q_race() {
+        let bq = Arc::new(BlockingQueue::<u32>::new());
+        bq.en_q(1);
+        let rival_done = Arc::new(Barrier::new(2));
+        let (at_de_q, rival_go) = mpsc::channel::<()>();
+        let at_de_q = Mutex::new(at_de_q);
+        let done = Arc::clone(&rival_done);
+        bq.set_yield_hook(move |at| {
+            if at == YieldPoint::DeQ && thread::current().name() == Some("peeker") {
+                at_de_q.lock().unwrap().send(()).unwrap();
+                done.wait();
+            }
+        });
+        let q = Arc::clone(&bq);
+        let peeker = thread::Builder::new().name("peeker".to_string()).spawn(move || {
+            let seen = q.peek();
+            (seen, q.try_de_q())
+        }).unwrap();
+        /*-- rival runs only once peeker has peeked and is entering try_de_q --*/
+        rival_go.recv().unwrap();
+        assert_eq!(bq.try_de_q(), Some(1));
+        rival_done.wait();
+        assert_eq!(peeker.join().unwrap(), (Some(1), None));
+        bq.clear_yield_hook();
+    }
+}
@@ -0,0 +1,66 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::buffer_sizes.rs - BufReader/BufWriter sizes  //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   Demo:
+   Time a fixed payload, posted without waiting for
+   replies, for several CommConfig::buffer_size values
+   - start Listener with that buffer size
+   - post messages, then read all replies
+   - report elapsed time and throughput
+
+   cargo run --release --example buffer_sizes
+*/
+
+use rust_message::*;
+use rust_traits::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+use std::time::Instant;
+
+type Log = MuteLog;
+type M = Message;
+type P = CommProcessing<Log>;
+
+const NUM_MSGS: usize = 2_000;
+const BODY_SIZE: usize = 64 * 1024;
+
+fn run(size: usize) -> std::io::Result<f64> {
+    let config = CommConfig::default().buffer_size(size);
+    let mut lsnr = Listener::<P,Log>::new_with_config(4, config.clone());
+    let handle = lsnr.start("127.0.0.1:0")?;
+    let conn = Connector::<P,M,Log>::new_with_config(lsnr.local_addr().unwrap(), config)?;
+    let mut msg = Message::create_msg_bytes_fit(&vec![0; BODY_SIZE]);
+    msg.set_type(MessageType::FLUSH as u8);
+    let start = Instant::now();
+    for _ in 0..NUM_MSGS {
+        let _ = conn.post_message(msg.clone());
+    }
+    for _ in 0..NUM_MSGS {
+        if conn.get_message().is_none() {
+            break;
+        }
+    }
+    let secs = start.elapsed().as_secs_f64();
+    conn.shut_down();
+    lsnr.stop();
+    let _ = handle.join();
+    Ok(secs)
+}
+
+fn main() -> std::io::Result<()> {
+    print!("\n  -- {} msgs of {} bytes, each echoed back --", NUM_MSGS, BODY_SIZE);
+    for size in [1024, 8 * 1024, 64 * 1024, 256 * 1024, 1024 * 1024] {
+        let secs = run(size)?;
+        let mb = (2 * NUM_MSGS * BODY_SIZE) as f64 / (1024.0 * 1024.0);
+        print!(
+            "\n  buffer {:>8} bytes: {:>7.1} ms, {:>7.1} MB/s", 
+            size, 1000.0 * secs, mb / secs
+        );
+    }
+    println!("\n");
+    Ok(())
+}
@@ -9,7 +9,7 @@
    - start Connector component
    - send a few messages, ,observe replies
    - send END message to exit client handler
-   - send QUIT message to shut down Listener
+   - stop Listener, then join its thread
 */
 #![allow(unused_imports)]
 #![allow(dead_code)]
@@ -42,33 +42,34 @@ fn main() {
     let handle = rslt.unwrap();
     
     let rslt = Connector::<P,M,Log>::new(addr);
-    if rslt.is_ok() {
+    if let Ok(conn) = rslt {
         print!("\n  connected to: {:?}",addr);
         let _ = std::io::stdout().flush();
-        let conn = rslt.unwrap();
         let mut msg = Message::create_msg_str_fit("message #1");
         msg.set_type(MessageType::FLUSH as u8);
         print!("\n  main posting msg: {:?}", msg.get_content_str().unwrap());
         // let _ = std::io::stdout().flush();
         msg.show_message(8);
         let _ = std::io::stdout().flush();
-        conn.post_message(msg);
-        let msg = conn.get_message();
-        print!("\n\n  main received msg: {:?}",msg.get_content_str().unwrap());
+        let _ = conn.post_message(msg);
+        if let Some(msg) = conn.get_message() {
+            print!("\n\n  main received msg: {:?}",msg.get_content_str().unwrap());
+        }
         let _ = std::io::stdout().flush();
     
         let mut msg = Message::create_msg_str_fit("message #2");
         msg.set_type(MessageType::FLUSH as u8);
         print!("\n  main posting msg: {:?}", msg.get_content_str().unwrap());
-        conn.post_message(msg);
-        let msg = conn.get_message();
-        print!("\n  main received msg: {:?}",msg.get_content_str().unwrap());
+        let _ = conn.post_message(msg);
+        if let Some(msg) = conn.get_message() {
+            print!("\n  main received msg: {:?}",msg.get_content_str().unwrap());
+        }
 
         /*-- shut down connector --*/
-        let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE);
+        let mut msg = Message::new(HEADER_SIZE);
         msg.set_type(MessageType::END as u8);
         print!("\n  main posting {:?} msg", "END");
-        conn.post_message(msg);
+        let _ = conn.post_message(msg);
     }
     else {
         print!("\n  connection to {:?} failed", addr);
@@ -9,7 +9,7 @@
    - start Connector component
    - send a few messages, ,observe replies
    - send END message to exit client handler
-   - send QUIT message to shut down Listener
+   - stop Listener, then join its thread
 */
 #![allow(unused_imports)]
 #![allow(dead_code)]
@@ -37,9 +37,9 @@ fn start_client(
         for i in 0..n {
             /*-- used to test error handling --*/
             if sd && i == n-1 {
-                let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE);
+                let mut msg = Message::new(HEADER_SIZE);
                 msg.set_type(MessageType::QUIT as u8);
-                conn.post_message(msg);
+                let _ = conn.post_message(msg);
                 return;
             }
             /*---------------------------------*/
@@ -48,14 +48,14 @@ fn start_client(
             msg.set_type(MessageType::FLUSH as u8);
             print!("\n  posting msg:  {:?}", s);
             Log::write(&format!("\n  message size: {:?}", msg.len()));
-            conn.post_message(msg);
-            let msg = conn.get_message();
+            let _ = conn.post_message(msg);
+            let Some(msg) = conn.get_message() else { break; };
             print!("\n  received msg: {:?}", msg.get_content_str().unwrap());
         }
-        let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE);
+        let mut msg = Message::new(HEADER_SIZE);
         msg.set_type(MessageType::END as u8);
         print!("\n  posting END message");
-        conn.post_message(msg);
+        let _ = conn.post_message(msg);
     });
     handle
 }
@@ -13,7 +13,7 @@
    - send a fixed number of messages
    - send END message to exit client handler
    - eval elapsed time
-   - send QUIT message to shut down Listener
+   - stop Listener, then join its thread
 */
 #![allow(unused_imports)]
 #![allow(dead_code)]
@@ -77,8 +77,8 @@ fn client_wait_for_reply<L: Logger>(
                     name, sz_bytes
                 )
             );
-            conn.post_message(msg.clone());
-            let msg = conn.get_message();
+            let _ = conn.post_message(msg.clone());
+            let Some(msg) = conn.get_message() else { break; };
             L::write(
                 &format!(
                     "\n  received msg: {:?}", 
@@ -90,7 +90,7 @@ fn client_wait_for_reply<L: Logger>(
         let et = tmr.elapsed_micros();
         let mut msg = Message::create_msg_header_only();
         msg.set_type(MessageType::END as u8);
-        conn.post_message(msg);
+        let _ = conn.post_message(msg);
         display_test_data(et, num_msgs, sz_bytes);
     });
     handle
@@ -136,15 +136,15 @@ fn client_no_wait_for_reply<L: Logger>(
                     name, sz_bytes
                 )
             );
-            sconn1.post_message(msg.clone());
+            let _ = sconn1.post_message(msg.clone());
         }
-        let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE);
+        let mut msg = Message::new(HEADER_SIZE);
         msg.set_type(MessageType::END as u8);
-        sconn1.post_message(msg);
+        let _ = sconn1.post_message(msg);
     });
     let handle = std::thread::spawn(move || {
         for _i in 0..num_msgs {
-            let msg = sconn2.get_message();
+            let Some(msg) = sconn2.get_message() else { break; };
             L::write(
                 &format!(
                     "\n  received msg: {:?}", 
@@ -171,7 +171,7 @@ fn main() {
 
     type L = MuteLog;
 
-    let nt: u8 = 8;
+    let nt: usize = 8;
     let addr = "127.0.0.1:8080";
     let mut lsnr = Listener::<P,Log>::new(nt);
     let rslt = lsnr.start(addr);
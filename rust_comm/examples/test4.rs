@@ -17,7 +17,7 @@
        - send a fixed number of messages
        - send END message to exit client handler
    - eval elapsed time
-   - send QUIT message to shut down Listener
+   - stop Listener, then join its thread
 */
 #![allow(unused_imports)]
 #![allow(dead_code)]
@@ -68,8 +68,8 @@ fn client_wait_for_reply<L: Logger>(
                     name, sz_bytes
                 )
             );
-            conn.post_message(msg.clone());
-            let _msg = conn.get_message();
+            let _ = conn.post_message(msg.clone());
+            let Some(_msg) = conn.get_message() else { break; };
             L::write(
                 &format!(
                     "\n  received msg: {:?}", 
@@ -83,7 +83,7 @@ fn client_wait_for_reply<L: Logger>(
         let mut msg = Message::new(HEADER_SIZE + content_size);
         msg.set_type(MessageType::END as u8);
         msg.set_content_size(content_size);
-        conn.post_message(msg);
+        let _ = conn.post_message(msg);
         display_test_data(et, num_msgs, sz_bytes);
         let _ = std::io::stdout().flush();
     });
@@ -115,17 +115,17 @@ fn client_no_wait_for_reply<L: Logger>(
                     name, sz_bytes
                 )
             );
-            sconn1.post_message(msg.clone());
+            let _ = sconn1.post_message(msg.clone());
         }
         let content_size = 32;
         let mut msg = Message::new(HEADER_SIZE + content_size);
         msg.set_type(MessageType::END as u8);
         msg.set_content_size(content_size);
-        sconn1.post_message(msg);
+        let _ = sconn1.post_message(msg);
     });
     let handle = std::thread::Builder::new().name("second".to_string()).spawn(move || {
         for _i in 0..num_msgs {
-            let msg = sconn2.get_message();
+            let Some(msg) = sconn2.get_message() else { break; };
             L::write(
                 &format!(
                     "\n  received msg: {:?}", 
@@ -196,7 +196,7 @@ fn main() {
 
     type L = MuteLog;
     
-    let nt: u8 = 8;
+    let nt: usize = 8;
     let addr = "127.0.0.1:8080";
     print!("\n  num thrdpool thrds: {:?}",nt);
     let mut lsnr = Listener::<P,Log>::new(nt);
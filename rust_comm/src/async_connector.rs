@@ -0,0 +1,250 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::async_connector.rs - tokio Connector         //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   AsyncConnector<M,L,C,Z>, feature "tokio":
+   - talks to a Listener over a tokio::net::TcpStream,
+     using tokio tasks and channels in place of OS threads
+     and BlockingQueues, so async applications needn't
+     block a runtime thread
+   - C and Z are the Codec and Compression, as for
+     CommProcessing<L,C,Z>, so the wire format is the one
+     sync Connectors use, chunking and checksums included
+   - a send task writes queued messages, flushing when
+     the queue empties, and a recv task decodes replies
+     as their bytes arrive
+   - the recv task retries decoding after each read, so
+     it suits messages of moderate size
+   - connect exchanges protocol preambles, see module
+     handshake, failing as a Connector does on mismatch
+   - must be created within a tokio runtime
+*/
+
+use crate::*;
+use std::marker::PhantomData;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/*-- messages queued for the send task before post_message waits --*/
+pub const ASYNC_QUEUE_SIZE: usize = 1024;
+
+#[derive(Debug)]
+pub struct AsyncConnector<M, L, C = FrameCodec, Z = NoCompression> {
+    snd_queue: mpsc::Sender<M>,
+    rcv_queue: tokio::sync::Mutex<mpsc::Receiver<M>>,
+    connected: Arc<AtomicBool>,
+    next_id: AtomicU64,
+    send_task: Mutex<Option<JoinHandle<()>>>,
+    recv_task: Mutex<Option<JoinHandle<()>>>,
+    codec: PhantomData<(L, C, Z)>,
+}
+impl<M, L, C, Z> AsyncConnector<M, L, C, Z> where
+    M: Msg + Clone + Send + Default + 'static,
+    L: Logger + 'static,
+    C: Codec<M> + 'static,
+    Z: Compression + 'static
+{
+    /*-- connect to a Listener and start send and recv tasks --*/
+    pub async fn connect<A>(addr: A) -> std::io::Result<AsyncConnector<M,L,C,Z>>
+    where A: tokio::net::ToSocketAddrs
+    {
+        let mut stream = tokio::net::TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        let peer = stream.peer_addr()?;
+        exchange(&mut stream).await?;
+        let (reader, writer) = stream.into_split();
+        let (snd_tx, snd_rx) = mpsc::channel::<M>(ASYNC_QUEUE_SIZE);
+        let (rcv_tx, rcv_rx) = mpsc::channel::<M>(ASYNC_QUEUE_SIZE);
+        let connected = Arc::new(AtomicBool::new(true));
+        let send_task = tokio::spawn(
+            send_loop::<M,L,C,Z>(snd_rx, writer, Arc::clone(&connected))
+        );
+        let recv_task = tokio::spawn(
+            recv_loop::<M,L,C>(reader, rcv_tx, Arc::clone(&connected))
+        );
+        L::info(&format!("\n--async connector connected to {}--", peer));
+        Ok(AsyncConnector {
+            snd_queue: snd_tx,
+            rcv_queue: tokio::sync::Mutex::new(rcv_rx),
+            connected,
+            next_id: AtomicU64::new(1),
+            send_task: Mutex::new(Some(send_task)),
+            recv_task: Mutex::new(Some(recv_task)),
+            codec: PhantomData,
+        })
+    }
+    /*-- false once the socket has failed or been shut down --*/
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+    /*-----------------------------------------------------
+      queue msg for send task, waiting while the queue is
+      full
+      - msg without a correlation id is given the next
+        unused id
+      - fails with NotConnected once the socket has failed
+        or been shut down
+    */
+    pub async fn post_message(&self, mut msg: M) -> std::result::Result<(), CommError> {
+        if !self.is_connected() {
+            L::warn("\n  -- not connected, msg discarded --");
+            return Err(CommError::NotConnected);
+        }
+        if msg.get_id() == 0 {
+            msg.set_id(self.next_id.fetch_add(1, Ordering::Relaxed));
+        }
+        self.snd_queue.send(msg).await.map_err(|_| CommError::NotConnected)
+    }
+    /*-----------------------------------------------------
+      wait for a message
+      - None once the connection has closed and all
+        received messages have been read
+    */
+    pub async fn get_message(&self) -> Option<M> {
+        self.rcv_queue.lock().await.recv().await
+    }
+    /*-----------------------------------------------------
+      send anything queued, then an END message, then
+      close the connection
+      - replies already received can still be read, then
+        get_message returns None
+    */
+    pub async fn shut_down(&self) {
+        let mut msg = M::new(HEADER_SIZE);
+        msg.set_type(MessageType::END as u8);
+        let _ = self.snd_queue.send(msg).await;
+        let send_task = self.send_task.lock().unwrap().take();
+        if let Some(task) = send_task {
+            let _ = task.await;
+        }
+        /*-- recv task may wait on a peer that never closes --*/
+        if let Some(task) = self.recv_task.lock().unwrap().take() {
+            task.abort();
+        }
+        self.connected.store(false, Ordering::Relaxed);
+        L::info("\n--async connector shut down--");
+    }
+}
+/*-- send preamble and check the Listener's, dropping stream closes it on failure --*/
+async fn exchange(stream: &mut tokio::net::TcpStream) -> std::io::Result<()> {
+    stream.write_all(&handshake::PREAMBLE).await?;
+    let mut got = [0u8; 3];
+    match tokio::time::timeout(CONNECT_TIMEOUT, stream.read_exact(&mut got)).await {
+        Ok(rslt) => rslt?,
+        Err(_) => return Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("no protocol preamble within {:?}", CONNECT_TIMEOUT)
+        )),
+    };
+    handshake::recv_preamble(&mut &got[..])
+}
+/*-- write queued messages, flushing when the queue empties or after END --*/
+async fn send_loop<M,L,C,Z>(
+    mut queue: mpsc::Receiver<M>, writer: OwnedWriteHalf, connected: Arc<AtomicBool>
+) where
+    M: Msg + Clone + Send + Default,
+    L: Logger,
+    C: Codec<M>,
+    Z: Compression
+{
+    let mut writer = BufWriter::with_capacity(BUFFER_SIZE, writer);
+    let mut frame = Vec::<u8>::new();
+    while let Some(msg) = queue.recv().await {
+        frame.clear();
+        let end = msg.get_type() == MessageType::END as u8;
+        let mut rslt = match write_message::<M,C,Z,_>(&msg, &mut frame) {
+            Ok(()) => writer.write_all(&frame).await,
+            Err(e) => Err(e),
+        };
+        if rslt.is_ok() && (end || queue.is_empty()) {
+            rslt = writer.flush().await;
+        }
+        if let Err(e) = rslt {
+            L::error(&format!("\n--async send failed: {}--", e));
+            connected.store(false, Ordering::Relaxed);
+            return;
+        }
+        if end {
+            break;
+        }
+    }
+    let _ = writer.shutdown().await;
+}
+/*-- decode messages as their bytes arrive, until EOF or error --*/
+async fn recv_loop<M,L,C>(
+    mut reader: OwnedReadHalf, queue: mpsc::Sender<M>, connected: Arc<AtomicBool>
+) where
+    M: Msg + Clone + Send + Default,
+    L: Logger,
+    C: Codec<M>
+{
+    let mut buf = Vec::<u8>::with_capacity(BUFFER_SIZE);
+    loop {
+        /*-- deliver every complete message buffered --*/
+        loop {
+            let mut cursor = std::io::Cursor::new(&buf[..]);
+            match read_message::<M,C,_>(&mut cursor) {
+                Ok(msg) => {
+                    let used = cursor.position() as usize;
+                    buf.drain(..used);
+                    if queue.send(msg).await.is_err() {
+                        return;  // AsyncConnector dropped
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => {
+                    L::error(&format!("\n--async recv failed: {}--", e));
+                    connected.store(false, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+        buf.reserve(BUFFER_SIZE);
+        match reader.read_buf(&mut buf).await {
+            Ok(n) if n > 0 => {}
+            _ => {
+                L::debug("\n  async connection closed");
+                connected.store(false, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    type L = MuteLog;
+    type M = Message;
+    type P = CommProcessing<L>;
+
+    #[tokio::test]
+    async fn async_connector_talks_to_listener() {
+        let mut lsnr = Listener::<P,L>::new(2);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = AsyncConnector::<M,L>::connect(lsnr.local_addr().unwrap()).await.unwrap();
+        /*-- large body exercises chunking and partial reads --*/
+        let big = vec![5u8; 3 * CHUNK_SIZE];
+        for body in [b"small".to_vec(), big.clone()].iter() {
+            let mut msg = Message::create_msg_bytes_fit(body);
+            msg.set_type(MessageType::FLUSH as u8);
+            conn.post_message(msg).await.unwrap();
+        }
+        let first = conn.get_message().await.unwrap();
+        assert!(first.get_body_bytes().starts_with(b"small"));
+        let second = conn.get_message().await.unwrap();
+        assert!(second.get_body_bytes().starts_with(&big));
+        assert_eq!(second.get_id(), first.get_id() + 1);
+
+        conn.shut_down().await;
+        assert!(!conn.is_connected());
+        assert!(conn.post_message(Message::create_msg_str_fit("late")).await.is_err());
+        assert!(conn.get_message().await.is_none());
+        lsnr.stop();
+        let _ = handle.join();
+    }
+}
@@ -0,0 +1,506 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::config.rs - Connector and Listener options   //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   CommConfig:
+   - holds socket and queue options used by Connector
+     and Listener
+   - options std doesn't expose, e.g., keepalive, are set
+     with socket2
+   - built by chaining setters on the default, e.g.,
+       CommConfig::default().nodelay(false)
+   - with feature "tls", tls_server makes Listener wrap
+     accepted streams in TLS
+   - Listener binds its socket with bind, so it can set
+     SO_REUSEADDR and the listen backlog first
+   - Connector connects with connect, which binds the
+     local end first when local_addr is set, and goes
+     through a SOCKS5 proxy when proxy is set
+*/
+
+use std::io::Result;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+use socket2::{Domain, SockRef, Socket, TcpKeepalive, Type};
+
+/*-- used by Connector::new when no timeout is given --*/
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/*-- BufReader and BufWriter capacity, same as std's default --*/
+pub const BUFFER_SIZE: usize = 8 * 1024;
+/*-- how often Connector's recv thread wakes to check for shut_down --*/
+pub const RECV_POLL: Duration = Duration::from_millis(100);
+/*-- pending connections the OS queues for accept, as std uses --*/
+pub const LISTEN_BACKLOG: i32 = 128;
+/*-- accepted connections Listener holds for a busy pool --*/
+pub const MAX_PENDING: usize = 128;
+
+/*-- control types coalesce accepts, never application data --*/
+fn is_control(mt: u8) -> bool {
+    use crate::MessageType;
+    mt == MessageType::PING as u8
+        || mt == MessageType::PONG as u8
+        || mt == MessageType::SUBSCRIBE as u8
+        || mt == MessageType::UNSUBSCRIBE as u8
+}
+
+#[derive(Debug, Clone)]
+pub struct CommConfig {
+    pub(crate) nodelay: bool,
+    pub(crate) keepalive: Option<Duration>,
+    pub(crate) linger: Option<Duration>,
+    pub(crate) connect_timeout: Duration,
+    pub(crate) send_capacity: Option<usize>,
+    pub(crate) max_connections: Option<usize>,
+    pub(crate) idle_timeout: Option<Duration>,
+    pub(crate) heartbeat: Option<(Duration, Duration)>,
+    pub(crate) buffer_size: usize,
+    pub(crate) auth_token: Option<Vec<u8>>,
+    pub(crate) rate_limit: Option<(u32, u32)>,
+    pub(crate) reuse_address: bool,
+    pub(crate) listen_backlog: i32,
+    pub(crate) max_pending: usize,
+    pub(crate) ipv6_only: Option<bool>,
+    pub(crate) websocket: bool,
+    pub(crate) handshake: bool,
+    pub(crate) local_addr: Option<SocketAddr>,
+    pub(crate) recv_poll: Option<Duration>,
+    pub(crate) verify_sequence: bool,
+    pub(crate) dead_letters: bool,
+    pub(crate) coalesce: Vec<u8>,
+    pub(crate) proxy: Option<SocketAddr>,
+    pub(crate) proxy_auth: Option<(String, String)>,
+    #[cfg(feature = "tls")]
+    pub(crate) tls_server: Option<std::sync::Arc<rustls::ServerConfig>>,
+}
+impl Default for CommConfig {
+    fn default() -> CommConfig {
+        CommConfig {
+            nodelay: true,
+            keepalive: None,
+            linger: None,
+            connect_timeout: CONNECT_TIMEOUT,
+            send_capacity: None,
+            max_connections: None,
+            idle_timeout: None,
+            heartbeat: None,
+            buffer_size: BUFFER_SIZE,
+            auth_token: None,
+            rate_limit: None,
+            reuse_address: true,
+            listen_backlog: LISTEN_BACKLOG,
+            max_pending: MAX_PENDING,
+            ipv6_only: None,
+            websocket: false,
+            handshake: true,
+            local_addr: None,
+            recv_poll: Some(RECV_POLL),
+            verify_sequence: false,
+            dead_letters: false,
+            coalesce: Vec::new(),
+            proxy: None,
+            proxy_auth: None,
+            #[cfg(feature = "tls")]
+            tls_server: None,
+        }
+    }
+}
+impl CommConfig {
+    /*-----------------------------------------------------
+      set TCP_NODELAY, on by default
+      - small request/reply messages aren't held back by
+        Nagle's algorithm
+      - bulk senders may turn it off to coalesce writes
+    */
+    pub fn nodelay(mut self, on: bool) -> Self {
+        self.nodelay = on;
+        self
+    }
+    /*-----------------------------------------------------
+      enable TCP keepalive, probing after idle time
+      - keeps NAT gateways from dropping idle connections
+      - None, the default, leaves the OS setting alone
+    */
+    pub fn keepalive(mut self, idle: Option<Duration>) -> Self {
+        self.keepalive = idle;
+        self
+    }
+    /*-----------------------------------------------------
+      set SO_LINGER on Connector and Listener sockets
+      - Some(dur) makes closing a socket block up to dur
+        while unsent data drains, then reset it, e.g., so
+        END or a session's last replies aren't lost
+      - Some(Duration::ZERO) resets on close, discarding
+        unsent data, so the peer sees ECONNRESET
+      - None, the default, leaves the OS setting alone,
+        close returns at once and the OS sends what's left
+      - the thread dropping the socket waits: shut_down's
+        caller for a Connector, a pool thread for each
+        session, so a long linger stalls stop_graceful
+        and holds pool threads while clients are slow to
+        read; shutdown_write doesn't linger, it only stops
+        writing
+    */
+    pub fn linger(mut self, dur: Option<Duration>) -> Self {
+        self.linger = dur;
+        self
+    }
+    /*-- Connector only, longest wait for connect --*/
+    pub fn connect_timeout(mut self, dur: Duration) -> Self {
+        self.connect_timeout = dur;
+        self
+    }
+    /*-- Connector only, bound on send queue length --*/
+    pub fn send_capacity(mut self, cap: usize) -> Self {
+        self.send_capacity = Some(cap);
+        self
+    }
+    /*-----------------------------------------------------
+      Listener only, bound on clients handled at once
+      - clients past it wait unaccepted, so a Connector's
+        handshake waits too, up to its connect timeout
+    */
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+    /*-----------------------------------------------------
+      Listener only, close sessions whose client sends 
+      nothing for dur, freeing their threadpool thread
+    */
+    pub fn idle_timeout(mut self, dur: Duration) -> Self {
+        self.idle_timeout = Some(dur);
+        self
+    }
+    /*-----------------------------------------------------
+      Connector only, send PING every interval and treat
+      the connection as dead if no PONG arrives within
+      timeout
+    */
+    pub fn heartbeat(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.heartbeat = Some((interval, timeout));
+        self
+    }
+    /*-----------------------------------------------------
+      capacity of each stream's BufReader and BufWriter,
+      BUFFER_SIZE by default
+      - larger buffers take fewer reads and writes for
+        large messages and bursts, at the cost of memory
+        per connection, two buffers each
+      - a message larger than the buffer bypasses it, so
+        small buffers cost syscalls, not correctness
+    */
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.buffer_size = bytes.max(1);
+        self
+    }
+    /*-----------------------------------------------------
+      Connector only, send token in an AUTH message on
+      each connect, failing to connect unless the
+      Listener answers AUTH_OK
+    */
+    pub fn auth_token(mut self, token: impl Into<Vec<u8>>) -> Self {
+        self.auth_token = Some(token.into());
+        self
+    }
+    /*-----------------------------------------------------
+      Listener only, limit each client to per_sec messages
+      a second, after a burst of up to burst messages
+      - messages over the limit are delayed, not dropped,
+        so a flooding client is slowed by TCP backpressure
+      - off by default
+    */
+    pub fn rate_limit(mut self, per_sec: u32, burst: u32) -> Self {
+        self.rate_limit = Some((per_sec, burst));
+        self
+    }
+    /*-----------------------------------------------------
+      Listener only, set SO_REUSEADDR, on by default, so a
+      restarted Listener can bind while its predecessor's
+      connections are in TIME_WAIT
+      - unix only, on Windows SO_REUSEADDR would let
+        another socket take over a port in use
+    */
+    pub fn reuse_address(mut self, on: bool) -> Self {
+        self.reuse_address = on;
+        self
+    }
+    /*-----------------------------------------------------
+      Listener only, connections the OS holds waiting for
+      accept, LISTEN_BACKLOG by default
+      - the OS may cap it, e.g., at somaxconn on Linux
+    */
+    pub fn listen_backlog(mut self, n: i32) -> Self {
+        self.listen_backlog = n;
+        self
+    }
+    /*-----------------------------------------------------
+      Listener only, connections accepted while every pool
+      thread is busy that wait for one, MAX_PENDING by
+      default
+      - connections arriving while n wait are closed, and
+        logged, so a connection storm can't grow memory
+        without bound
+      - n of 0 is taken as 1, as sessions reach the pool
+        by waiting
+      - max_connections, if set, holds clients in the
+        accept backlog before this limit is reached
+    */
+    pub fn max_pending(mut self, n: usize) -> Self {
+        self.max_pending = n.max(1);
+        self
+    }
+    /*-----------------------------------------------------
+      Listener only, set IPV6_V6ONLY on IPv6 sockets
+      - false lets one Listener bound to [::] serve IPv4
+        clients too, as mapped addresses, where the OS
+        allows it
+      - unset, the default, keeps the OS default, true on
+        Windows, usually false on Linux
+    */
+    pub fn ipv6_only(mut self, on: bool) -> Self {
+        self.ipv6_only = Some(on);
+        self
+    }
+    /*-----------------------------------------------------
+      Listener only, accept browser clients: a session
+      opening with an HTTP upgrade request talks
+      WebSocket frames, see module websocket
+      - off by default, as the Listener must wait for a
+        client's first bytes to tell
+    */
+    pub fn websocket(mut self, on: bool) -> Self {
+        self.websocket = on;
+        self
+    }
+    /*-----------------------------------------------------
+      exchange protocol preambles as each connection
+      opens, on by default, see module handshake
+      - off lets clients that don't send one, e.g.,
+        netcat talking to a TextLineCodec Listener, be
+        served; both ends must agree
+    */
+    pub fn handshake(mut self, on: bool) -> Self {
+        self.handshake = on;
+        self
+    }
+    /*-----------------------------------------------------
+      Connector only, originate connections from addr,
+      e.g., one interface's IP with port 0, on a
+      multi-homed host
+      - connect fails, saying so, if addr can't be bound,
+        e.g., it's not an address of this host
+      - unset, the default, lets the OS choose
+    */
+    pub fn local_addr(mut self, addr: SocketAddr) -> Self {
+        self.local_addr = Some(addr);
+        self
+    }
+    /*-----------------------------------------------------
+      Connector only, read timeout of the recv thread,
+      RECV_POLL by default
+      - at each timeout the thread checks for shut_down,
+        then goes on reading, so a quiet connection stays
+        up and a message arriving in pieces is read whole
+      - None blocks reads, so the thread ends only when
+        the socket is shut down or the peer closes it
+    */
+    pub fn recv_poll(mut self, interval: Option<Duration>) -> Self {
+        self.recv_poll = interval;
+        self
+    }
+    /*-----------------------------------------------------
+      Connector only, check the sequence numbers a
+      Listener stamps on each session's messages
+      - a message numbered other than one past the last,
+        e.g., lost, repeated, or reordered, is logged and
+        reported as ConnectionEvent::SequenceGap, then
+        delivered as usual
+      - numbering starts again with each connection
+      - off by default
+    */
+    pub fn verify_sequence(mut self, verify: bool) -> Self {
+        self.verify_sequence = verify;
+        self
+    }
+    /*-----------------------------------------------------
+      Connector only, when a send fails keep the messages
+      it strands for Connector::take_undelivered
+      - those of the failed batch, which may have
+        arrived in part, then everything still queued,
+        so a reconnect doesn't send them
+      - off by default, queued messages wait for a
+        reconnect and the failed batch is lost
+    */
+    pub fn dead_letters(mut self, keep: bool) -> Self {
+        self.dead_letters = keep;
+        self
+    }
+    /*-----------------------------------------------------
+      Connector only, send one of each run of identical
+      messages of these types found queued back to back,
+      e.g., PINGs posted by a runaway loop
+      - identical means same type, flags, topic, and body,
+        ids aside, so SUBSCRIBEs to different topics are
+        all sent
+      - only control types, PING, PONG, SUBSCRIBE, and
+        UNSUBSCRIBE, are coalesced, others are ignored,
+        so data is always sent as posted
+      - dropped messages count as sent for flush
+      - none by default
+    */
+    pub fn coalesce<I>(mut self, types: I) -> Self 
+    where I: IntoIterator<Item = crate::MessageType>
+    {
+        self.coalesce = types.into_iter()
+            .map(|mt| mt as u8)
+            .filter(|mt| is_control(*mt))
+            .collect();
+        self
+    }
+    /*-----------------------------------------------------
+      Connector only, connect through the SOCKS5 proxy at
+      addr, see module socks
+      - local_addr, if set, binds the connection to the
+        proxy
+      - unset, the default, connects directly
+    */
+    pub fn proxy(mut self, addr: SocketAddr) -> Self {
+        self.proxy = Some(addr);
+        self
+    }
+    /*-- username and password offered to the proxy, else no authentication --*/
+    pub fn proxy_auth(mut self, user: &str, password: &str) -> Self {
+        self.proxy_auth = Some((user.to_string(), password.to_string()));
+        self
+    }
+    /*-----------------------------------------------------
+      Listener only, accept TLS clients using config,
+      e.g., from tls::server_config
+    */
+    #[cfg(feature = "tls")]
+    pub fn tls_server(mut self, config: std::sync::Arc<rustls::ServerConfig>) -> Self {
+        self.tls_server = Some(config);
+        self
+    }
+    /*-- set options on connected or accepted stream --*/
+    pub(crate) fn apply(&self, stream: &TcpStream) -> Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        if let Some(idle) = self.keepalive {
+            let ka = TcpKeepalive::new().with_time(idle);
+            SockRef::from(stream).set_tcp_keepalive(&ka)?;
+        }
+        if self.linger.is_some() {
+            SockRef::from(stream).set_linger(self.linger)?;
+        }
+        Ok(())
+    }
+    /*-- connect to addr within dur, from local_addr and through proxy if set --*/
+    pub(crate) fn connect(&self, addr: &SocketAddr, dur: Duration) -> Result<TcpStream> {
+        let Some(proxy) = self.proxy else {
+            return self.connect_direct(addr, dur);
+        };
+        let deadline = std::time::Instant::now() + dur;
+        let mut stream = self.connect_direct(&proxy, dur).map_err(|e| std::io::Error::new(
+            e.kind(), format!("can't reach socks5 proxy {}: {}", proxy, e)
+        ))?;
+        let left = deadline.saturating_duration_since(std::time::Instant::now())
+            .max(Duration::from_millis(1));
+        stream.set_read_timeout(Some(left))?;
+        stream.set_write_timeout(Some(left))?;
+        crate::socks::connect(&mut stream, addr, self.proxy_auth.as_ref())?;
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(None)?;
+        Ok(stream)
+    }
+    fn connect_direct(&self, addr: &SocketAddr, dur: Duration) -> Result<TcpStream> {
+        let Some(local) = self.local_addr else {
+            return TcpStream::connect_timeout(addr, dur);
+        };
+        let socket = Socket::new(Domain::for_address(*addr), Type::STREAM, None)?;
+        socket.bind(&local.into()).map_err(|e| std::io::Error::new(
+            e.kind(), format!("can't bind local address {}: {}", local, e)
+        ))?;
+        socket.connect_timeout(&(*addr).into(), dur)?;
+        Ok(socket.into())
+    }
+    /*-- listening socket on first of addrs that binds --*/
+    pub(crate) fn bind(&self, addrs: &[SocketAddr]) -> Result<TcpListener> {
+        let mut last_err = None;
+        for addr in addrs {
+            match self.bind_one(addr) {
+                Ok(tcpl) => return Ok(tcpl),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidInput, "no address to bind"
+        )))
+    }
+    fn bind_one(&self, addr: &SocketAddr) -> Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(*addr), Type::STREAM, None)?;
+        if cfg!(unix) {
+            socket.set_reuse_address(self.reuse_address)?;
+        }
+        if let (true, Some(on)) = (addr.is_ipv6(), self.ipv6_only) {
+            socket.set_only_v6(on)?;
+        }
+        socket.bind(&(*addr).into())?;
+        socket.listen(self.listen_backlog)?;
+        Ok(socket.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    #[test]
+    fn keepalive_applied_only_when_set() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(tcpl.local_addr().unwrap()).unwrap();
+        CommConfig::default().apply(&stream).unwrap();
+        assert!(!SockRef::from(&stream).keepalive().unwrap());
+
+        let idle = Duration::from_secs(30);
+        CommConfig::default().keepalive(Some(idle)).apply(&stream).unwrap();
+        let sock = SockRef::from(&stream);
+        assert!(sock.keepalive().unwrap());
+        #[cfg(target_os = "linux")]
+        assert_eq!(sock.keepalive_time().unwrap(), idle);
+    }
+    #[test]
+    fn linger_applied_only_when_set() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(tcpl.local_addr().unwrap()).unwrap();
+        let os_default = SockRef::from(&stream).linger().unwrap();
+        CommConfig::default().apply(&stream).unwrap();
+        assert_eq!(SockRef::from(&stream).linger().unwrap(), os_default);
+
+        let dur = Duration::from_secs(2);
+        CommConfig::default().linger(Some(dur)).apply(&stream).unwrap();
+        assert_eq!(SockRef::from(&stream).linger().unwrap(), Some(dur));
+        CommConfig::default().linger(Some(Duration::ZERO)).apply(&stream).unwrap();
+        assert_eq!(SockRef::from(&stream).linger().unwrap(), Some(Duration::ZERO));
+    }
+    #[cfg(unix)]
+    #[test]
+    fn rebinds_while_old_connection_is_in_time_wait() {
+        let config = CommConfig::default();
+        let tcpl = config.bind(&["127.0.0.1:0".parse().unwrap()]).unwrap();
+        let addr = tcpl.local_addr().unwrap();
+        let client = TcpStream::connect(addr).unwrap();
+        let (server, _) = tcpl.accept().unwrap();
+        /*-- server closes first, so its end waits in TIME_WAIT --*/
+        drop(server);
+        let mut buf = [0u8; 1];
+        assert_eq!(std::io::Read::read(&mut &client, &mut buf).unwrap(), 0);
+        drop(client);
+        drop(tcpl);
+
+        let no_reuse = CommConfig::default().reuse_address(false);
+        assert_eq!(no_reuse.bind(&[addr]).unwrap_err().kind(), std::io::ErrorKind::AddrInUse);
+        assert!(config.listen_backlog(16).bind(&[addr]).is_ok());
+    }
+}
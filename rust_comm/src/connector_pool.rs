@@ -0,0 +1,341 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::connector_pool.rs - Connectors as one client //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   ConnectorPool<P,M,L>:
+   - holds a Connector to each of several Listener
+     endpoints, e.g., members of a cluster
+   - post_message round-robins over members that are
+     connected, skipping dead ones, so traffic fails over
+     when a Listener goes away
+   - get_message returns replies from every member, in
+     the order members are polled, not the order sent
+   - a member is dead once its Connector sees its socket
+     fail; rebalance reconnects dead members
+   - every endpoint must accept a connection when the
+     pool is built
+
+   Checkout, for request/reply bursts:
+   - with_size(addr, n) opens n Connectors to one
+     Listener, so requests don't pay for connecting
+   - checkout hands out an idle member as a
+     PooledConnector, which returns it when dropped
+   - a dead member is reconnected before it's handed out
+   - at most n members are checked out at once, checkout
+     blocks and try_checkout returns None when all are
+   - replies left unread are discarded at return, so the
+     next user doesn't see them
+   - post_message and get_message still use every member,
+     so don't mix them with checkout on one pool
+*/
+
+use crate::*;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/*-- longest wait on one member before polling the next --*/
+const MERGE_POLL: Duration = Duration::from_millis(5);
+
+#[derive(Debug)]
+pub struct ConnectorPool<P,M,L> where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
+    L: Logger + Debug + Copy + Clone + Default
+{
+    members: Vec<Connector<P,M,L>>,
+    next_post: AtomicUsize,
+    next_poll: AtomicUsize,
+    stopped: AtomicBool,
+    idle: (Mutex<Vec<usize>>, Condvar),  // members not checked out
+}
+impl<P,M,L> ConnectorPool<P,M,L> where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
+    L: Logger + Debug + Copy + Clone + Default
+{
+    /*-- connect to each of addrs, failing if any can't be reached --*/
+    pub fn new<A>(addrs: &[A]) -> std::io::Result<ConnectorPool<P,M,L>>
+    where A: ToSocketAddrs + Debug
+    {
+        Self::new_with_config(addrs, CommConfig::default())
+    }
+    pub fn new_with_config<A>(addrs: &[A], config: CommConfig)
+        -> std::io::Result<ConnectorPool<P,M,L>>
+    where A: ToSocketAddrs + Debug
+    {
+        let mut members = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            members.push(Connector::<P,M,L>::new_with_config(addr, config.clone())?);
+        }
+        Ok(Self::from_members(members))
+    }
+    /*-- n Connectors to addr, for checkout --*/
+    pub fn with_size<A>(addr: A, n: usize) -> std::io::Result<ConnectorPool<P,M,L>>
+    where A: ToSocketAddrs + Debug
+    {
+        let mut members = Vec::with_capacity(n);
+        for _ in 0..n {
+            members.push(Connector::<P,M,L>::new(&addr)?);
+        }
+        Ok(Self::from_members(members))
+    }
+    fn from_members(members: Vec<Connector<P,M,L>>) -> ConnectorPool<P,M,L> {
+        let idle = (0..members.len()).rev().collect();
+        ConnectorPool {
+            members,
+            next_post: AtomicUsize::new(0),
+            next_poll: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+            idle: (Mutex::new(idle), Condvar::new()),
+        }
+    }
+    /*-----------------------------------------------------
+      wait for an idle member and hand it out
+      - fails if the member is dead and can't reconnect,
+        leaving it in the pool to retry later
+    */
+    pub fn checkout(&self) -> std::io::Result<PooledConnector<'_,P,M,L>> {
+        let (lock, cv) = &self.idle;
+        let mut idle = lock.lock().unwrap();
+        loop {
+            if let Some(index) = idle.pop() {
+                drop(idle);
+                return self.hand_out(index);
+            }
+            idle = cv.wait(idle).unwrap();
+        }
+    }
+    /*-- hand out an idle member, None if all are checked out --*/
+    pub fn try_checkout(&self) -> Option<std::io::Result<PooledConnector<'_,P,M,L>>> {
+        let index = self.idle.0.lock().unwrap().pop()?;
+        Some(self.hand_out(index))
+    }
+    fn hand_out(&self, index: usize) -> std::io::Result<PooledConnector<'_,P,M,L>> {
+        let pooled = PooledConnector { pool: self, index };
+        if !pooled.is_connected() {
+            pooled.reconnect()?;  // failure returns member via drop
+        }
+        Ok(pooled)
+    }
+    /*-- number of members not checked out --*/
+    pub fn idle_count(&self) -> usize {
+        self.idle.0.lock().unwrap().len()
+    }
+    /*-- number of members whose connection is live --*/
+    pub fn healthy_count(&self) -> usize {
+        self.members.iter().filter(|c| c.is_connected()).count()
+    }
+    /*-----------------------------------------------------
+      post msg to the next connected member
+      - fails with NotConnected if no member is connected
+    */
+    pub fn post_message(&self, msg: M) -> std::result::Result<(), CommError> {
+        /*-- each skipped member uses up its turn, keeping load even --*/
+        let n = self.members.len();
+        let member = (0..n)
+            .map(|_| &self.members[self.next_post.fetch_add(1, Ordering::Relaxed) % n])
+            .find(|c| c.is_connected());
+        match member {
+            Some(conn) if !self.stopped.load(Ordering::Relaxed) => conn.post_message(msg),
+            _ => Err(CommError::NotConnected),
+        }
+    }
+    /*-- return a reply from any member, never blocks --*/
+    pub fn try_get_message(&self) -> Option<M> {
+        let n = self.members.len();
+        let start = self.next_poll.fetch_add(1, Ordering::Relaxed);
+        (0..n).find_map(|i| self.members[(start + i) % n].try_get_message())
+    }
+    /*-----------------------------------------------------
+      wait for a reply from any member
+      - None once shut down and all replies have been read
+    */
+    pub fn get_message(&self) -> Option<M> {
+        loop {
+            if let Some(msg) = self.get_message_timeout(Duration::from_secs(1)) {
+                return Some(msg);
+            }
+            if self.stopped.load(Ordering::Relaxed) || self.members.is_empty() {
+                return None;
+            }
+        }
+    }
+    /*-- wait at most dur for a reply from any member --*/
+    pub fn get_message_timeout(&self, dur: Duration) -> Option<M> {
+        let deadline = Instant::now() + dur;
+        loop {
+            if let Some(msg) = self.try_get_message() {
+                return Some(msg);
+            }
+            let now = Instant::now();
+            if now >= deadline || self.members.is_empty() {
+                return None;
+            }
+            let k = self.next_poll.fetch_add(1, Ordering::Relaxed) % self.members.len();
+            let wait = MERGE_POLL.min(deadline - now);
+            if let Some(msg) = self.members[k].get_message_timeout(wait) {
+                return Some(msg);
+            }
+            if self.stopped.load(Ordering::Relaxed) && self.try_get_message().is_none() {
+                return None;
+            }
+        }
+    }
+    /*-----------------------------------------------------
+      reconnect members whose connection is dead
+      - messages queued on a dead member are sent once
+        it reconnects
+      - returns number of healthy members afterward
+    */
+    pub fn rebalance(&self) -> usize {
+        for conn in self.members.iter().filter(|c| !c.is_connected()) {
+            if let Err(e) = conn.reconnect() {
+                L::warn(&format!("\n  -- pool member still down: {} --", e));
+            }
+        }
+        self.healthy_count()
+    }
+    /*-- shut down every member, see Connector::shut_down --*/
+    pub fn shut_down(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        for conn in &self.members {
+            conn.shut_down();
+        }
+    }
+}
+
+/*---------------------------------------------------------
+  PooledConnector - a checked out pool member
+  - derefs to its Connector
+  - returned to the pool when dropped
+*/
+#[derive(Debug)]
+pub struct PooledConnector<'a,P,M,L> where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
+    L: Logger + Debug + Copy + Clone + Default
+{
+    pool: &'a ConnectorPool<P,M,L>,
+    index: usize,
+}
+impl<P,M,L> Deref for PooledConnector<'_,P,M,L> where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
+    L: Logger + Debug + Copy + Clone + Default
+{
+    type Target = Connector<P,M,L>;
+    fn deref(&self) -> &Connector<P,M,L> {
+        &self.pool.members[self.index]
+    }
+}
+impl<P,M,L> Drop for PooledConnector<'_,P,M,L> where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
+    L: Logger + Debug + Copy + Clone + Default
+{
+    fn drop(&mut self) {
+        while self.try_get_message().is_some() {}
+        let (lock, cv) = &self.pool.idle;
+        lock.lock().unwrap().push(self.index);
+        cv.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    type L = MuteLog;
+    type M = Message;
+    type P = CommProcessing<L>;
+
+    fn wait_until<F: Fn() -> bool>(pred: F) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !pred() {
+            if Instant::now() > deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+    #[test]
+    fn round_robin_fails_over_and_rebalances() {
+        /*-- third listener drops idle clients, killing its member --*/
+        let configs = [
+            CommConfig::default(),
+            CommConfig::default(),
+            CommConfig::default().idle_timeout(Duration::from_millis(300)),
+        ];
+        let mut lsnrs: Vec<_> = configs.iter()
+            .map(|config| Listener::<P,L>::new_with_config(1, config.clone()))
+            .collect();
+        let handles: Vec<_> = lsnrs.iter_mut()
+            .map(|l| l.start("127.0.0.1:0").unwrap())
+            .collect();
+        let addrs: Vec<_> = lsnrs.iter().map(|l| l.local_addr().unwrap()).collect();
+        let pool = ConnectorPool::<P,M,L>::new(&addrs).unwrap();
+        let mut msg = Message::create_msg_str_fit("spread");
+        msg.set_type(MessageType::FLUSH as u8);
+        let exchange = |n: usize| {
+            for _ in 0..n {
+                pool.post_message(msg.clone()).unwrap();
+            }
+            for _ in 0..n {
+                pool.get_message_timeout(Duration::from_secs(5)).unwrap();
+            }
+        };
+        exchange(3);
+        let counts = || lsnrs.iter().map(|l| l.stats().messages_processed).collect::<Vec<_>>();
+        assert_eq!(counts(), vec![1, 1, 1]);
+
+        assert!(wait_until(|| pool.healthy_count() == 2));
+        exchange(4);
+        assert_eq!(counts(), vec![3, 3, 1]);
+
+        assert_eq!(pool.rebalance(), 3);
+        exchange(3);
+        assert_eq!(counts().iter().sum::<u64>(), 10);
+        pool.shut_down();
+        assert!(pool.get_message().is_none());
+        for (l, h) in lsnrs.iter_mut().zip(handles) {
+            l.stop();
+            let _ = h.join();
+        }
+    }
+    #[test]
+    fn checkout_is_capped_and_reconnects() {
+        /*-- listener drops idle clients, killing the members --*/
+        let config = CommConfig::default().idle_timeout(Duration::from_millis(300));
+        let mut lsnr = Listener::<P,L>::new_with_config(2, config);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let pool = ConnectorPool::<P,M,L>::with_size(lsnr.local_addr().unwrap(), 2).unwrap();
+        let mut msg = Message::create_msg_str_fit("pooled");
+        msg.set_type(MessageType::FLUSH as u8);
+        let dur = Duration::from_secs(5);
+        {
+            let first = pool.checkout().unwrap();
+            let _second = pool.checkout().unwrap();
+            assert!(pool.try_checkout().is_none());
+            assert!(first.post_and_wait(msg.clone(), dur).is_some());
+        }
+        assert_eq!(pool.idle_count(), 2);
+        assert!(wait_until(|| pool.healthy_count() == 0));
+
+        /*-- blocked checkout proceeds when a member is returned --*/
+        let held = pool.checkout().unwrap();
+        assert!(held.is_connected());
+        let _other = pool.checkout().unwrap();
+        thread::scope(|s| {
+            let waiter = s.spawn(|| pool.checkout().unwrap().post_and_wait(msg.clone(), dur));
+            thread::sleep(Duration::from_millis(50));
+            assert!(!waiter.is_finished());
+            drop(held);
+            assert!(waiter.join().unwrap().is_some());
+        });
+        pool.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+}
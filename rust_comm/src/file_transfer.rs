@@ -0,0 +1,258 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::file_transfer.rs - send files to a Listener  //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   Connector::send_file(path) sends a file as:
+   - FILE_START, body is the file's name
+   - FILE_CHUNK messages, body is a 4 byte big-endian
+     sequence number, from 0, then up to FILE_CHUNK_SIZE
+     bytes of the file, written from the read buffer by
+     post_bytes, so a large file isn't copied or queued
+   - FILE_END, body is the 8 byte big-endian file length,
+     answered by a REPLY whose body is empty when the
+     file was stored, else says why not
+
+   A Listener given receive_files(dir):
+   - writes each session's incoming file to a hidden
+     .part file in dir, renamed to the sent name, less
+     any directories, when FILE_END's length matches
+   - discards the partial file if the session ends, e.g.,
+     the client disconnects, mid-transfer, or a chunk
+     arrives out of sequence
+   - one file at a time per session, a FILE_START
+     discards any transfer in progress
+*/
+
+use crate::*;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/*-- file bytes carried by each FILE_CHUNK --*/
+pub const FILE_CHUNK_SIZE: usize = 64 * 1024;
+/*-- longest send_file waits for the Listener to confirm a file --*/
+pub const FILE_ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+const SEQ_SIZE: usize = 4;
+
+impl<P,M,L> Connector<P,M,L> where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
+    L: Logger + Debug + Copy + Clone + Default
+{
+    /*-----------------------------------------------------
+      send file at path to a Listener receiving files
+      - returns number of file bytes sent once the
+        Listener confirms it has stored them
+      - Io error if the file can't be read, or the
+        Listener refuses it or doesn't answer within
+        FILE_ACK_TIMEOUT
+    */
+    pub fn send_file<Q: AsRef<Path>>(&self, path: Q) -> std::result::Result<u64, CommError> {
+        let path = path.as_ref();
+        let name = path.file_name().and_then(|n| n.to_str()).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput, format!("{:?} has no file name", path)
+            )
+        })?;
+        let mut file = File::open(path)?;
+        self.post_bytes(MessageType::FILE_START as u8, name.as_bytes())?;
+        let mut chunk = vec![0u8; SEQ_SIZE + FILE_CHUNK_SIZE];
+        let mut total = 0u64;
+        for seq in 0u32.. {
+            let n = file.read(&mut chunk[SEQ_SIZE..])?;
+            if n == 0 {
+                break;
+            }
+            chunk[..SEQ_SIZE].copy_from_slice(&seq.to_be_bytes());
+            self.post_bytes(MessageType::FILE_CHUNK as u8, &chunk[..SEQ_SIZE + n])?;
+            total += n as u64;
+        }
+        let mut end = M::new(HEADER_SIZE + 8);
+        end.set_type(MessageType::FILE_END as u8);
+        end.set_content_bytes(&total.to_be_bytes());
+        let reply = self.post_and_wait(end, FILE_ACK_TIMEOUT).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::TimedOut, "file transfer not confirmed")
+        })?;
+        if !reply.get_content_bytes().is_empty() {
+            let why = String::from_utf8_lossy(reply.get_content_bytes());
+            return Err(std::io::Error::other(format!("file refused: {}", why)).into());
+        }
+        L::info(&format!("\n--sent file {:?}, {} bytes--", path, total));
+        Ok(total)
+    }
+}
+
+/*-- file being received, removed unless finished --*/
+#[derive(Debug)]
+struct Incoming {
+    file: File,
+    part: PathBuf,
+    dest: PathBuf,
+    next_seq: u32,
+    len: u64,
+    failed: Option<String>,
+    done: bool,
+}
+impl Drop for Incoming {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = std::fs::remove_file(&self.part);
+        }
+    }
+}
+/*---------------------------------------------------------
+  FileSink - a session's file reception, see receive_files
+  - dropped with the session, so a partial file is
+    discarded when its client disconnects
+*/
+#[derive(Debug)]
+pub(crate) struct FileSink {
+    dir: PathBuf,
+    incoming: Option<Incoming>,
+}
+impl FileSink {
+    pub(crate) fn new(dir: &Path) -> FileSink {
+        FileSink { dir: dir.to_path_buf(), incoming: None }
+    }
+    pub(crate) fn is_file_msg(msg_type: u8) -> bool {
+        msg_type == MessageType::FILE_START as u8
+            || msg_type == MessageType::FILE_CHUNK as u8
+            || msg_type == MessageType::FILE_END as u8
+    }
+    /*-- handle a file message, returning the reply to FILE_END --*/
+    pub(crate) fn handle<M: Msg, L: Logger>(&mut self, msg: &M) -> Option<M> {
+        let body = msg.get_content_bytes();
+        let msg_type = msg.get_type();
+        if msg_type == MessageType::FILE_START as u8 {
+            self.incoming = None;
+            match self.start(body) {
+                Ok(incoming) => self.incoming = Some(incoming),
+                Err(e) => L::warn(&format!("\n--can't receive file: {}--", e)),
+            }
+            None
+        }
+        else if msg_type == MessageType::FILE_CHUNK as u8 {
+            if let Some(incoming) = self.incoming.as_mut() {
+                incoming.write(body);
+            }
+            None
+        }
+        else {
+            let outcome = match self.incoming.take() {
+                Some(incoming) => incoming.finish(body),
+                None => "no file started".to_string(),
+            };
+            let mut reply = M::new(HEADER_SIZE + outcome.len());
+            reply.set_type(MessageType::REPLY as u8);
+            reply.set_id(msg.get_id());
+            reply.set_content_bytes(outcome.as_bytes());
+            Some(reply)
+        }
+    }
+    fn start(&self, body: &[u8]) -> std::io::Result<Incoming> {
+        let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, "bad file name");
+        let name = std::str::from_utf8(body).map_err(|_| invalid())?;
+        /*-- keep only the last component, so files stay in dir --*/
+        let name = Path::new(name).file_name().ok_or_else(invalid)?;
+        let dest = self.dir.join(name);
+        let mut part_name = std::ffi::OsString::from(".");
+        part_name.push(name);
+        part_name.push(".part");
+        let part = self.dir.join(part_name);
+        let file = File::create(&part)?;
+        Ok(Incoming { file, part, dest, next_seq: 0, len: 0, failed: None, done: false })
+    }
+}
+impl Incoming {
+    fn write(&mut self, body: &[u8]) {
+        if self.failed.is_some() {
+            return;
+        }
+        if body.len() < SEQ_SIZE {
+            self.failed = Some("chunk too short".to_string());
+            return;
+        }
+        let mut seq = [0u8; SEQ_SIZE];
+        seq.copy_from_slice(&body[..SEQ_SIZE]);
+        if u32::from_be_bytes(seq) != self.next_seq {
+            self.failed = Some("chunk out of sequence".to_string());
+            return;
+        }
+        self.next_seq += 1;
+        let data = &body[SEQ_SIZE..];
+        match self.file.write_all(data) {
+            Ok(()) => self.len += data.len() as u64,
+            Err(e) => self.failed = Some(e.to_string()),
+        }
+    }
+    /*-- empty string if stored, else why not --*/
+    fn finish(mut self, body: &[u8]) -> String {
+        if let Some(why) = self.failed.take() {
+            return why;
+        }
+        let mut len = [0u8; 8];
+        if body.len() != len.len() {
+            return "bad FILE_END".to_string();
+        }
+        len.copy_from_slice(body);
+        if u64::from_be_bytes(len) != self.len {
+            return format!("received {} of {} bytes", self.len, u64::from_be_bytes(len));
+        }
+        if let Err(e) = self.file.flush().and_then(|_| std::fs::rename(&self.part, &self.dest)) {
+            return e.to_string();
+        }
+        self.done = true;
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    type L = MuteLog;
+    type M = Message;
+    type P = CommProcessing<L>;
+
+    #[test]
+    fn files_arrive_whole_or_not_at_all() {
+        let dir = std::env::temp_dir().join(format!("rust_comm_files_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let dest = dir.join("dest");
+        std::fs::create_dir_all(&dest).unwrap();
+        let src = dir.join("data.bin");
+        let data: Vec<u8> = (0..3 * FILE_CHUNK_SIZE + 11).map(|i| (i % 253) as u8).collect();
+        std::fs::write(&src, &data).unwrap();
+
+        let mut lsnr = Listener::<P,L>::new(2);
+        lsnr.receive_files(&dest);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        assert_eq!(conn.send_file(&src).unwrap(), data.len() as u64);
+        assert_eq!(std::fs::read(dest.join("data.bin")).unwrap(), data);
+        conn.shut_down();
+
+        /*-- client leaves mid-transfer, partial file discarded --*/
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        conn.post_bytes(MessageType::FILE_START as u8, b"cut.bin").unwrap();
+        let mut chunk = 0u32.to_be_bytes().to_vec();
+        chunk.extend_from_slice(b"partial");
+        conn.post_bytes(MessageType::FILE_CHUNK as u8, &chunk).unwrap();
+        let part = dest.join(".cut.bin.part");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !part.exists() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(part.exists());
+        conn.shut_down();
+        while part.exists() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!part.exists());
+        assert!(!dest.join("cut.bin").exists());
+        lsnr.stop();
+        let _ = handle.join();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
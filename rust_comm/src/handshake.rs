@@ -0,0 +1,156 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::handshake.rs - protocol version preamble     //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   Each end of a connection opens by sending PREAMBLE,
+   the 2 byte PROTOCOL_MAGIC then the 1 byte
+   PROTOCOL_VERSION, and checks the peer's before any
+   message flows:
+   - a Connector sends its preamble, then waits up to
+     the connect timeout for the Listener's, so connect
+     fails, rather than misframing later, when talking
+     to an incompatible Listener
+   - a Listener sends its preamble as it accepts a plain
+     TCP client, so the client needn't wait for a pool
+     thread, or, with TLS or websocket, when a pool
+     thread takes the session, then checks the client's
+   - a mismatch fails with CommError::ProtocolMismatch,
+     wrapped in an io::Error of kind InvalidData, and the
+     socket is shut down; a Listener still sends its own
+     preamble first, so the client can report it
+   - an older peer sends no preamble: a Listener sees the
+     start of its first frame, a mismatch, and an older
+     Listener never answers, so the Connector times out
+   - CommConfig::handshake(false) leaves the preamble
+     out, e.g., for netcat clients of a TextLineCodec
+     Listener, and must be set on both ends
+   - PROTOCOL_MAGIC can't begin with 'G', which starts a
+     websocket upgrade request
+*/
+
+use crate::*;
+
+/*-- the wire format changed incompatibly when this last changed --*/
+pub const PROTOCOL_VERSION: u8 = 1;
+pub const PROTOCOL_MAGIC: [u8; 2] = *b"RC";
+pub(crate) const PREAMBLE: [u8; 3] = [PROTOCOL_MAGIC[0], PROTOCOL_MAGIC[1], PROTOCOL_VERSION];
+
+pub(crate) fn send_preamble<W: Write>(writer: &mut W) -> Result<()> {
+    writer.write_all(&PREAMBLE)?;
+    writer.flush()
+}
+/*-- read peer's preamble, Err wrapping ProtocolMismatch unless it's ours --*/
+pub(crate) fn recv_preamble<R: Read>(reader: &mut R) -> Result<()> {
+    let mut got = [0u8; 3];
+    reader.read_exact(&mut got)?;
+    let actual = if got[..2] != PROTOCOL_MAGIC {
+        None
+    } else if got[2] != PROTOCOL_VERSION {
+        Some(got[2])
+    } else {
+        return Ok(());
+    };
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        CommError::ProtocolMismatch { expected: PROTOCOL_VERSION, actual }
+    ))
+}
+/*-----------------------------------------------------
+  Connector's side, run before its threads start
+  - shuts the stream down if the Listener's preamble
+    doesn't match or doesn't arrive within timeout
+*/
+pub(crate) fn exchange<T: Transport>(stream: &T, timeout: Duration) -> Result<()> {
+    let peer = stream.peer_addr()?;
+    let mut strm = stream.try_clone()?;
+    stream.set_read_timeout(Some(timeout))?;
+    let rslt = send_preamble(&mut strm).and_then(|_| recv_preamble(&mut strm));
+    if let Err(e) = rslt {
+        let _ = stream.shutdown(Shutdown::Both);
+        return Err(match e.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("no protocol preamble from {} within {:?}", peer, timeout)
+            ),
+            /*-- e.g., refused by a Listener with max_pending waiting --*/
+            std::io::ErrorKind::UnexpectedEof => std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("{} closed the connection before its protocol preamble", peer)
+            ),
+            _ => e,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    type L = MuteLog;
+    type M = Message;
+    type P = CommProcessing<L>;
+
+    fn mismatch(e: &std::io::Error) -> Option<&CommError> {
+        e.get_ref().and_then(|inner| inner.downcast_ref::<CommError>())
+    }
+    #[test]
+    fn connector_refuses_other_protocol_versions() {
+        let config = CommConfig::default().connect_timeout(Duration::from_secs(2));
+        let server = |answer: &'static [u8]| {
+            let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = tcpl.local_addr().unwrap();
+            let handle = thread::spawn(move || {
+                let (mut client, _) = tcpl.accept().unwrap();
+                client.write_all(answer).unwrap();
+                /*-- the Connector closes its end after a mismatch --*/
+                let mut rest = Vec::new();
+                let _ = client.read_to_end(&mut rest);
+                rest
+            });
+            (addr, handle)
+        };
+        let (addr, handle) = server(&[b'R', b'C', PROTOCOL_VERSION + 1]);
+        let err = Connector::<P,M,L>::new_with_config(addr, config.clone()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(matches!(
+            mismatch(&err),
+            Some(CommError::ProtocolMismatch { expected: PROTOCOL_VERSION, actual: Some(v) })
+                if *v == PROTOCOL_VERSION + 1
+        ));
+        assert_eq!(handle.join().unwrap(), PREAMBLE);
+
+        /*-- an older Listener's first bytes are a frame length --*/
+        let (addr, handle) = server(&[0, 0, 0, 5]);
+        let err = Connector::<P,M,L>::new_with_config(addr, config).unwrap_err();
+        assert!(err.to_string().contains("no rust_comm preamble"), "{}", err);
+        assert!(matches!(mismatch(&err), Some(CommError::ProtocolMismatch { actual: None, .. })));
+        let _ = handle.join();
+    }
+    #[test]
+    fn listener_closes_clients_without_preamble() {
+        let mut lsnr = Listener::<P,L>::new(1);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        /*-- an older client opens with a frame --*/
+        let mut old = TcpStream::connect(lsnr.local_addr().unwrap()).unwrap();
+        old.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut msg = Message::create_msg_str_fit("old client");
+        msg.set_type(MessageType::FLUSH as u8);
+        P::send_message(&msg, &mut old).unwrap();
+        let mut answer = Vec::new();
+        match old.read_to_end(&mut answer) {
+            Ok(_) => assert_eq!(answer, PREAMBLE),
+            Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset),
+        }
+
+        /*-- a current client is served --*/
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        let reply = conn.post_and_wait(msg, Duration::from_secs(5)).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "old client");
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+}
@@ -8,20 +8,64 @@
 
    Defined Types:
    - Listener<P,L>
-   - Connector<P,M,L>
+   - Connector<P,M,L,T>
      - P is a processing type supporting application needs
      - L is a log type which is expected to be either
        VerboseLog or MuteLog
      - M is a message type
+     - T is the Transport it talks over, TcpStream unless
+       built with Connector::from_transport
+   - MemoryTransport, in module transport, an in-memory
+     Transport for running without sockets
+   - TlsStream, in module tls, with feature "tls", a
+     Transport encrypting its TcpStream
+   - AsyncConnector<M,L,C,Z>, in module async_connector,
+     with feature "tokio", a Connector for async code
+   - ConnectionEvent, from Connector::events, tells an
+     application when its connection drops or comes back
+   - WebSocket sessions, in module websocket, letting
+     browsers talk to a Listener
+   - SOCKS5 proxy support, in module socks, for
+     Connectors that can't reach a Listener directly
+   - protocol version preamble, in module handshake,
+     exchanged as each connection opens
    P processes messages and its code must work with that
    of the Message type.
    
    Traits used by these types are defined in rust_traits.
+   Options for both are held in CommConfig, in module config.
 */
 
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
+mod config;
+pub use config::*;
+mod transport;
+pub use transport::*;
+mod rate;
+use rate::TokenBucket;
+mod stats;
+pub use stats::{ConnectorStats, ServerStats};
+mod connector_pool;
+pub use connector_pool::*;
+mod file_transfer;
+pub use file_transfer::{FILE_CHUNK_SIZE, FILE_ACK_TIMEOUT};
+use file_transfer::FileSink;
+mod websocket;
+mod socks;
+mod handshake;
+pub use handshake::{PROTOCOL_MAGIC, PROTOCOL_VERSION};
+use stats::{Counters, Metered};
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "tls")]
+pub use tls::*;
+#[cfg(feature = "tokio")]
+mod async_connector;
+#[cfg(feature = "tokio")]
+pub use async_connector::*;
+
 /*-- rust_comm facilities --*/
 use rust_traits::*;
 use rust_message::*;
@@ -32,264 +76,3778 @@ use rust_thread_pool::*;
 
 /*-- std library facilities --*/
 use std::fmt::*;
-use std::sync::{Arc, atomic::AtomicBool, atomic::Ordering};
-use std::net::{TcpStream, TcpListener, Shutdown};
+use std::sync::{Arc, Mutex, Condvar, atomic::AtomicBool, atomic::AtomicUsize, atomic::AtomicU64, atomic::Ordering};
+use std::net::{TcpStream, TcpListener, Shutdown, SocketAddr, ToSocketAddrs};
 use std::io::{Result, BufReader, BufWriter, stdout, Write};
 use std::io::prelude::*;
 use std::thread;
 use std::thread::{JoinHandle};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TrySendError};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 type L = MuteLog;
 type M = Message;
 type P = CommProcessing<L>;
 
+/*-- how often Listener's accept loop checks for stop --*/
+pub const ACCEPT_POLL: Duration = Duration::from_millis(10);
+/*-- longest a dropped Connector waits for each of its threads --*/
+pub const DROP_TIMEOUT: Duration = Duration::from_secs(2);
+/*-- send queue level of heartbeat PINGs, ahead of all others --*/
+pub const PING_PRIORITY: u8 = u8::MAX;
+/*-- how often Connector::incoming checks a waiting link is up --*/
+pub const INCOMING_POLL: Duration = Duration::from_millis(10);
+/*-- unread events held for each Connector::events receiver --*/
+pub const EVENT_QUEUE_SIZE: usize = 64;
+
+/*---------------------------------------------------------
+  connect to first resolved address that answers
+  - deadline is shared by all resolved addresses so the
+    whole attempt takes no longer than config's
+    connect_timeout
+*/
+fn connect_timeout(addrs: &[SocketAddr], config: &CommConfig) -> Result<TcpStream> {
+    let deadline = Instant::now() + config.connect_timeout;
+    let mut last_err = std::io::Error::new(
+        std::io::ErrorKind::InvalidInput, "address did not resolve"
+    );
+    for sock_addr in addrs {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut, "connect timed out"
+            ));
+        }
+        match config.connect(sock_addr, deadline - now) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
+}
+/*-- resolve and connect to addr, reporting failure --*/
+fn open_stream<L, A>(addr: &A, config: &CommConfig) -> Result<(Vec<SocketAddr>, TcpStream)>
+where L: Logger, A: ToSocketAddrs + Debug
+{
+    let addrs = resolve(addr)?;
+    match connect_timeout(&addrs, config) {
+        Ok(stream) => {
+            L::info(&format!("\n--connected to {:?}--", addr));
+            Ok((addrs, stream))
+        }
+        Err(e) => {
+            L::error(&format!("\n-- connection to {:?} failed --", addr));
+            Err(std::io::Error::other(
+                format!("connect to {:?} failed: {}", addr, e)
+            ))
+        }
+    }
+}
+/*-- resolve addr once, so Connector can reconnect without DNS --*/
+fn resolve<A: ToSocketAddrs + Debug>(addr: &A) -> Result<Vec<SocketAddr>> {
+    addr.to_socket_addrs()
+        .map(|iter| iter.collect())
+        .map_err(|e| std::io::Error::new(
+            e.kind(), format!("can't resolve {:?}: {}", addr, e)
+        ))
+}
+
 /*---------------------------------------------------------
-  Connector<P,M,L> - attempts to connect to Listener<P,L>
+  Link - socket state shared by Connector and its threads
+  - send thread writes through writer, waiting on the
+    condvar while there is no live socket
+  - send thread holds the lock while writing, so the
+    stream used to shut down the socket is kept outside
+    of Link, in Connector::stream, and the thread handles
+    in Connector::threads, letting shut_down unblock a
+    write to a peer that has stopped reading
+  - generation identifies the socket a recv thread was
+    started for, so a stale recv thread can't take down
+    a newer connection
+  - ping_sent is when the heartbeat's outstanding PING
+    was queued, cleared when its PONG arrives
+  - next_seq numbers the socket's outgoing messages,
+    from 1 for each socket
 */
 #[derive(Debug)]
-pub struct Connector<P,M,L> where 
-    M: Msg + Clone + Send + Default,
+struct Link<T: Transport> {
+    writer: Option<BufWriter<Metered<T>>>,
+    generation: u64,
+    next_seq: u64,
+    ping_sent: Option<Instant>,
+}
+impl<T: Transport> Default for Link<T> {
+    fn default() -> Self {
+        Link {
+            writer: None,
+            generation: 0,
+            next_seq: 1,
+            ping_sent: None,
+        }
+    }
+}
+type SharedLink<T> = Arc<(Mutex<Link<T>>, Condvar)>;
+
+/*-- Connector's send, recv, and heartbeat threads --*/
+#[derive(Debug, Default)]
+struct Threads {
+    send: Option<JoinHandle<()>>,
+    recv: Option<JoinHandle<()>>,
+    beat: Option<JoinHandle<()>>,
+}
+
+/*---------------------------------------------------------
+  replies awaited by post_and_wait, keyed by correlation id
+  - None until recv thread delivers the reply
+*/
+type Pending<M> = Arc<(Mutex<HashMap<u64, Option<M>>>, Condvar)>;
+
+/*---------------------------------------------------------
+  send queue progress, for Connector::flush
+  - posted counts messages put in the send queue, settled
+    those the send thread has since written and flushed,
+    or lost in a failed write, so message n is settled
+    once settled >= n
+  - lost_upto is settled after the last failed write, and
+    checked the messages covered by earlier flush calls,
+    so a loss is reported by the flush that covers it
+*/
+#[derive(Debug, Default)]
+struct Progress {
+    posted: u64,
+    settled: u64,
+    lost_upto: u64,
+    checked: u64,
+}
+type SharedProgress = Arc<(Mutex<Progress>, Condvar)>;
+
+/*---------------------------------------------------------
+  whether a Connector's link is up, and who's told when
+  that changes
+  - up changes only while watchers is locked, so every
+    subscriber sees events in the order they happened
+  - ever_up tells a first connection from a reconnect
+*/
+#[derive(Debug, Default)]
+struct LinkStatus {
+    up: AtomicBool,
+    watchers: Mutex<Watchers>,
+}
+#[derive(Debug, Default)]
+struct Watchers {
+    ever_up: bool,
+    subscribers: Vec<SyncSender<ConnectionEvent>>,
+}
+impl Watchers {
+    /*-- never blocks, dropped for a full queue, pruned if receiver is gone --*/
+    fn notify(&mut self, event: ConnectionEvent) {
+        self.subscribers.retain(|tx| {
+            !matches!(tx.try_send(event), Err(TrySendError::Disconnected(_)))
+        });
+    }
+}
+impl LinkStatus {
+    fn is_up(&self) -> bool {
+        self.up.load(Ordering::Relaxed)
+    }
+    fn came_up(&self) {
+        let mut w = self.watchers.lock().unwrap();
+        if !self.up.swap(true, Ordering::Relaxed) {
+            let event = if w.ever_up {
+                ConnectionEvent::Reconnected
+            } else {
+                ConnectionEvent::Connected
+            };
+            w.ever_up = true;
+            w.notify(event);
+        }
+    }
+    fn went_down(&self) {
+        let mut w = self.watchers.lock().unwrap();
+        if self.up.swap(false, Ordering::Relaxed) {
+            w.notify(ConnectionEvent::Disconnected);
+        }
+    }
+    fn report(&self, event: ConnectionEvent) {
+        self.watchers.lock().unwrap().notify(event);
+    }
+    fn subscribe(&self) -> Receiver<ConnectionEvent> {
+        let (tx, rx) = sync_channel(EVENT_QUEUE_SIZE);
+        let mut w = self.watchers.lock().unwrap();
+        if self.is_up() {
+            let _ = tx.try_send(ConnectionEvent::Connected);
+        }
+        w.subscribers.push(tx);
+        rx
+    }
+    /*-- after shut_down, receivers see the channel close --*/
+    fn close(&self) {
+        self.watchers.lock().unwrap().subscribers.clear();
+    }
+}
+
+/*-- move sent, then all queued, less PINGs, to dead, returning number queued --*/
+fn strand<M: Msg>(sent: &mut Vec<M>, queue: &PriorityBlockingQueue<M>, dead: &Mutex<Vec<M>>) 
+    -> u64 
+{
+    let mut queued = 0;
+    while let Some(msg) = queue.try_de_q() {
+        queued += 1;
+        sent.push(msg);
+    }
+    let ping = MessageType::PING as u8;
+    dead.lock().unwrap().extend(sent.drain(..).filter(|m| m.get_type() != ping));
+    queued
+}
+/*-- addresses of a Connector's connection, as its stream reported them --*/
+#[derive(Debug, Default, Clone, Copy)]
+struct Endpoints {
+    local: Option<SocketAddr>,
+    peer: Option<SocketAddr>,
+}
+/*-- next is a repeat, of a type coalesced, of last, ids aside --*/
+fn repeats<M: Msg>(coalesce: &[u8], last: &M, next: &M) -> bool {
+    coalesce.contains(&next.get_type())
+        && next.get_type() == last.get_type()
+        && next.get_flags() == last.get_flags()
+        && next.get_topic() == last.get_topic()
+        && next.get_content_bytes() == last.get_content_bytes()
+}
+/*-- Connector's on_message callback, passed each received message --*/
+pub type MessageCallback<M> = Arc<dyn Fn(M) + Send + Sync>;
+/*-- on_message callback, if set, shared by Connector and recv thread --*/
+struct MessageHook<M>(Mutex<Option<MessageCallback<M>>>);
+impl<M> MessageHook<M> {
+    fn get(&self) -> Option<MessageCallback<M>> {
+        self.0.lock().unwrap().clone()
+    }
+    fn set(&self, f: Option<MessageCallback<M>>) {
+        *self.0.lock().unwrap() = f;
+    }
+}
+impl<M> Debug for MessageHook<M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MessageHook").field(&self.0.lock().unwrap().is_some()).finish()
+    }
+}
+
+/*-- hand a REPLY to a waiting post_and_wait, else return it --*/
+fn claim_reply<M: Msg>(pending: &Pending<M>, msg: M) -> Option<M> {
+    let id = msg.get_id();
+    if id == 0 || msg.get_type() != MessageType::REPLY as u8 {
+        return Some(msg);
+    }
+    let mut waiting = pending.0.lock().unwrap();
+    match waiting.get_mut(&id) {
+        Some(slot) if slot.is_none() => {
+            *slot = Some(msg);
+            pending.1.notify_all();
+            None
+        }
+        _ => Some(msg),
+    }
+}
+
+/*---------------------------------------------------------
+  Connector<P,M,L,T> - attempts to connect to Listener<P,L>
+  - T is TcpStream, except for Connectors built around
+    another Transport by from_transport, which can't
+    reconnect
+*/
+#[derive(Debug)]
+pub struct Connector<P,M,L,T = TcpStream> where 
+    M: Msg + Clone + Send + Default + 'static,
     P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>, 
-    L: Logger + Debug + Copy + Clone + Default
+    L: Logger + Debug + Copy + Clone + Default,
+    T: Transport
 {
-    snd_queue: Arc<BlockingQueue<M>>,
+    snd_queue: Arc<PriorityBlockingQueue<M>>,
     rcv_queue: Arc<BlockingQueue<M>>,
      _p: P,
-     connected: bool,
+     connected: Arc<LinkStatus>,
+     stop: Arc<AtomicBool>,  // set by shut_down
      log: L,
+     addrs: Vec<SocketAddr>,
+     link: SharedLink<T>,
+     stream: Arc<Mutex<Option<T>>>,
+     threads: Mutex<Threads>,
+     config: CommConfig,
+     next_id: AtomicU64,
+     pending: Pending<M>,
+     progress: SharedProgress,
+     counters: Arc<Counters>,
+     undelivered: Arc<Mutex<Vec<M>>>,  // kept with dead_letters
+     on_message: Arc<MessageHook<M>>,  // unset queues messages
+     endpoints: Mutex<Endpoints>,  // of the current connection
     //  msg_size: usize,
 }
-impl<P,M,L> Connector<P,M,L> where
+impl<P,M,L,T> Connector<P,M,L,T> where
     M: Msg + Clone + Send + Default + 'static,
     P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
-    L: Logger + Debug + Copy + Clone + Default
+    L: Logger + Debug + Copy + Clone + Default,
+    T: Transport
 {    
+    /*-----------------------------------------------------
+      talk over a connected stream, e.g., one end of
+      MemoryTransport::pair()
+    */
+    pub fn from_transport(stream: T) -> std::io::Result<Connector<P,M,L,T>> {
+        Self::from_transport_with_config(stream, CommConfig::default())
+    }
+    /*-- same, using config's queue and heartbeat options --*/
+    pub fn from_transport_with_config(stream: T, config: CommConfig) 
+        -> std::io::Result<Connector<P,M,L,T>> 
+    {
+        Self::build(Vec::new(), stream, config)
+    }
+    /*-----------------------------------------------------
+      false once the socket has failed, or, with a 
+      heartbeat configured, once the peer stops answering
+      PINGs
+    */
     pub fn is_connected(&self) -> bool {
-        self.connected
+        self.connected.is_up()
+    }
+    /*-----------------------------------------------------
+      local address of the current connection, set before
+      new returns and again by each reconnect
+      - None if the transport can't report it
+    */
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.endpoints.lock().unwrap().local
+    }
+    /*-- remote address of the current connection, as for local_addr --*/
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.endpoints.lock().unwrap().peer
+    }
+    /*-----------------------------------------------------
+      receiver of this Connector's ConnectionEvents
+      - starts with Connected if the Connector is connected
+        now, then has each later change, in order, so
+        Disconnected alternates with Connected or
+        Reconnected
+      - sending never blocks the Connector's threads, an
+        event is dropped if EVENT_QUEUE_SIZE are unread
+      - closes once the Connector is shut down, after its
+        last Disconnected
+      - each call makes a new receiver, all see the same
+        events
+    */
+    pub fn events(&self) -> Receiver<ConnectionEvent> {
+        if self.stop.load(Ordering::Relaxed) {
+            return sync_channel(0).1;
+        }
+        self.connected.subscribe()
+    }
+    /*-----------------------------------------------------
+      queue msg for send thread
+      - msg without a correlation id, i.e., id 0, is given
+        the next unused id
+      - fails with NotConnected after shut_down, or once
+        the socket is found dead, until reconnect succeeds
+    */
+    pub fn post_message(&self, msg: M) -> std::result::Result<(), CommError> {
+        self.post_message_priority(msg, DEFAULT_PRIORITY)
+    }
+    /*-----------------------------------------------------
+      same as post_message, but msg is sent ahead of
+      queued messages of lower level, and behind those of
+      its own, post_message using DEFAULT_PRIORITY, 0
+      - e.g., a QUIT posted at a high level goes out next,
+        and the messages it passed are never sent
+    */
+    pub fn post_message_priority(&self, mut msg: M, level: u8) 
+        -> std::result::Result<(), CommError> 
+    {
+        if !self.can_send() {
+            L::warn("\n  -- not connected, msg discarded --");
+            return Err(CommError::NotConnected);
+        }
+        if msg.get_id() == 0 {
+            msg.set_id(self.next_id.fetch_add(1, Ordering::Relaxed));
+        }
+        self.snd_queue.en_q_priority(msg, level);
+        self.progress.0.lock().unwrap().posted += 1;
+        Ok(())
+    }
+    /*-----------------------------------------------------
+      wait at most timeout for every message posted so far
+      to be written to the socket and flushed
+      - true once they have been, false at timeout, or if
+        a write failed, losing messages posted since the
+        previous flush
+      - a flush may also cover messages posted while it
+        waits
+    */
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let (lock, cv) = &*self.progress;
+        let deadline = Instant::now().checked_add(timeout);
+        let mut progress = lock.lock().unwrap();
+        let target = progress.posted;
+        while progress.settled < target {
+            let Some(deadline) = deadline else {
+                progress = cv.wait(progress).unwrap();
+                continue;
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            progress = cv.wait_timeout(progress, deadline - now).unwrap().0;
+        }
+        let ok = progress.lost_upto <= progress.checked;
+        progress.checked = progress.checked.max(target);
+        ok
+    }
+    /*-----------------------------------------------------
+      post msg and wait at most timeout for its reply
+      - reply is the REPLY message carrying msg's
+        correlation id; other messages, e.g., the
+        server's pushes, stay queued for get_message
+      - None if not connected or the reply doesn't arrive
+        in time, a late reply is queued for get_message
+    */
+    pub fn post_and_wait(&self, mut msg: M, timeout: Duration) -> Option<M> {
+        if msg.get_id() == 0 {
+            msg.set_id(self.next_id.fetch_add(1, Ordering::Relaxed));
+        }
+        let id = msg.get_id();
+        let (lock, cv) = &*self.pending;
+        lock.lock().unwrap().insert(id, None);
+        if self.post_message(msg).is_err() {
+            lock.lock().unwrap().remove(&id);
+            return None;
+        }
+        let deadline = Instant::now().checked_add(timeout);
+        let mut waiting = lock.lock().unwrap();
+        loop {
+            if let Some(Some(_)) = waiting.get(&id) {
+                return waiting.remove(&id).flatten();
+            }
+            let Some(deadline) = deadline else {
+                waiting = cv.wait(waiting).unwrap();
+                continue;
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                waiting.remove(&id);
+                return None;
+            }
+            waiting = cv.wait_timeout(waiting, deadline - now).unwrap().0;
+        }
     }
-    pub fn post_message(&self, msg: M) {
-        self.snd_queue.en_q(msg);
+    /*-----------------------------------------------------
+      write a message of type msg_type with body at once,
+      on the caller's thread, without copying body into
+      an M, e.g., for megabyte file contents
+      - returns the message's correlation id, for matching
+        its reply
+      - isn't queued, so it may pass messages that are
+        still queued by post_message
+      - no flags are set, so body has no checksum
+      - fails with NotConnected if there's no live socket,
+        or Io if the write fails, marking the link down,
+        or if P has no buf_write_bytes
+    */
+    pub fn post_bytes(&self, msg_type: u8, body: &[u8]) -> std::result::Result<u64, CommError> {
+        if !self.can_send() {
+            return Err(CommError::NotConnected);
+        }
+        let mut lk = self.link.0.lock().unwrap();
+        let Some(writer) = lk.writer.as_mut() else {
+            return Err(CommError::NotConnected);
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = P::buf_write_bytes(msg_type, id, body, writer).and_then(|_| writer.flush()) {
+            if e.kind() == std::io::ErrorKind::Unsupported {
+                return Err(CommError::Io(e));  // nothing was written
+            }
+            L::warn("\n  -- send failed, waiting for reconnect --");
+            lk.writer = None;
+            self.connected.went_down();
+            return Err(CommError::Io(e));
+        }
+        Counters::count(&self.counters.messages_sent, 1);
+        Ok(id)
     }
-    pub fn get_message(&self) -> M {
+    /*-----------------------------------------------------
+      wait for a message
+      - None once shut down and all received messages
+        have been read
+    */
+    pub fn get_message(&self) -> Option<M> {
         self.rcv_queue.de_q()
     }
+    /*-- return a message if one is waiting, never blocks --*/
+    pub fn try_get_message(&self) -> Option<M> {
+        self.rcv_queue.try_de_q()
+    }
+    /*-----------------------------------------------------
+      iterate over received messages, as get_message
+      returns them, e.g., for msg in conn.incoming() {...}
+      - ends after shut_down, or once the connection is
+        down, when the messages already received have
+        been read
+      - after a reconnect, call incoming again to go on
+    */
+    pub fn incoming(&self) -> impl Iterator<Item = M> + '_ {
+        std::iter::from_fn(move || loop {
+            if let Some(msg) = self.rcv_queue.de_q_timeout(INCOMING_POLL) {
+                return Some(msg);
+            }
+            if self.rcv_queue.is_closed() || !self.is_connected() {
+                return self.rcv_queue.try_de_q();
+            }
+        })
+    }
+    /*-----------------------------------------------------
+      copy of the next message get_message would return,
+      left queued, None if none is waiting, never blocks
+      - not atomic with a following get_message, another
+        thread reading this Connector may take the message
+        first, so peek from the only reader
+    */
+    pub fn peek_message(&self) -> Option<M> {
+        self.rcv_queue.peek()
+    }
+    /*-----------------------------------------------------
+      have the recv thread pass each message it receives
+      to f, in place of queuing it for get_message
+      - replies awaited by post_and_wait still go to
+        their callers, and messages queued before f was
+        set stay queued
+      - f runs on the recv thread, so nothing is read
+        while it runs; it must not call get_message,
+        post_and_wait, flush, or shut_down, which wait on
+        that thread, nor post_message on a full bounded
+        queue; hand such work to another thread
+      - f must not panic, that ends the recv thread
+    */
+    pub fn on_message<F>(&self, f: F) 
+    where F: Fn(M) + Send + Sync + 'static
+    {
+        self.on_message.set(Some(Arc::new(f)));
+    }
+    /*-- queue received messages for get_message again --*/
+    pub fn clear_on_message(&self) {
+        self.on_message.set(None);
+    }
+    /*-----------------------------------------------------
+      messages stranded by failed sends, oldest first,
+      see CommConfig::dead_letters
+      - empty unless dead_letters is set, each taken once
+    */
+    pub fn take_undelivered(&self) -> Vec<M> {
+        std::mem::take(&mut *self.undelivered.lock().unwrap())
+    }
+    /*-- wait at most dur for a message, None if none arrives --*/
+    pub fn get_message_timeout(&self, dur: Duration) -> Option<M> {
+        self.rcv_queue.de_q_timeout(dur)
+    }
     pub fn has_msg(&self) -> bool {
-        self.rcv_queue.len() > 0
+        !self.rcv_queue.is_empty()
+    }
+    /*-- traffic counters and current send queue depth --*/
+    pub fn stats(&self) -> ConnectorStats {
+        self.counters.snapshot(self.snd_queue.len())
+    }
+    /*-- send queue capacity, None if unbounded --*/
+    pub fn send_capacity(&self) -> Option<usize> {
+        self.snd_queue.capacity()
+    }
+    /*-----------------------------------------------------
+      stop send and recv threads and wait for them
+      - send thread sends anything already queued, then
+        an END message, then exits
+      - replies already received can still be read, then
+        get_message returns None
+      - socket is then shut down so recv thread exits
+        even if the peer doesn't close its end
+    */
+    pub fn shut_down(&self) {
+        self.stop_threads(None);
+    }
+    /*-----------------------------------------------------
+      stop sending, but keep receiving
+      - send thread writes everything already queued, then
+        exits, and the socket's write side is shut down,
+        so the Listener sees EOF, flushes its replies, and
+        closes its end
+      - recv thread runs on, queueing those replies for
+        get_message, until the Listener closes
+      - later posts fail with NotConnected, and shut_down
+        still stops the recv thread
+      - NotConnected if the connection is down, queued
+        messages are then dropped, or, with dead_letters,
+        kept for take_undelivered
+    */
+    pub fn shutdown_write(&self) -> std::result::Result<(), CommError> {
+        self.snd_queue.close();
+        /*-- wake send thread if it's waiting for a socket, it gives up --*/
+        self.link.1.notify_all();
+        let send_handle = self.threads.lock().unwrap().send.take();
+        if let Some(handle) = send_handle {
+            let _ = handle.join();
+        }
+        self.link.0.lock().unwrap().writer = None;
+        if !self.is_connected() {
+            return Err(CommError::NotConnected);
+        }
+        match self.stream.lock().unwrap().as_ref() {
+            Some(stream) => Ok(stream.shutdown(Shutdown::Write)?),
+            None => Err(CommError::NotConnected),
+        }
     }
-    pub fn new(addr: &'static str) -> std::io::Result<Connector<P,M,L>>
-    where
-        M: Msg + Clone + Send + Default + 'static,
-        P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
-        L: Logger + Copy + Clone + Default
+    /*-- not shut down, or shut down for writing, and connected --*/
+    fn can_send(&self) -> bool {
+        !self.stop.load(Ordering::Relaxed) && !self.snd_queue.is_closed() && self.is_connected()
+    }
+    /*-----------------------------------------------------
+      shut_down, waiting at most limit for each thread
+      - a thread still running at the limit is detached
+      - END and the send thread share one limit, then the
+        socket is shut down, failing a write blocked on a
+        peer that has stopped reading
+      - the link lock, held by a blocked write, isn't
+        taken until the socket is shut down
+    */
+    fn stop_threads(&self, limit: Option<Duration>) {
+        if self.stop.swap(true, Ordering::Relaxed) {
+            return;  // already shut down
+        }
+        /*-- a limit too long to add to now is no limit --*/
+        let deadline = limit.and_then(|limit| Instant::now().checked_add(limit));
+        let expired = || deadline.map(|d| Instant::now() >= d).unwrap_or(false);
+        /*-- wake send thread if it's waiting for a socket --*/
+        self.link.1.notify_all();
+        /*-----------------------------------------------
+          a bounded queue may be full, so retry END
+          until it fits or the send thread has exited
+        */
+        let mut msg = M::new(HEADER_SIZE);
+        msg.set_type(MessageType::END as u8);
+        while let Err(m) = self.snd_queue.try_en_q(msg) {
+            let done = self.threads.lock().unwrap().send.as_ref()
+                .map(|h| h.is_finished()).unwrap_or(true);
+            if done || expired() {
+                break;
+            }
+            msg = m;
+            thread::sleep(Duration::from_millis(1));
+        }
+        self.snd_queue.close();
+        let send_handle = self.threads.lock().unwrap().send.take();
+        if let Some(handle) = send_handle {
+            let left = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            join_within(handle, left);
+        }
+        if let Some(stream) = self.stream.lock().unwrap().take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        self.link.0.lock().unwrap().writer = None;
+        let recv_handle = self.threads.lock().unwrap().recv.take();
+        if let Some(handle) = recv_handle {
+            join_within(handle, limit);
+        }
+        let beat_handle = self.threads.lock().unwrap().beat.take();
+        if let Some(handle) = beat_handle {
+            join_within(handle, limit);
+        }
+        self.connected.went_down();
+        self.connected.close();
+        /*-- wakes get_message callers once replies are read --*/
+        self.rcv_queue.close();
+        L::info("\n--connector shut down--");
+    }
+    /*-----------------------------------------------------
+      consume Connector, returning only after its send
+      and recv threads have exited
+      - same as shut_down, which can be used when the
+        Connector is shared, e.g., through an Arc
+    */
+    pub fn join(self) {
+        self.shut_down();
+    }
+    /*-- true once send, recv, and heartbeat threads have exited --*/
+    pub fn is_finished(&self) -> bool {
+        let threads = self.threads.lock().unwrap();
+        let done = |h: &Option<JoinHandle<()>>| {
+            h.as_ref().map(|h| h.is_finished()).unwrap_or(true)
+        };
+        done(&threads.send) && done(&threads.recv) && done(&threads.beat)
+    }
+    /*-- build Connector around a connected stream --*/
+    fn build(
+        addrs: Vec<SocketAddr>, stream: T, config: CommConfig
+    ) -> std::io::Result<Connector<P,M,L,T>>
     {
-        let mut _is_connected = false;
-        let rslt = TcpStream::connect(addr);
-        if rslt.is_err() {
-             print!("\n-- connection to {:?} failed --", addr);
-             return Err(std::io::Error::new(std::io::ErrorKind::Other, "connect failed"));
-        }
-        else {
-            _is_connected = true;
-            L::write(&format!("\n--connected to {:?}--", addr));
-        }
-        let stream = rslt.unwrap();
-        let mut buf_writer = BufWriter::new(stream.try_clone()?);
-        let mut buf_reader = BufReader::new(stream.try_clone()?);
-        
-        let send_queue = Arc::new(BlockingQueue::<M>::new());
-        let recv_queue = Arc::new(BlockingQueue::<M>::new());
-        
-        /*-- send thread reads input queue and sends msg --*/
-        let sqm = Arc::clone(&send_queue);
-        let _ = std::thread::spawn(move || {
-            let ssq = Arc::clone(&sqm);
+        let snd_queue = match config.send_capacity {
+            Some(cap) => PriorityBlockingQueue::<M>::with_capacity(cap),
+            None => PriorityBlockingQueue::<M>::new(),
+        };
+        let me =
+        Self {
+            _p: P::default(),
+            snd_queue: Arc::new(snd_queue),
+            rcv_queue: Arc::new(BlockingQueue::<M>::new()),
+            connected: Arc::new(LinkStatus::default()),
+            stop: Arc::new(AtomicBool::new(false)),
+            log: L::default(),
+            addrs,
+            link: Arc::new((Mutex::new(Link::default()), Condvar::new())),
+            stream: Arc::new(Mutex::new(None)),
+            threads: Mutex::new(Threads::default()),
+            config,
+            next_id: AtomicU64::new(1),
+            pending: Arc::new((Mutex::new(HashMap::new()), Condvar::new())),
+            progress: Arc::new((Mutex::new(Progress::default()), Condvar::new())),
+            counters: Arc::new(Counters::default()),
+            undelivered: Arc::new(Mutex::new(Vec::new())),
+            on_message: Arc::new(MessageHook(Mutex::new(None))),
+            endpoints: Mutex::new(Endpoints::default()),
+            // msg_size: msg_size,
+        };
+        me.attach(stream)?;
+        if let Some((interval, timeout)) = me.config.heartbeat {
+            let handle = me.start_heartbeat(interval, timeout);
+            me.threads.lock().unwrap().beat = Some(handle);
+        }
+        Ok(me)
+    }
+    /*-----------------------------------------------------
+      install stream in link and start its recv thread
+      - send thread is started on first attach, or again
+        if it exited after sending END
+    */
+    fn attach(&self, stream: T) -> std::io::Result<()> {
+        if self.config.handshake {
+            handshake::exchange(&stream, self.config.connect_timeout)?;
+        }
+        if let Some(token) = &self.config.auth_token {
+            authenticate::<P,M,T>(&stream, token, self.config.connect_timeout)?;
+        }
+        *self.endpoints.lock().unwrap() = Endpoints {
+            local: stream.local_addr().ok(), peer: stream.peer_addr().ok()
+        };
+        let size = self.config.buffer_size;
+        let metered = Metered::new(stream.try_clone()?, &self.counters);
+        let buf_writer = BufWriter::with_capacity(size, metered.try_clone()?);
+        let buf_reader = BufReader::with_capacity(size, Polled::new(metered, &self.stop));
+        stream.set_read_timeout(self.config.recv_poll)?;
+        *self.stream.lock().unwrap() = Some(stream);
+        let mut lk = self.link.0.lock().unwrap();
+        lk.writer = Some(buf_writer);
+        lk.next_seq = 1;
+        lk.ping_sent = None;
+        let generation = lk.generation;
+        let mut threads = self.threads.lock().unwrap();
+        let send_running = threads.send.as_ref()
+            .map(|h| !h.is_finished()).unwrap_or(false);
+        if !send_running {
+            threads.send = Some(self.start_sender());
+        }
+        threads.recv = Some(self.start_receiver(buf_reader, generation));
+        self.connected.came_up();
+        self.link.1.notify_all();
+        Ok(())
+    }
+    /*-----------------------------------------------------
+      send thread reads input queue and sends msg
+      - blocks for a message, then writes it and every
+        message already queued behind it, flushing once
+        when the queue runs dry
+      - stops a batch at END or QUIT, which is flushed,
+        and exits, leaving later messages queued, as 
+        either one closes the connection
+      - settles each batch in progress once flushed, or
+        lost, waking flush callers
+      - stamps each message with the socket's next seq
+      - drops queued repeats of the message just written
+        if its type is one config's coalesce names
+      - on a failed send, marks the link down, and with
+        dead_letters moves the batch and queued messages,
+        less PINGs, to undelivered, as it does messages
+        found queued once the link is down
+    */
+    fn start_sender(&self) -> JoinHandle<()> {
+        let counters = Arc::clone(&self.counters);
+        let progress = Arc::clone(&self.progress);
+        let ssq = Arc::clone(&self.snd_queue);
+        let link = Arc::clone(&self.link);
+        let connected = Arc::clone(&self.connected);
+        let stop = Arc::clone(&self.stop);
+        let undelivered = Arc::clone(&self.undelivered);
+        let keep = self.config.dead_letters;
+        let coalesce = self.config.coalesce.clone();
+        std::thread::spawn(move || {
+            let (lock, cv) = &*link;
+            let mut sent = Vec::<M>::new();
             loop {
-                L::write("\n  -- dequing send msg --");
-                let msg = ssq.de_q();
-                L::write("\n  sending msg");
-                let msg_type = msg.get_type();
-                let rslt = P::buf_send_message(&msg, &mut buf_writer);
+                L::debug("\n  -- dequing send msg --");
+                let Some(mut msg) = ssq.de_q() else {
+                    break;  // closed by shut_down
+                };
+                /*-- wait for a live socket, may be mid-reconnect --*/
+                let mut lk = lock.lock().unwrap();
+                if keep && lk.writer.is_none() && !stop.load(Ordering::Relaxed) {
+                    sent.push(msg);
+                    let stranded = 1 + strand(&mut sent, &ssq, &undelivered);
+                    let mut p = progress.0.lock().unwrap();
+                    p.settled += stranded;
+                    p.lost_upto = p.settled;
+                    progress.1.notify_all();
+                    continue;
+                }
+                while lk.writer.is_none() {
+                    if stop.load(Ordering::Relaxed) || ssq.is_closed() {
+                        L::debug("\n--terminating connector send thread--");
+                        return;
+                    }
+                    lk = cv.wait(lk).unwrap();
+                }
+                let Link { writer, next_seq, .. } = &mut *lk;
+                let writer = writer.as_mut().unwrap();
+                let mut batch = 0u64;
+                let (rslt, end) = loop {
+                    batch += 1;
+                    L::debug("\n  sending msg");
+                    msg.set_seq(*next_seq);
+                    *next_seq += 1;
+                    if keep {
+                        sent.push(msg.clone());
+                    }
+                    let rslt = P::buf_write_message(&msg, writer);
+                    if rslt.is_ok() {
+                        Counters::count(&counters.messages_sent, 1);
+                    }
+                    let end = msg.get_type() == MessageType::END as u8
+                        || msg.get_type() == MessageType::QUIT as u8;
+                    if rslt.is_err() || end {
+                        break (rslt, end);
+                    }
+                    let next = loop {
+                        match ssq.try_de_q() {
+                            Some(next) if repeats(&coalesce, &msg, &next) => batch += 1,
+                            next => break next,
+                        }
+                    };
+                    match next {
+                        Some(next) => msg = next,
+                        None => break (rslt, end),
+                    }
+                };
+                let rslt = rslt.and_then(|_| writer.flush());
+                if rslt.is_err() && keep {
+                    batch += strand(&mut sent, &ssq, &undelivered);
+                }
+                sent.clear();
+                {
+                    let mut p = progress.0.lock().unwrap();
+                    p.settled += batch;
+                    if rslt.is_err() {
+                        p.lost_upto = p.settled;
+                    }
+                    progress.1.notify_all();
+                }
                 if rslt.is_err() {
-                    // may cause panic if io doesn't complete before 
-                    // thread shuts down
-                    // print!("\n  msg send error");
-                    break;
+                    /*-- batch is lost, queued msgs wait for reconnect --*/
+                    L::warn("\n  -- send failed, waiting for reconnect --");
+                    lk.writer = None;
+                    connected.went_down();
+                    continue;
                 }
-                L::write("\n  -- send successful --");
-                if msg_type == MessageType::END as u8 {
-                    L::write("\n--terminating connector send thread--");
+                L::debug("\n  -- send successful --");
+                if end {
+                    L::debug("\n--terminating connector send thread--");
                     break;
                 }
             }            
-        });
-        /*-- recv thread recvs msg (may block) and enQs for user --*/
-        let rqm = Arc::clone(&recv_queue);
-        let _ = std::thread::spawn(move || {
-            let srq = Arc::clone(&rqm);
-            loop {
-                L::write("\n  attempting to receive msg in connector");
-                let rslt = P::buf_recv_message(&mut buf_reader);
+        })
+    }
+    /*-----------------------------------------------------
+      recv thread recvs msg (may block) and enQs for user
+      - reads time out every recv_poll, so the thread sees
+        shut_down even while the peer is quiet
+      - with verify_sequence, reports numbered messages
+        that don't follow the last, see CommConfig
+      - passes messages to on_message's callback, if set
+    */
+    fn start_receiver(&self, mut buf_reader: BufReader<Polled<Metered<T>>>, generation: u64) 
+        -> JoinHandle<()> 
+    {
+        let counters = Arc::clone(&self.counters);
+        let srq = Arc::clone(&self.rcv_queue);
+        let link = Arc::clone(&self.link);
+        let connected = Arc::clone(&self.connected);
+        let stop = Arc::clone(&self.stop);
+        let pending = Arc::clone(&self.pending);
+        let verify = self.config.verify_sequence;
+        let on_message = Arc::clone(&self.on_message);
+        std::thread::spawn(move || {
+            let mut expected = 1u64;
+            while !stop.load(Ordering::Relaxed) {
+                L::debug("\n  attempting to receive msg in connector");
+                let rslt = P::buf_recv_message(&mut buf_reader).map(|(msg, _)| msg);
                 if rslt.is_err() {
-                    L::write("\n--terminating connector receive thread--");
+                    /*-- only current socket's failure marks link down --*/
+                    let mut lk = link.0.lock().unwrap();
+                    if lk.generation == generation {
+                        lk.writer = None;
+                        connected.went_down();
+                    }
+                    L::debug("\n--terminating connector receive thread--");
                     break;
                 }
                 let msg = rslt.unwrap();
+                Counters::count(&counters.messages_received, 1);
+                let got = msg.get_seq();
+                if verify && got != 0 {
+                    if got != expected {
+                        L::warn(&format!("\n--sequence gap, expected {}, got {}--", expected, got));
+                        connected.report(ConnectionEvent::SequenceGap { expected, got });
+                    }
+                    expected = got + 1;
+                }
+                if msg.get_type() == MessageType::PONG as u8 {
+                    link.0.lock().unwrap().ping_sent = None;
+                    continue;
+                }
+                let Some(msg) = claim_reply(&pending, msg) else {
+                    continue;  // delivered to post_and_wait
+                };
+                if let Some(f) = on_message.get() {
+                    f(msg);
+                    continue;
+                }
                 srq.en_q(msg);
-                L::write(&format!("\n  recv_queue len: {}", srq.len()));
+                L::debug(&format!("\n  recv_queue len: {}", srq.len()));
             }
-        });
-        /*-- return new Connector as std::io::Result --*/
-        let me =
-        Self {
-            _p: P::default(),
-            snd_queue: send_queue,
-            rcv_queue: recv_queue,
-            connected: _is_connected,
-            log: L::default(),
-            // msg_size: msg_size,
+        })
+    }
+    /*-----------------------------------------------------
+      heartbeat thread, sends PING every interval
+      - if the last PING is still unanswered after timeout
+        the link is marked down and its socket shut down,
+        so recv thread exits and reconnect can recover
+      - PING is queued at PING_PRIORITY, so it's sent
+        ahead of queued application messages, though it
+        may wait for a batch being written
+      - skips beats while there is no live socket
+    */
+    fn start_heartbeat(&self, interval: Duration, timeout: Duration) 
+        -> JoinHandle<()> 
+    {
+        let ssq = Arc::clone(&self.snd_queue);
+        let progress = Arc::clone(&self.progress);
+        let link = Arc::clone(&self.link);
+        let connected = Arc::clone(&self.connected);
+        let stop = Arc::clone(&self.stop);
+        let stream = Arc::clone(&self.stream);
+        std::thread::spawn(move || {
+            let (lock, cv) = &*link;
+            let mut next_beat = Instant::now() + interval;
+            let mut lk = lock.lock().unwrap();
+            loop {
+                let now = Instant::now();
+                if !stop.load(Ordering::Relaxed) && now < next_beat {
+                    lk = cv.wait_timeout(lk, next_beat - now).unwrap().0;
+                    continue;  // woken early or deadline now passed
+                }
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                next_beat = now + interval;
+                if lk.writer.is_none() {
+                    lk.ping_sent = None;
+                    continue;
+                }
+                match lk.ping_sent {
+                    Some(sent) if sent.elapsed() >= timeout => {
+                        L::error("\n--no PONG from peer, connection is dead--");
+                        lk.writer = None;
+                        lk.ping_sent = None;
+                        connected.went_down();
+                        if let Some(s) = stream.lock().unwrap().as_ref() {
+                            let _ = s.shutdown(Shutdown::Both);
+                        }
+                    }
+                    Some(_) => {}
+                    None => {
+                        let mut ping = M::new(HEADER_SIZE);
+                        ping.set_type(MessageType::PING as u8);
+                        if ssq.try_en_q_priority(ping, PING_PRIORITY).is_ok() {
+                            progress.0.lock().unwrap().posted += 1;
+                            lk.ping_sent = Some(Instant::now());
+                        }
+                    }
+                }
+            }
+            L::debug("\n--terminating connector heartbeat thread--");
+        })
+    }
+}
+/*-- constructors and reconnect for Connectors over TCP --*/
+impl<P,M,L> Connector<P,M,L,TcpStream> where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
+    L: Logger + Debug + Copy + Clone + Default
+{
+    /*-- connect, giving up after CONNECT_TIMEOUT --*/
+    pub fn new<A>(addr: A) -> std::io::Result<Connector<P,M,L>>
+    where A: ToSocketAddrs + Debug
+    {
+        Self::new_with_timeout(addr, CONNECT_TIMEOUT)
+    }
+    /*-- connect, giving up after dur --*/
+    pub fn new_with_timeout<A>(addr: A, dur: Duration) 
+        -> std::io::Result<Connector<P,M,L>>
+    where A: ToSocketAddrs + Debug
+    {
+        Self::new_with_config(addr, CommConfig::default().connect_timeout(dur))
+    }
+    /*-----------------------------------------------------
+      connect, with send queue holding at most capacity
+      messages
+      - post_message blocks while the queue is full, so a
+        fast producer is paced by the send thread
+    */
+    pub fn new_bounded<A>(addr: A, capacity: usize) 
+        -> std::io::Result<Connector<P,M,L>>
+    where A: ToSocketAddrs + Debug
+    {
+        Self::new_with_config(addr, CommConfig::default().send_capacity(capacity))
+    }
+    /*-- connect using options in config --*/
+    pub fn new_with_config<A>(addr: A, config: CommConfig) 
+        -> std::io::Result<Connector<P,M,L>>
+    where A: ToSocketAddrs + Debug
+    {
+        let (addrs, stream) = open_stream::<L,A>(&addr, &config)?;
+        Self::from_stream(addrs, stream, config)
+    }
+    /*-----------------------------------------------------
+      connect, making up to max_attempts tries
+      - waits base_delay after first failure, doubling
+        the wait after each subsequent failure
+    */
+    pub fn new_with_retry<A>(
+        addr: A, max_attempts: usize, base_delay: Duration
+    ) -> std::io::Result<Connector<P,M,L>>
+    where A: ToSocketAddrs + Debug
+    {
+        Self::new_with_config_retry(addr, CommConfig::default(), max_attempts, base_delay)
+    }
+    /*-----------------------------------------------------
+      same as new_with_retry, using options in config
+      - each attempt waits at most config's connect timeout
+    */
+    pub fn new_with_config_retry<A>(
+        addr: A, config: CommConfig, max_attempts: usize, base_delay: Duration
+    ) -> std::io::Result<Connector<P,M,L>>
+    where A: ToSocketAddrs + Debug
+    {
+        let addrs = resolve(&addr)?;
+        let stream = connect_with_retry::<L>(&addrs, &config, max_attempts, base_delay)?;
+        L::info(&format!("\n--connected to {:?}--", addr));
+        Self::from_stream(addrs, stream, config)
+    }
+    /*-----------------------------------------------------
+      drop current socket and connect again to same addr
+      - snd_queue and rcv_queue are kept, so messages
+        posted but not yet sent go out on the new socket
+        and unread replies remain available
+      - a message the send thread was writing when the
+        old socket failed is lost, as are any replies the
+        peer had not yet sent on the old socket
+    */
+    pub fn reconnect(&self) -> std::io::Result<()> {
+        if self.stop.load(Ordering::Relaxed) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected, "connector is shut down"
+            ));
+        }
+        /*-- shut down old socket, its recv thread will exit --*/
+        if let Some(old) = self.stream.lock().unwrap().take() {
+            let _ = old.shutdown(Shutdown::Both);
+        }
+        let old_recv = {
+            let mut lk = self.link.0.lock().unwrap();
+            lk.writer = None;
+            lk.generation += 1;
+            self.connected.went_down();
+            self.threads.lock().unwrap().recv.take()
         };
-        Ok(me)
+        if let Some(handle) = old_recv {
+            let _ = handle.join();
+        }
+        let stream = connect_timeout(&self.addrs, &self.config)
+            .map_err(|e| std::io::Error::other(
+                format!("reconnect to {:?} failed: {}", self.addrs, e)
+            ))?;
+        L::info(&format!("\n--reconnected to {:?}--", self.addrs));
+        self.config.apply(&stream)?;
+        self.attach(stream)
+    }
+    /*-- apply socket options, then build Connector around stream --*/
+    fn from_stream(
+        addrs: Vec<SocketAddr>, stream: TcpStream, config: CommConfig
+    ) -> std::io::Result<Connector<P,M,L>>
+    {
+        config.apply(&stream)?;
+        Self::build(addrs, stream, config)
+    }
+}
+/*-- constructors for Connectors over TLS, feature "tls" --*/
+#[cfg(feature = "tls")]
+impl<P,M,L> Connector<P,M,L,TlsStream> where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
+    L: Logger + Debug + Copy + Clone + Default
+{
+    /*-----------------------------------------------------
+      connect and start TLS, validating the server's
+      certificate against server_name
+      - fails if the TLS handshake fails
+    */
+    pub fn new_tls<A>(
+        addr: A, server_name: &str, tls: Arc<rustls::ClientConfig>
+    ) -> std::io::Result<Connector<P,M,L,TlsStream>>
+    where A: ToSocketAddrs + Debug
+    {
+        Self::new_tls_with_config(addr, server_name, tls, CommConfig::default())
+    }
+    pub fn new_tls_with_config<A>(
+        addr: A, server_name: &str, tls: Arc<rustls::ClientConfig>, config: CommConfig
+    ) -> std::io::Result<Connector<P,M,L,TlsStream>>
+    where A: ToSocketAddrs + Debug
+    {
+        let (addrs, stream) = open_stream::<L,A>(&addr, &config)?;
+        config.apply(&stream)?;
+        let stream = TlsStream::connect(stream, server_name, tls).map_err(|e| {
+            L::error(&format!("\n--{}--", e));
+            e
+        })?;
+        Self::build(addrs, stream, config)
     }
 }
 /*---------------------------------------------------------
-  Each threadpool thread executes thread_proc
-  - get next TcpStream instance, strm
-  - communicate with connecter using handle_client(strm)
+  dropping a Connector stops its threads
+  - bounded by DROP_TIMEOUT so a stuck peer can't hang
+    the dropping thread
 */
-pub fn thread_proc(bq: &BlockingQueue<TcpStream>, run: &Arc<AtomicBool>) {
-    loop {
-        if !run.load(Ordering::Relaxed) {
-            print!("\n  terminating listener thread");
-            // let _ = std::io::stdout().flush();
-            break;
+impl<P,M,L,T> Drop for Connector<P,M,L,T> where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
+    L: Logger + Debug + Copy + Clone + Default,
+    T: Transport
+{
+    fn drop(&mut self) {
+        self.stop_threads(Some(DROP_TIMEOUT));
+    }
+}
+/*-----------------------------------------------------
+  send token in an AUTH message and wait for the answer
+  - runs before Connector's threads start, so the reply
+    is the first message on the stream
+  - waits at most timeout for the reply, so a peer that
+    never answers AUTH can't hang the connect
+  - shuts the stream down unless the answer is AUTH_OK
+*/
+fn authenticate<P,M,T>(stream: &T, token: &[u8], timeout: Duration) -> std::io::Result<()>
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M> + Rcvr<M>,
+    T: Transport
+{
+    let peer = stream.peer_addr()?;
+    let mut strm = stream.try_clone()?;
+    stream.set_read_timeout(Some(timeout))?;
+    let mut msg = M::new(HEADER_SIZE + token.len());
+    msg.set_type(MessageType::AUTH as u8);
+    msg.set_content_bytes(token);
+    let answer = P::send_message(&msg, &mut strm)
+        .and_then(|_| <P as Rcvr<M>>::recv_message(&mut strm));
+    match answer {
+        Ok(reply) if reply.get_type() == MessageType::AUTH_OK as u8 => Ok(()),
+        rslt => {
+            let _ = stream.shutdown(Shutdown::Both);
+            let (kind, why) = match rslt {
+                Ok(reply) => (
+                    std::io::ErrorKind::PermissionDenied,
+                    format!("authentication with {} failed: answered {}", peer, reply.type_display())
+                ),
+                Err(e) if matches!(
+                    e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) => (
+                    std::io::ErrorKind::TimedOut,
+                    format!("no AUTH reply from {} within {:?}", peer, timeout)
+                ),
+                Err(e) => (
+                    std::io::ErrorKind::PermissionDenied,
+                    format!("authentication with {} failed: {}", peer, e)
+                ),
+            };
+            Err(std::io::Error::new(kind, why))
         }
-        let strm = bq.de_q();
-        let rslt = handle_client(strm);
-        if rslt.is_err() {
-            print!("\n  stream failure in handle_client");
-            break;  // this kills one threadpool thread
+    }
+}
+/*-- join handle, giving up after limit if there is one --*/
+fn join_within(handle: JoinHandle<()>, limit: Option<Duration>) {
+    if let Some(deadline) = limit.and_then(|limit| Instant::now().checked_add(limit)) {
+        while !handle.is_finished() {
+            if Instant::now() >= deadline {
+                return;  // detach
+            }
+            thread::sleep(Duration::from_millis(1));
         }
     }
+    let _ = handle.join();
 }
 /*---------------------------------------------------------
-  Handle client messages:
-  - extract message, msg, from stream 
-  - process using reply_msg = P::process_message(msg)
-  - send back reply_msg
+  connect with exponential backoff between attempts
 */
-pub fn handle_client(strm: TcpStream) -> Result<()> {
-
-    /*-- thread handles client until receiving an END or QUIT message --*/
-    let mut buf_writer = BufWriter::new(strm.try_clone()?);
-    let mut buf_reader = BufReader::new(strm.try_clone()?);
+fn connect_with_retry<L: Logger>(
+    addrs: &[SocketAddr], config: &CommConfig, max_attempts: usize, base_delay: Duration
+) -> Result<TcpStream> {
+    let mut delay = base_delay;
+    let mut attempt = 1;
     loop {
-        L::write("\n  attempting to recv message in client handler");
-        // let _ = std::io::stdout().flush();
-        let rslt:Result<Message> = P::buf_recv_message(&mut buf_reader);
-        L::write("\n  receive successful in client handler");
-        if rslt.is_err() {
-            print!("\n  socket session closed abruptly");
-            // let _ = std::io::stdout().flush();
-            break;
+        match connect_timeout(addrs, config) {
+            Ok(stream) => return Ok(stream),
+            Err(e) if attempt >= max_attempts => {
+                L::error(&format!("\n-- connection to {:?} failed --", addrs));
+                return Err(std::io::Error::other(format!(
+                    "connect to {:?} failed after {} attempts: {}", 
+                    addrs, attempt, e
+                )));
+            }
+            Err(_) => {
+                L::warn(&format!(
+                    "\n  attempt {} to connect to {:?} failed, retrying in {:?}",
+                    attempt, addrs, delay
+                ));
+                thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+                attempt += 1;
+            }
         }
-        let mut msg = rslt.unwrap();
-        if msg.get_type() == MessageType::END as u8 {
-            L::write("\n--listener received END message--");
-            L::write("\n--terminating client handler loop--");           
+    }
+}
+/*---------------------------------------------------------
+  Each threadpool thread executes thread_proc
+  - get next Session, holding accepted TcpStream
+  - communicate with connecter using handle_client(session)
+  - handle_client runs on this thread, spawning none, so
+    the pool's size bounds the sessions served at once,
+    further clients waiting in its queue
+  - P, M, and L are the Listener's processing, message,
+    and log types
+*/
+pub fn thread_proc<P,M,L>(bq: &BlockingQueue<Session<M,NetStream>>, run: &Arc<AtomicBool>) 
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M> + Rcvr<M> + StatefulProcess<M>,
+    L: Logger
+{
+    loop {
+        if !run.load(Ordering::Relaxed) {
+            L::debug("\n  terminating listener thread");
             break;
         }
-        else if msg.get_type() == MessageType::QUIT as u8 {
-            L::write("\n--listener received QUIT message--");
-            L::write("\n--terminating client handler loop--");
+        /*-- None once the pool is stopped, or resized to retire this thread --*/
+        let Some(session) = bq.de_q_while(run) else {
             break;
+        };
+        /*-- counted in the pool's active_count while the session runs --*/
+        let _busy = busy();
+        /*-- a failed session takes down only itself --*/
+        if let Err(e) = handle_client::<P,M,L,NetStream>(session) {
+            L::warn(&format!("\n--{}--", e));
         }
-        P::process_message(&mut msg);
-        let _ = P::buf_send_message(&msg, &mut buf_writer);
-    } 
-    L::write("\n  terminating handler thread");
-    Ok(())
+    }
+}
+/*-- application callback, passed client's address --*/
+pub type Callback = Arc<dyn Fn(SocketAddr) + Send + Sync>;
+
+/*-- connection callbacks registered on Listener --*/
+#[derive(Clone, Default)]
+struct Hooks {
+    on_connect: Option<Callback>,
+    on_disconnect: Option<Callback>,
+}
+impl Debug for Hooks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hooks")
+         .field("on_connect", &self.on_connect.is_some())
+         .field("on_disconnect", &self.on_disconnect.is_some())
+         .finish()
+    }
 }
 /*---------------------------------------------------------
-  Listener<P,L> 
-  - attempts to bind to listening address
-  - blocks on accept via the incoming iterator
+  message handler run by Listener for each client message
+  - sends zero or more replies on the Sender, which are
+    written to the client after the handler returns
+  - shared by all sessions, so state it keeps needs
+    interior mutability, e.g., a Mutex
 */
-#[derive(Debug)]
-pub struct Listener<P,L> 
-where 
-P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + 'static,
-L: Logger + Debug + Copy + Clone + Default
-{
-    p: P,
-    run: Arc<AtomicBool>,  // used to terminate Listener
-    log: L, 
-    num_thrds: u8,
-    addr: &'static str,
+pub type Handler<M> = Arc<dyn Fn(M, &Sender<M>) + Send + Sync>;
+
+/*-- validates a client's AUTH token, passed token and client's address --*/
+pub type Authenticator = Arc<dyn Fn(&[u8], SocketAddr) -> bool + Send + Sync>;
+
+/*---------------------------------------------------------
+  a session's writer, framing messages with P, or for a
+  WebSocket
+  - numbers each message framed with P, next_seq from 1,
+    so a Connector can check none went missing
+*/
+struct SessionWriter<T: Transport> {
+    buf: BufWriter<Metered<T>>,
+    ws: bool,
+    next_seq: u64,
+}
+impl<T: Transport> SessionWriter<T> {
+    fn send<P, M>(&mut self, msg: &M) -> Result<()> 
+    where P: Sndr<M>, M: Msg + Clone + Send + Default
+    {
+        if self.ws {
+            return websocket::write_message(msg, &mut self.buf);
+        }
+        let mut msg = msg.clone();
+        msg.set_seq(self.next_seq);
+        self.next_seq += 1;
+        P::buf_send_message(&msg, &mut self.buf).map(|_| ())
+    }
+}
+impl<T: Transport> std::ops::Deref for SessionWriter<T> {
+    type Target = BufWriter<Metered<T>>;
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+impl<T: Transport> std::ops::DerefMut for SessionWriter<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+/*-- writers of active sessions, used for broadcast --*/
+type SharedWriter<T = NetStream> = Arc<Mutex<SessionWriter<T>>>;
+type Writers<T = NetStream> = Arc<Mutex<HashMap<SocketAddr, SharedWriter<T>>>>;
+/*-- subscribers to each topic, used for publish --*/
+type Topics = Arc<Mutex<HashMap<String, HashSet<SocketAddr>>>>;
+
+/*-- writers of sessions subscribed to topic --*/
+fn subscribers<T: Transport>(topics: &Topics, writers: &Writers<T>, topic: &str) 
+    -> Vec<(SocketAddr, SharedWriter<T>)> 
+{
+    let addrs: Vec<SocketAddr> = match topics.lock().unwrap().get(topic) {
+        Some(subs) => subs.iter().copied().collect(),
+        None => return Vec::new(),
+    };
+    let writers = writers.lock().unwrap();
+    addrs.iter()
+        .filter_map(|a| writers.get(a).map(|w| (*a, Arc::clone(w))))
+        .collect()
+}
+/*-----------------------------------------------------
+  write and flush msg to each target, returning number
+  reached
+  - a target whose write fails is dropped from writers,
+    and its handler ends when its next read fails
+*/
+fn deliver<P,M,L,T>(msg: &M, targets: Vec<(SocketAddr, SharedWriter<T>)>, writers: &Writers<T>) 
+    -> usize
+where P: Sndr<M>, M: Msg + Clone + Send + Default, L: Logger, T: Transport
+{
+    let mut sent = 0;
+    for (addr, writer) in targets {
+        let rslt = match writer.lock() {
+            Ok(mut w) => w.send::<P,M>(msg).and_then(|_| w.flush()),
+            Err(_) => Err(std::io::Error::other("session writer poisoned")),
+        };
+        match rslt {
+            Ok(_) => sent += 1,
+            Err(_) => {
+                L::warn(&format!("\n--{}: delivery failed, dropping client--", addr));
+                writers.lock().unwrap().remove(&addr);
+            }
+        }
+    }
+    sent
+}
+
+/*-- state shared by Listener's accept loop and sessions --*/
+struct Shared<M, T: Transport = NetStream> {
+    active: Arc<AtomicUsize>,
+    run: Arc<AtomicBool>,  // Listener's, cleared by QUIT
+    draining: Arc<AtomicBool>,  // set by stop_graceful
+    writers: Writers<T>,
+    topics: Topics,
+    hooks: Hooks,
+    handler: Option<Handler<M>>,  // None uses session's P
+    authenticator: Option<Authenticator>,
+    file_dir: Option<PathBuf>,  // set by receive_files
+    websocket: bool,
+    handshake: bool,
+    buffer_size: usize,
+    rate_limit: Option<(u32, u32)>,
+    counters: Arc<Counters>,
+}
+impl<M, T: Transport> Debug for Shared<M,T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shared")
+         .field("active", &self.active)
+         .field("hooks", &self.hooks)
+         .finish()
+    }
+}
+/*---------------------------------------------------------
+  Session
+  - accepted stream and its peer address, posted by 
+    Listener to its threadpool
+  - holds one of the Listener's connection slots, given
+    back when the session is dropped at end of its
+    handler loop
+  - on_connect runs when session is created and
+    on_disconnect when it is dropped
+  - greeted once the Listener's protocol preamble has
+    been sent, see module handshake
+*/
+#[derive(Debug)]
+pub struct Session<M, T: Transport = NetStream> {
+    stream: T,
+    peer: SocketAddr,
+    shared: Arc<Shared<M,T>>,
+    greeted: bool,
+}
+impl<M, T: Transport> Session<M,T> {
+    /*-- takes the slot, Listener has checked one is free --*/
+    fn new(stream: T, peer: SocketAddr, shared: &Arc<Shared<M,T>>) -> Session<M,T> {
+        shared.active.fetch_add(1, Ordering::SeqCst);
+        if let Some(f) = &shared.hooks.on_connect {
+            f(peer);
+        }
+        Session { stream, peer, shared: Arc::clone(shared), greeted: false }
+    }
+    /*-- address of connected client --*/
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+}
+impl<M, T: Transport> Drop for Session<M,T> {
+    fn drop(&mut self) {
+        self.shared.writers.lock().unwrap().remove(&self.peer);
+        /*-- unsubscribe from every topic, dropping emptied ones --*/
+        self.shared.topics.lock().unwrap().retain(|_, subs| {
+            subs.remove(&self.peer);
+            !subs.is_empty()
+        });
+        self.shared.active.fetch_sub(1, Ordering::SeqCst);
+        if let Some(f) = &self.shared.hooks.on_disconnect {
+            f(self.peer);
+        }
+    }
+}
+/*---------------------------------------------------------
+  Handle client messages:
+  - log lines are prefixed with client's address
+  - client closing its write side, i.e., EOF at a message
+    boundary, ends the session cleanly: replies are
+    flushed, then the write side is shut down
+  - extract message, msg, from stream 
+  - END ends this session, QUIT ends it and also stops
+    the Listener, as stop does, so it accepts no more
+    clients while other sessions run to their end
+  - with an authenticator, the first message must be
+    an AUTH whose token it accepts, else the client is
+    answered AUTH_FAIL and disconnected
+  - with a rate limit, messages arriving faster than
+    the limit are delayed
+  - SUBSCRIBE and UNSUBSCRIBE add and remove the client
+    from msg's topic, and PUBLISH sends msg, unchanged,
+    to each subscriber of its topic, none of them
+    answered or seen by the handler
+  - with CommConfig::websocket, a client opening with
+    an HTTP upgrade request talks WebSocket frames in
+    place of P's, see module websocket
+  - any other client must open with a matching protocol
+    preamble, see module handshake, else it's closed and
+    the session returns Err, unless handshake is off
+  - with receive_files, FILE_START, FILE_CHUNK, and
+    FILE_END store a file sent by Connector::send_file,
+    see module file_transfer, a partial file being
+    discarded if the session ends
+  - application types, see MessageType::app, are never
+    treated as control, and go to the handler like TEXT
+  - pass msg to Listener's handler, by default one 
+    that replies with msg after the session's own P
+    processes it, P::process(&mut msg)
+  - send back each reply the handler produced, giving
+    replies without an id the id of msg
+  - once the Listener is draining, see stop_graceful,
+    the session ends between messages, after flushing
+    its replies
+  - returns Err, naming the client, if the session fails
+    rather than ending with END, QUIT, EOF, an idle
+    timeout, or a drain
+*/
+pub fn handle_client<P,M,L,T>(session: Session<M,T>) -> Result<()> 
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M> + Rcvr<M> + StatefulProcess<M>,
+    L: Logger,
+    T: Transport
+{
+    let strm = &session.stream;
+    let peer = session.peer;
+    let handler = &session.shared.handler;
+    let authenticator = &session.shared.authenticator;
+    let mut authenticated = authenticator.is_none();
+    let mut limiter = session.shared.rate_limit
+        .map(|(rate, burst)| TokenBucket::new(rate, burst));
+    let mut throttled = false;
+    /*-- per-session processor, used when no handler is set --*/
+    let mut state = P::default();
+    let mut files = session.shared.file_dir.as_deref().map(FileSink::new);
+    let (replies, pending) = channel::<M>();
+
+    let clone = || strm.try_clone().map_err(|e| std::io::Error::new(
+        e.kind(), format!("{}: can't clone stream: {}", peer, e)
+    ));
+    /*-- thread handles client until receiving an END or QUIT message --*/
+    let size = session.shared.buffer_size;
+    let counters = &session.shared.counters;
+    let metered = || clone().map(|s| Metered::new(s, counters));
+    let buf_writer = Arc::new(Mutex::new(SessionWriter {
+        buf: BufWriter::with_capacity(size, metered()?), ws: false, next_seq: 1
+    }));
+    let mut buf_reader = BufReader::with_capacity(size, metered()?);
+    let mut outcome = Ok(());
+    /*-- reachable by broadcast until session is dropped --*/
+    session.shared.writers.lock().unwrap().insert(peer, Arc::clone(&buf_writer));
+    let ws = session.shared.websocket && websocket::wants_upgrade(&mut buf_reader);
+    if ws {
+        let mut response = Vec::<u8>::new();
+        let rslt = websocket::accept(&mut buf_reader, &mut response);
+        let mut writer = buf_writer.lock().unwrap();
+        let _ = writer.write_all(&response).and_then(|_| writer.flush());
+        rslt.map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", peer, e)))?;
+        writer.ws = true;
+        L::info(&format!("\n--{}: websocket session--", peer));
+    }
+    else if session.shared.handshake {
+        /*-- sent even if client's is wrong, so it can report the mismatch --*/
+        if !session.greeted {
+            clone().and_then(|mut s| handshake::send_preamble(&mut s))?;
+        }
+        if let Err(e) = handshake::recv_preamble(&mut buf_reader) {
+            let _ = strm.shutdown(Shutdown::Both);
+            return Err(std::io::Error::new(e.kind(), format!("{}: {}", peer, e)));
+        }
+    }
+    loop {
+        if session.shared.draining.load(Ordering::SeqCst) {
+            L::info(&format!("\n--{}: listener stopping, ending session--", peer));
+            let _ = buf_writer.lock().unwrap().flush();
+            let _ = strm.shutdown(Shutdown::Write);
+            break;
+        }
+        L::debug(&format!("\n  {}: attempting to recv message in client handler", peer));
+        let rslt:Result<M> = if ws {
+            websocket::read_message::<M,_,T>(&mut buf_reader, &buf_writer)
+        } else {
+            P::buf_recv_message(&mut buf_reader).map(|(msg, _)| msg)
+        };
+        if let Err(e) = &rslt {
+            match e.kind() {
+                /*-- read timeout, set from CommConfig::idle_timeout --*/
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                    L::info(&format!("\n--{}: idle timeout, closing session--", peer));
+                    let _ = strm.shutdown(Shutdown::Both);
+                }
+                /*-- half-close, client wants its replies then EOF --*/
+                std::io::ErrorKind::UnexpectedEof => {
+                    L::info(&format!("\n--{}: client closed its end, ending session--", peer));
+                    let _ = buf_writer.lock().unwrap().flush();
+                    let _ = strm.shutdown(Shutdown::Write);
+                }
+                _ => {
+                    outcome = Err(std::io::Error::new(
+                        e.kind(), format!("{}: connection reset: {}", peer, e)
+                    ));
+                }
+            }
+            break;
+        }
+        L::debug(&format!("\n  {}: receive successful in client handler", peer));
+        let msg = rslt.unwrap();
+        if msg.get_type() == MessageType::END as u8 {
+            L::debug(&format!("\n--{}: listener received END message--", peer));
+            L::debug(&format!("\n--{}: terminating client handler loop--", peer));
+            break;
+        }
+        else if msg.get_type() == MessageType::QUIT as u8 {
+            L::info(&format!("\n--{}: listener received QUIT message, stopping--", peer));
+            session.shared.run.store(false, Ordering::Relaxed);
+            L::debug(&format!("\n--{}: terminating client handler loop--", peer));
+            break;
+        }
+        else if msg.get_type() == MessageType::AUTH as u8 || !authenticated {
+            /*-- without an authenticator every AUTH is accepted --*/
+            authenticated = msg.get_type() == MessageType::AUTH as u8 
+                && authenticator.as_ref()
+                    .map(|f| f(msg.get_content_bytes(), peer))
+                    .unwrap_or(true);
+            let mut answer = M::new(HEADER_SIZE);
+            answer.set_type(if authenticated { 
+                MessageType::AUTH_OK as u8 
+            } else { 
+                MessageType::AUTH_FAIL as u8 
+            });
+            answer.set_id(msg.get_id());
+            let mut writer = buf_writer.lock().unwrap();
+            let _ = writer.send::<P,M>(&answer).and_then(|_| writer.flush());
+            if !authenticated {
+                let _ = strm.shutdown(Shutdown::Both);
+                outcome = Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied, 
+                    format!("{}: authentication failed", peer)
+                ));
+                break;
+            }
+            L::debug(&format!("\n  {}: authenticated", peer));
+            continue;
+        }
+        else if msg.get_type() == MessageType::PING as u8 {
+            /*-- heartbeat, answered here and not seen by handler --*/
+            let mut pong = M::new(HEADER_SIZE);
+            pong.set_type(MessageType::PONG as u8);
+            let _ = buf_writer.lock().unwrap().send::<P,M>(&pong);
+            continue;
+        }
+        if let Some(bucket) = limiter.as_mut() {
+            match bucket.take() {
+                Some(wait) => {
+                    if !throttled {
+                        L::warn(&format!("\n--{}: over message rate limit, delaying--", peer));
+                        throttled = true;
+                    }
+                    thread::sleep(wait);
+                }
+                None => throttled = false,
+            }
+        }
+        let id = msg.get_id();
+        let msg_type = msg.get_type();
+        if msg_type == MessageType::SUBSCRIBE as u8 {
+            session.shared.topics.lock().unwrap()
+                .entry(msg.get_topic().to_string()).or_default().insert(peer);
+            continue;
+        }
+        else if msg_type == MessageType::UNSUBSCRIBE as u8 {
+            let mut topics = session.shared.topics.lock().unwrap();
+            if let Some(subs) = topics.get_mut(msg.get_topic()) {
+                subs.remove(&peer);
+                if subs.is_empty() {
+                    topics.remove(msg.get_topic());
+                }
+            }
+            continue;
+        }
+        else if let Some(sink) = files.as_mut().filter(|_| FileSink::is_file_msg(msg_type)) {
+            if let Some(reply) = sink.handle::<M,L>(&msg) {
+                let mut writer = buf_writer.lock().unwrap();
+                let _ = writer.send::<P,M>(&reply).and_then(|_| writer.flush());
+            }
+            continue;
+        }
+        else if msg_type == MessageType::PUBLISH as u8 {
+            let shared = &session.shared;
+            let targets = subscribers(&shared.topics, &shared.writers, msg.get_topic());
+            let n = deliver::<P,M,L,T>(&msg, targets, &shared.writers);
+            L::debug(&format!("\n  {}: published to {} subscribers", peer, n));
+            continue;
+        }
+        Counters::count(&counters.messages_received, 1);
+        match handler {
+            Some(handler) => handler(msg, &replies),
+            None => {
+                let mut msg = msg;
+                state.process(&mut msg);
+                let _ = replies.send(msg);
+            }
+        }
+        let mut writer = buf_writer.lock().unwrap();
+        for mut reply in pending.try_iter() {
+            if reply.get_id() == 0 {
+                reply.set_id(id);
+            }
+            let _ = writer.send::<P,M>(&reply);
+        }
+        if msg_type == MessageType::FLUSH as u8 {
+            let _ = writer.flush();
+        }
+    } 
+    L::debug(&format!("\n  {}: terminating handler thread", peer));
+    outcome
+}
+/*-- wrap accepted stream in TLS if config asks for it --*/
+fn wrap_accepted(stream: TcpStream, config: &CommConfig) -> Result<NetStream> {
+    #[cfg(feature = "tls")]
+    if let Some(tls) = &config.tls_server {
+        return TlsStream::accept(stream, Arc::clone(tls)).map(NetStream::Tls);
+    }
+    let _ = config;
+    Ok(NetStream::Tcp(stream))
+}
+/*-- pool size for a requested nt, 0 meaning one per cpu --*/
+fn pool_threads(nt: usize) -> usize {
+    if nt > 0 {
+        return nt;
+    }
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+/*---------------------------------------------------------
+  Listener<P,L> 
+  - attempts to bind to listening address
+  - blocks on accept via the incoming iterator
+*/
+pub struct Listener<P,L> 
+where 
+P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + 'static,
+L: Logger + Debug + Copy + Clone + Default
+{
+    p: P,
+    run: Arc<AtomicBool>,  // used to terminate Listener
+    log: L, 
+    num_thrds: usize,  // effective, 0 replaced by cpu count
+    local_addr: Option<SocketAddr>,
+    config: CommConfig,
+    active: Arc<AtomicUsize>,  // sessions being handled
+    draining: Arc<AtomicBool>,  // sessions end between messages
+    counters: Arc<Counters>,  // traffic of all sessions
+    writers: Writers,
+    topics: Topics,
+    hooks: Hooks,
+    handler: Option<Handler<M>>,  // None uses P::process
+    authenticator: Option<Authenticator>,  // None accepts every client
+    file_dir: Option<PathBuf>,  // None passes file messages to handler
     // msg_size: usize,
     /*-- ThreadPool instance is aggregated in self.start() --*/
 }
-impl<P,L> Listener<P,L> 
+impl<P,L> Debug for Listener<P,L> 
 where 
-    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + Process<M> + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + 'static,
     L: Logger + Debug + Copy + Clone + Default
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Listener")
+         .field("num_thrds", &self.num_thrds)
+         .field("local_addr", &self.local_addr)
+         .field("config", &self.config)
+         .field("active", &self.active)
+         .field("hooks", &self.hooks)
+         .field("handler", &self.handler.is_some())
+         .field("authenticator", &self.authenticator.is_some())
+         .finish()
+    }
+}
+impl<P,L> Listener<P,L> 
+where 
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + StatefulProcess<M> + 'static,
+    L: Logger + Debug + Copy + Clone + Default + 'static
     {    
-    pub fn new(nt: u8) -> Listener<P,L> {
+    /*-----------------------------------------------------
+      serve clients on a pool of nt threads
+      - nt of 0 uses one thread per cpu, as reported by
+        std::thread::available_parallelism, see pool_size
+    */
+    pub fn new(nt: usize) -> Listener<P,L> {
+        Self::new_with_config(nt, CommConfig::default())
+    }
+    /*-----------------------------------------------------
+      handle at most max_conns clients at a time
+      - further clients wait in the OS accept backlog 
+        until a session ends
+    */
+    pub fn new_bounded(nt: usize, max_conns: usize) -> Listener<P,L> {
+        Self::new_with_config(
+            nt, CommConfig::default().max_connections(max_conns)
+        )
+    }
+    /*-----------------------------------------------------
+      answer each client message with f's reply, see
+      set_reply_handler
+      - f may close over state, e.g., a connection pool
+        or cache, in place of P::process
+    */
+    pub fn with_handler<F>(nt: usize, f: F) -> Listener<P,L> 
+    where F: Fn(M) -> M + Send + Sync + 'static
+    {
+        let mut lsnr = Self::new(nt);
+        lsnr.set_reply_handler(f);
+        lsnr
+    }
+    /*-- config options are applied to each accepted stream --*/
+    pub fn new_with_config(nt: usize, config: CommConfig) -> Listener<P,L> {
         Listener {
               p: P::default(),
               run: Arc::new(AtomicBool::new(true)),
               log: L::default(),
-              num_thrds: nt,
-              addr: "",
+              num_thrds: pool_threads(nt),
+              local_addr: None,
+              config,
+              active: Arc::new(AtomicUsize::new(0)),
+              draining: Arc::new(AtomicBool::new(false)),
+              counters: Arc::new(Counters::default()),
+              writers: Arc::new(Mutex::new(HashMap::new())),
+              topics: Arc::new(Mutex::new(HashMap::new())),
+              hooks: Hooks::default(),
+              handler: None,
+              authenticator: None,
+              file_dir: None,
             //   msg_size: 64,
         }
     }
-    /*-- starts thread wrapping incoming loop which often blocks --*/
-    pub fn start(&mut self, addr: &'static str) -> Result<JoinHandle<()>> 
+    /*-----------------------------------------------------
+      starts thread wrapping accept loop
+      - listening socket is non-blocking, so the loop
+        polls for connections and checks the run flag,
+        letting stop end it without a wakeup connection
+      - once stopped, the thread stops its threadpool and
+        joins every worker before it exits, so joining the
+        returned handle waits for the whole server: each
+        session has ended and its on_disconnect has run
+      - stop leaves open sessions running, so the join
+        waits for their clients, stop_graceful closes them
+      - sessions started by serve run on their own threads,
+        joined through the handles serve returns
+      - closes connections accepted while max_pending
+        others wait for a pool thread, see CommConfig
+    */
+    pub fn start<A>(&mut self, addr: A) -> Result<JoinHandle<()>> 
+    where A: ToSocketAddrs + Debug
     {
-        self.addr = addr;
-        L::write(&format!("\n--starting listener on {:?}--", addr));
-        let rslt = TcpListener::bind(addr);
-        if rslt.is_err() {
-            print!("\n  binding to {:?} failed", addr);
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "listener bind failed"));
-        }
-        let tcpl = rslt.unwrap();
+        let addrs = resolve(&addr)?;
+        L::info(&format!("\n--starting listener on {:?}--", addr));
+        let tcpl = self.config.bind(&addrs).map_err(|e| {
+            L::error(&format!("\n--binding to {:?} failed--", addr));
+            std::io::Error::new(e.kind(), format!("bind to {:?} failed: {}", addr, e))
+        })?;
+        self.local_addr = Some(tcpl.local_addr()?);
+        tcpl.set_nonblocking(true)?;
+        self.run.store(true, Ordering::Relaxed);
+        self.draining.store(false, Ordering::SeqCst);
         let nt = self.num_thrds;
         let run_ref = Arc::clone(&self.run);
+        let config = self.config.clone();
+        let shared = self.shared(Arc::clone(&self.writers), Arc::clone(&self.topics));
 
         /*-- this outer thread prevents appl from blocking waiting for connections --*/
         let handle = std::thread::spawn(move || {
-            let mut tp = ThreadPool::<TcpStream>::new(nt, thread_proc);
-            /*-- loop on incoming iterator which calls accept and so blocks --*/
-            for stream in tcpl.incoming() {
-                if !run_ref.load(Ordering::Relaxed) {
-                    break;
-                }
-                if stream.is_ok() {
-                    tp.post(stream.unwrap());
+            let mut tp = ThreadPool::<Session<M,NetStream>>::new_bounded(
+                nt, config.max_pending, thread_proc::<P,M,L>
+            );
+            tp.on_panic(|msg| {
+                L::error(&format!("\n  recovered from handler panic: {}", msg));
+            });
+            while run_ref.load(Ordering::Relaxed) {
+                /*-- leave connection in backlog until a slot frees --*/
+                if let Some(max) = config.max_connections {
+                    if shared.active.load(Ordering::SeqCst) >= max {
+                        thread::sleep(ACCEPT_POLL);
+                        continue;
+                    }
                 }
-                else {
-                    continue;
+                match tcpl.accept() {
+                    Ok((stream, peer)) => {
+                        /*-- only this thread posts, so the check holds --*/
+                        if tp.queue_depth() >= config.max_pending {
+                            L::warn(&format!(
+                                "\n--{}: connection refused, {} already wait for a pool thread--",
+                                peer, config.max_pending
+                            ));
+                            let _ = stream.shutdown(Shutdown::Both);
+                            continue;
+                        }
+                        /*-- some platforms pass non-blocking on to accepted streams --*/
+                        if stream.set_nonblocking(false).is_err() 
+                            || config.apply(&stream).is_err() 
+                            || stream.set_read_timeout(config.idle_timeout).is_err() {
+                            continue;
+                        }
+                        let Ok(mut stream) = wrap_accepted(stream, &config) else {
+                            continue;
+                        };
+                        /*-- sessions send theirs if TLS, or websocket, must come first --*/
+                        let greeted = shared.handshake && !shared.websocket
+                            && matches!(stream, NetStream::Tcp(_));
+                        if greeted && handshake::send_preamble(&mut stream).is_err() {
+                            continue;
+                        }
+                        L::info(&format!("\n--{}: connection accepted--", peer));
+                        let mut session = Session::new(stream, peer, &shared);
+                        session.greeted = greeted;
+                        tp.post(session);
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(ACCEPT_POLL);
+                    }
+                    Err(_) => continue,
                 }
             }
+            /*-- joins workers, so sessions end before this thread --*/
             tp.stop();
-            L::write("\n--terminating listener thread--");  
+            L::info("\n--terminating listener thread--");  
         });
         Ok(handle)
     }
+    /*-- state shared by sessions --*/
+    fn shared<T: Transport>(&self, writers: Writers<T>, topics: Topics) -> Arc<Shared<M,T>> {
+        Arc::new(Shared {
+            active: Arc::clone(&self.active),
+            run: Arc::clone(&self.run),
+            draining: Arc::clone(&self.draining),
+            writers,
+            topics,
+            hooks: self.hooks.clone(),
+            handler: self.handler.clone(),
+            authenticator: self.authenticator.clone(),
+            file_dir: self.file_dir.clone(),
+            websocket: self.config.websocket,
+            handshake: self.config.handshake,
+            buffer_size: self.config.buffer_size,
+            rate_limit: self.config.rate_limit,
+            counters: Arc::clone(&self.counters),
+        })
+    }
+    /*-----------------------------------------------------
+      handle one client connected over stream, e.g., one
+      end of MemoryTransport::pair(), on a new thread
+      - uses the Listener's handler and callbacks, and
+        counts toward active_connections, but isn't
+        reached by broadcast or publish
+      - returned handle finishes when the session ends
+    */
+    pub fn serve<T: Transport>(&self, stream: T) -> Result<JoinHandle<()>> {
+        let peer = stream.peer_addr()?;
+        let shared = self.shared::<T>(
+            Arc::new(Mutex::new(HashMap::new())), Arc::new(Mutex::new(HashMap::new()))
+        );
+        let session = Session::new(stream, peer, &shared);
+        Ok(std::thread::spawn(move || {
+            let _ = handle_client::<P,M,L,T>(session);
+        }))
+    }
+    /*-----------------------------------------------------
+      address bound by start, None before start
+      - reports port chosen by OS when started on port 0
+    */
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+    /*-- threads serving sessions, the cpu count if made with 0 --*/
+    pub fn pool_size(&self) -> usize {
+        self.num_thrds
+    }
+    /*-----------------------------------------------------
+      register callbacks run as each client is accepted
+      and as its session ends
+      - register before start, later sessions only use
+        callbacks registered then
+      - called on Listener and threadpool threads, so 
+        should be quick
+    */
+    pub fn on_connect<F>(&mut self, f: F) 
+    where F: Fn(SocketAddr) + Send + Sync + 'static
+    {
+        self.hooks.on_connect = Some(Arc::new(f));
+    }
+    pub fn on_disconnect<F>(&mut self, f: F) 
+    where F: Fn(SocketAddr) + Send + Sync + 'static
+    {
+        self.hooks.on_disconnect = Some(Arc::new(f));
+    }
+    /*-----------------------------------------------------
+      replace P::process with handler for 
+      messages other than END and QUIT
+      - handler may send no reply, e.g., for fire and
+        forget messages, or many
+      - register before start
+    */
+    pub fn set_handler<F>(&mut self, f: F) 
+    where F: Fn(M, &Sender<M>) + Send + Sync + 'static
+    {
+        self.handler = Some(Arc::new(f));
+    }
+    /*-----------------------------------------------------
+      set_handler for handlers answering each message
+      with exactly one reply
+      - f's reply is sent as a REPLY, so post_and_wait
+        receives it, and as for set_handler, given the
+        request's correlation id if it has none
+      - f runs on the session's pool thread, so it may
+        block, e.g., on a database query
+    */
+    pub fn set_reply_handler<F>(&mut self, f: F) 
+    where F: Fn(M) -> M + Send + Sync + 'static
+    {
+        self.set_handler(move |msg: M, replies: &Sender<M>| {
+            let mut reply = f(msg);
+            reply.set_type(MessageType::REPLY as u8);
+            let _ = replies.send(reply);
+        });
+    }
+    /*-----------------------------------------------------
+      require each client to open with an AUTH message
+      whose token f accepts
+      - f is passed the token and the client's address
+      - a client failing, or sending anything else first,
+        is answered AUTH_FAIL and disconnected
+      - register before start
+    */
+    pub fn set_authenticator<F>(&mut self, f: F) 
+    where F: Fn(&[u8], SocketAddr) -> bool + Send + Sync + 'static
+    {
+        self.authenticator = Some(Arc::new(f));
+    }
+    /*-----------------------------------------------------
+      store files clients send with send_file in dir
+      - dir must exist; a file already there with the
+        same name is replaced
+      - register before start
+    */
+    pub fn receive_files<D: Into<PathBuf>>(&mut self, dir: D) {
+        self.file_dir = Some(dir.into());
+    }
+    /*-----------------------------------------------------
+      send msg to every client currently being handled
+      - returns number of clients msg was written to
+      - a client whose write fails, e.g., it disconnected
+        during the broadcast, is dropped from the set of
+        broadcast targets and its handler ends when its 
+        next read fails
+    */
+    pub fn broadcast(&self, msg: M) -> usize {
+        let targets: Vec<(SocketAddr, SharedWriter)> = self.writers.lock().unwrap()
+            .iter().map(|(a, w)| (*a, Arc::clone(w))).collect();
+        deliver::<P,M,L,NetStream>(&msg, targets, &self.writers)
+    }
+    /*-----------------------------------------------------
+      send msg to every client subscribed to its topic,
+      as a client's PUBLISH does
+      - returns number of subscribers msg was written to
+    */
+    pub fn publish(&self, msg: M) -> usize {
+        let targets = subscribers(&self.topics, &self.writers, msg.get_topic());
+        deliver::<P,M,L,NetStream>(&msg, targets, &self.writers)
+    }
+    /*-- number of clients currently being handled --*/
+    pub fn active_connections(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+    /*-- traffic totals across all sessions, past and present --*/
+    pub fn stats(&self) -> ServerStats {
+        self.counters.server_snapshot(self.active_connections())
+    }
+    /*-----------------------------------------------------
+      signal accept loop to stop
+      - loop exits within ACCEPT_POLL, then listener 
+        thread, returned by start, terminates
+      - safe to call more than once
+    */
     pub fn stop(&mut self) {
         self.run.store(false, Ordering::Relaxed);
-        let conn = Connector::<P,M,L>::new(self.addr).unwrap();
-        let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE + 1);
-        msg.set_type(MessageType::QUIT as u8);
-        conn.post_message(msg);
+    }
+    /*-----------------------------------------------------
+      stop, then let sessions finish and wait at most
+      timeout for them
+      - each session ends after the message it's handling,
+        flushing its replies, and an idle session, 
+        waiting to read, ends at once
+      - sessions still active at timeout have their
+        sockets shut down
+      - returns true if every session ended in time
+      - sessions started by serve end between messages,
+        but aren't woken or forced closed
+    */
+    pub fn stop_graceful(&mut self, timeout: Duration) -> bool {
+        self.stop();
+        self.draining.store(true, Ordering::SeqCst);
+        let close = |how: Shutdown| {
+            let writers: Vec<SharedWriter> = self.writers.lock().unwrap()
+                .values().cloned().collect();
+            for writer in writers {
+                if let Ok(w) = writer.lock() {
+                    let _ = w.get_ref().shutdown(how);
+                }
+            }
+        };
+        /*-- wakes sessions blocked reading, a reader sees EOF --*/
+        close(Shutdown::Read);
+        let deadline = Instant::now() + timeout;
+        while self.active_connections() > 0 {
+            if Instant::now() >= deadline {
+                L::warn(&format!(
+                    "\n--{} sessions still active, closing them--", self.active_connections()
+                ));
+                close(Shutdown::Both);
+                return false;
+            }
+            thread::sleep(ACCEPT_POLL);
+        }
+        true
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+    /*-- find a port with nothing listening on it --*/
+    fn unused_addr() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+    }
+    fn wait_until<F: Fn() -> bool>(pred: F) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !pred() {
+            if Instant::now() > deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+    /*-- raw client's protocol preamble exchange, see module handshake --*/
+    fn hello(strm: &mut TcpStream) {
+        handshake::send_preamble(strm).unwrap();
+        handshake::recv_preamble(strm).unwrap();
+    }
+    /*-- for Connectors whose peer end is read and written by the test --*/
+    fn raw_peer() -> CommConfig {
+        CommConfig::default().handshake(false)
+    }
+    #[test]
+    fn connect_timeout_fails_promptly() {
+        let addr = unused_addr();
+        let start = Instant::now();
+        let rslt = Connector::<P,M,L>::new_with_timeout(
+            addr, Duration::from_millis(200)
+        );
+        let err = rslt.err().unwrap();
+        assert!(err.to_string().contains(&addr.to_string()));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+    /*-- Linux routes all of 127/8 to loopback --*/
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn connector_connects_from_configured_local_addr() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let source: SocketAddr = "127.0.0.2:0".parse().unwrap();
+        let config = raw_peer().local_addr(source);
+        let conn = Connector::<P,M,L>::new_with_config(tcpl.local_addr().unwrap(), config).unwrap();
+        let (_server, peer) = tcpl.accept().unwrap();
+        assert_eq!(peer.ip(), source.ip());
+        assert_eq!(conn.local_addr(), Some(peer));
+        assert_eq!(conn.peer_addr(), tcpl.local_addr().ok());
+        conn.reconnect().unwrap();
+        let (_server, peer) = tcpl.accept().unwrap();
+        assert_eq!(peer.ip(), source.ip());
+        assert_eq!(conn.local_addr(), Some(peer));
+
+        /*-- TEST-NET address isn't this host's, so bind fails --*/
+        let foreign = CommConfig::default().local_addr("192.0.2.1:0".parse().unwrap());
+        let err = Connector::<P,M,L>::new_with_config(tcpl.local_addr().unwrap(), foreign)
+            .err().unwrap();
+        assert!(err.to_string().contains("can't bind local address 192.0.2.1:0"));
+    }
+    #[test]
+    fn start_reports_bind_failure() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = taken.local_addr().unwrap();
+        let mut lsnr = Listener::<P,L>::new(1);
+        assert_eq!(lsnr.pool_size(), 1);
+        let err = lsnr.start(addr).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+        assert!(err.to_string().contains(&addr.to_string()));
+    }
+    #[test]
+    fn connections_past_max_pending_are_refused() {
+        let config = CommConfig::default().max_pending(1);
+        let mut lsnr = Listener::<P,L>::new_with_config(1, config);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let echo = |conn: &Connector<P,M,L>, text: &str| {
+            let mut msg = Message::create_msg_str_fit(text);
+            msg.set_type(MessageType::FLUSH as u8);
+            conn.post_and_wait(msg, Duration::from_secs(5))
+        };
+        /*-- first holds the only worker, second waits, third is one too many --*/
+        let busy = Connector::<P,M,L>::new(addr).unwrap();
+        assert!(echo(&busy, "busy").is_some());
+        let waiting = Connector::<P,M,L>::new(addr).unwrap();
+        assert!(wait_until(|| lsnr.active_connections() == 2));
+        let err = Connector::<P,M,L>::new(addr).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(lsnr.active_connections(), 2);
+
+        /*-- waiting client is served once the worker frees --*/
+        busy.shut_down();
+        assert_eq!(echo(&waiting, "served").unwrap().get_content_str().unwrap(), "served");
+        waiting.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn shutdown_write_keeps_receiving_replies() {
+        let mut lsnr = Listener::<P,L>::new(1);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        /*-- TEXT replies wait in the Listener's buffer until it sees EOF --*/
+        for i in 0..5 {
+            let mut msg = Message::create_msg_str_fit(&format!("msg {}", i));
+            msg.set_type(MessageType::TEXT as u8);
+            conn.post_message(msg).unwrap();
+        }
+        conn.shutdown_write().unwrap();
+        assert!(matches!(
+            conn.post_message(Message::create_msg_str_fit("late")), Err(CommError::NotConnected)
+        ));
+        for i in 0..5 {
+            let reply = conn.get_message_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(reply.get_type(), MessageType::REPLY as u8);
+            assert_eq!(reply.get_content_str().unwrap(), format!("msg {}", i));
+        }
+        /*-- Listener closed its end once done --*/
+        assert!(wait_until(|| !conn.is_connected()));
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn zero_threads_means_one_per_cpu() {
+        let cpus = thread::available_parallelism().unwrap().get();
+        let mut lsnr = Listener::<P,L>::new(0);
+        assert_eq!(lsnr.pool_size(), cpus);
+        assert_eq!(Listener::<P,L>::new(300).pool_size(), 300);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        let mut msg = Message::create_msg_str_fit("served");
+        msg.set_type(MessageType::FLUSH as u8);
+        assert!(conn.post_and_wait(msg, Duration::from_secs(5)).is_some());
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn retry_backs_off_then_fails() {
+        let addr = unused_addr();
+        let start = Instant::now();
+        let rslt = Connector::<P,M,L>::new_with_retry(
+            addr, 3, Duration::from_millis(20)
+        );
+        assert!(rslt.is_err());
+        /*-- waits 20 ms then 40 ms between the three attempts --*/
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
+    #[test]
+    fn retry_with_config_keeps_its_options() {
+        /*-- a server that never greets needs handshake(false) --*/
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = raw_peer().nodelay(false);
+        let conn = Connector::<P,M,L>::new_with_config_retry(
+            tcpl.local_addr().unwrap(), config, 3, Duration::from_millis(10)
+        ).unwrap();
+        let (_strm, _) = tcpl.accept().unwrap();
+        assert!(conn.is_connected());
+        let nodelay = conn.stream.lock().unwrap().as_ref().unwrap().nodelay().unwrap();
+        assert!(!nodelay);
+        conn.shut_down();
+    }
+    #[test]
+    fn reconnect_keeps_queued_messages() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcpl.local_addr().unwrap();
+        /*-- new_with_retry uses the default config, so server answers the handshake --*/
+        let (accepted, streams) = channel::<TcpStream>();
+        thread::spawn(move || {
+            for strm in tcpl.incoming() {
+                let mut strm = strm.unwrap();
+                hello(&mut strm);
+                if accepted.send(strm).is_err() {
+                    break;
+                }
+            }
+        });
+        let conn = Connector::<P,M,L>::new_with_retry(
+            addr, 3, Duration::from_millis(10)
+        ).unwrap();
+        assert!(conn.is_connected());
+
+        /*-- server drops first session, connector notices --*/
+        let first = streams.recv().unwrap();
+        drop(first);
+        assert!(wait_until(|| !conn.is_connected()));
+
+        conn.reconnect().unwrap();
+        assert!(conn.is_connected());
+        let second = streams.recv().unwrap();
+        let mut msg = Message::create_msg_str_fit("after reconnect");
+        msg.set_type(MessageType::FLUSH as u8);
+        conn.post_message(msg).unwrap();
+        let mut reader = BufReader::new(second);
+        let (rcvd, _): (Message, usize) = P::buf_recv_message(&mut reader).unwrap();
+        assert_eq!(rcvd.get_content_str().unwrap(), "after reconnect");
+    }
+    #[test]
+    fn events_follow_connection_changes_in_order() {
+        use ConnectionEvent::*;
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new_with_config(tcpl.local_addr().unwrap(), raw_peer()).unwrap();
+        let events = conn.events();
+        let next = || events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(next(), Connected);
+
+        let (first, _) = tcpl.accept().unwrap();
+        drop(first);
+        assert_eq!(next(), Disconnected);
+        conn.reconnect().unwrap();
+        assert_eq!(next(), Reconnected);
+        /*-- a late subscriber starts from the current state --*/
+        let late = conn.events();
+        assert_eq!(late.try_recv().unwrap(), Connected);
+
+        conn.shut_down();
+        assert_eq!(next(), Disconnected);
+        assert!(events.recv().is_err());
+        assert_eq!(late.try_recv().unwrap(), Disconnected);
+        assert!(conn.events().recv().is_err());
+    }
+    #[test]
+    fn on_message_pushes_replies_in_place_of_queue() {
+        let mut lsnr = Listener::<P,L>::new(2);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        let (tx, rx) = channel::<M>();
+        let tx = Mutex::new(tx);
+        conn.on_message(move |msg| {
+            let _ = tx.lock().unwrap().send(msg);
+        });
+        let flush_msg = |text: &str| {
+            let mut msg = Message::create_msg_str_fit(text);
+            msg.set_type(MessageType::FLUSH as u8);
+            msg
+        };
+        let dur = Duration::from_secs(5);
+        conn.post_message(flush_msg("pushed")).unwrap();
+        assert_eq!(rx.recv_timeout(dur).unwrap().get_content_str().unwrap(), "pushed");
+        /*-- awaited replies still reach their caller --*/
+        let reply = conn.post_and_wait(flush_msg("awaited"), dur).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "awaited");
+        assert!(!conn.has_msg());
+
+        conn.clear_on_message();
+        conn.post_message(flush_msg("queued")).unwrap();
+        assert_eq!(conn.get_message_timeout(dur).unwrap().get_content_str().unwrap(), "queued");
+        assert!(rx.try_recv().is_err());
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn verify_sequence_reports_gaps() {
+        use ConnectionEvent::*;
+        let config = || CommConfig::default().verify_sequence(true);
+        /*-- a Listener numbers replies, so none are reported --*/
+        let mut lsnr = Listener::<P,L>::new(2);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new_with_config(lsnr.local_addr().unwrap(), config()).unwrap();
+        let events = conn.events();
+        for _ in 0..3 {
+            let mut msg = Message::create_msg_str_fit("counted");
+            msg.set_type(MessageType::FLUSH as u8);
+            conn.post_message(msg).unwrap();
+        }
+        for seq in 1..=3 {
+            let rcvd = conn.get_message_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(rcvd.get_seq(), seq);
+        }
+        assert_eq!(events.try_recv().unwrap(), Connected);
+        assert!(events.try_recv().is_err());
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+
+        /*-- a repeat and a skip are each reported --*/
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new_with_config(
+            tcpl.local_addr().unwrap(), config().handshake(false)
+        ).unwrap();
+        let events = conn.events();
+        let (mut server, _) = tcpl.accept().unwrap();
+        let mut wire = Vec::<u8>::new();
+        for seq in [1, 2, 2, 5, 6].iter() {
+            let mut msg = Message::create_msg_str_fit("numbered");
+            msg.set_type(MessageType::TEXT as u8);
+            msg.set_seq(*seq);
+            write_message::<M, FrameCodec, NoCompression, _>(&msg, &mut wire).unwrap();
+        }
+        server.write_all(&wire).unwrap();
+        for _ in 0..5 {
+            assert!(conn.get_message_timeout(Duration::from_secs(5)).is_some());
+        }
+        let next = || events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(next(), Connected);
+        assert_eq!(next(), SequenceGap { expected: 3, got: 2 });
+        assert_eq!(next(), SequenceGap { expected: 3, got: 5 });
+        assert!(events.try_recv().is_err());
+        conn.shut_down();
+    }
+    #[test]
+    fn recv_poll_neither_drops_quiet_links_nor_splits_messages() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = raw_peer().recv_poll(Some(Duration::from_millis(10)));
+        let conn = Connector::<P,M,L>::new_with_config(tcpl.local_addr().unwrap(), config).unwrap();
+        let (mut server, _) = tcpl.accept().unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(conn.is_connected());
+
+        /*-- pause mid-message for several timeouts --*/
+        let mut frame = Vec::<u8>::new();
+        let mut msg = Message::create_msg_str_fit("sent in two parts");
+        msg.set_type(MessageType::TEXT as u8);
+        FrameCodec::<MAX_FRAME_LEN>::encode(&msg, &mut frame).unwrap();
+        server.write_all(&frame[..7]).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        server.write_all(&frame[7..]).unwrap();
+        let rcvd = conn.get_message_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(rcvd.get_content_str().unwrap(), "sent in two parts");
+        assert!(conn.is_connected());
+
+        /*-- stop flag alone ends recv thread, socket still open --*/
+        conn.stop.store(true, Ordering::Relaxed);
+        let recv_done = || conn.threads.lock().unwrap().recv.as_ref()
+            .map(|h| h.is_finished()).unwrap_or(true);
+        assert!(wait_until(recv_done));
+        conn.stop.store(false, Ordering::Relaxed);
+        conn.shut_down();
+    }
+    #[test]
+    fn shut_down_sends_end_and_stops_threads() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcpl.local_addr().unwrap();
+        let conn = Connector::<P,M,L>::new_with_config(addr, raw_peer()).unwrap();
+        /*-- peer stays open, so shut_down must close the socket itself --*/
+        let (strm, _) = tcpl.accept().unwrap();
+        conn.shut_down();
+        assert!(!conn.is_connected());
+        let mut reader = BufReader::new(strm);
+        let (rcvd, _): (Message, usize) = P::buf_recv_message(&mut reader).unwrap();
+        assert_eq!(rcvd.get_type(), MessageType::END as u8);
+
+        let rslt = conn.post_message(Message::create_msg_str_fit("too late"));
+        assert!(matches!(rslt, Err(CommError::NotConnected)));
+        assert_eq!(conn.snd_queue.len(), 0);  // discarded, not queued
+        assert!(conn.reconnect().is_err());
+        conn.shut_down();  // second call is harmless
+    }
+    #[test]
+    fn join_waits_for_threads() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new_with_config(tcpl.local_addr().unwrap(), raw_peer()).unwrap();
+        let (_strm, _) = tcpl.accept().unwrap();
+        assert!(!conn.is_finished());
+        conn.join();
+    }
+    #[test]
+    fn drop_stops_threads() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new_with_config(tcpl.local_addr().unwrap(), raw_peer()).unwrap();
+        let (strm, _) = tcpl.accept().unwrap();
+        let start = Instant::now();
+        drop(conn);
+        assert!(start.elapsed() < DROP_TIMEOUT);
+        let mut reader = BufReader::new(strm);
+        let (rcvd, _): (Message, usize) = P::buf_recv_message(&mut reader).unwrap();
+        assert_eq!(rcvd.get_type(), MessageType::END as u8);
+    }
+    #[test]
+    fn drop_returns_while_peer_stops_reading() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new_with_config(tcpl.local_addr().unwrap(), raw_peer()).unwrap();
+        let (_strm, _) = tcpl.accept().unwrap();
+        /*-- send thread blocks writing, holding the link lock --*/
+        let mut big = Message::create_msg_bytes_fit(&vec![1u8; 8 * 1024 * 1024]);
+        big.set_type(MessageType::TEXT as u8);
+        conn.post_message(big).unwrap();
+        assert!(!conn.flush(Duration::from_millis(100)));
+        let start = Instant::now();
+        drop(conn);
+        /*-- the send thread gets DROP_TIMEOUT, then the socket is shut down --*/
+        assert!(start.elapsed() < DROP_TIMEOUT + Duration::from_millis(500));
+    }
+    #[test]
+    fn flush_waits_until_posted_messages_are_written() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new_with_config(tcpl.local_addr().unwrap(), raw_peer()).unwrap();
+        let (strm, _) = tcpl.accept().unwrap();
+        assert!(conn.flush(Duration::from_millis(10)));
+        /*-- peer isn't reading, so socket buffers fill --*/
+        let mut big = Message::create_msg_bytes_fit(&vec![1u8; 8 * 1024 * 1024]);
+        big.set_type(MessageType::TEXT as u8);
+        conn.post_message(big).unwrap();
+        conn.post_message(Message::create_msg_str_fit("last")).unwrap();
+        assert!(!conn.flush(Duration::from_millis(100)));
+
+        let reader = thread::spawn(move || {
+            let mut reader = BufReader::new(strm);
+            (0..2).map(|_| P::buf_recv_message(&mut reader).unwrap().0).last().unwrap()
+        });
+        assert!(conn.flush(Duration::from_secs(5)));
+        let last: Message = reader.join().unwrap();
+        assert_eq!(last.get_content_str().unwrap(), "last");
+    }
+    #[test]
+    fn oversized_frame_closes_session() {
+        let mut lsnr = Listener::<P,L>::new(1);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let mut strm = TcpStream::connect(lsnr.local_addr().unwrap()).unwrap();
+        strm.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        hello(&mut strm);
+        /*-- claims 32 MB, past MAX_MESSAGE_SIZE --*/
+        strm.write_all(&((32 * 1024 * 1024) as u32).to_be_bytes()).unwrap();
+        strm.write_all(&[1u8; 64]).unwrap();
+        let mut buf = [0u8; 16];
+        match strm.read(&mut buf) {
+            Ok(n) => assert_eq!(n, 0),
+            Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset),
+        }
+        assert!(wait_until(|| lsnr.active_connections() == 0));
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn listener_serves_ipv6_and_dual_stack_clients() {
+        let dur = Duration::from_secs(5);
+        let echo = |addr: SocketAddr| {
+            let conn = Connector::<P,M,L>::new(addr).unwrap();
+            let msg = Message::builder().msg_type(MessageType::FLUSH).body("v6").build().unwrap();
+            let reply = conn.post_and_wait(msg, dur).unwrap();
+            assert_eq!(reply.get_content_str().unwrap(), "v6");
+            conn.shut_down();
+        };
+        let mut lsnr = Listener::<P,L>::new(1);
+        let handle = lsnr.start("[::1]:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        assert!(addr.is_ipv6());
+        echo(addr);
+        lsnr.stop();
+        let _ = handle.join();
+
+        /*-- one socket on [::] takes IPv4 clients too --*/
+        let config = CommConfig::default().ipv6_only(false);
+        let mut lsnr = Listener::<P,L>::new_with_config(1, config);
+        let handle = lsnr.start("[::]:0").unwrap();
+        let port = lsnr.local_addr().unwrap().port();
+        echo(SocketAddr::from(([127, 0, 0, 1], port)));
+        echo(SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, port)));
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn text_lines_work_like_netcat() {
+        let config = CommConfig::default().handshake(false);
+        let mut lsnr = Listener::<CommProcessing<L, TextLineCodec>, L>::new_with_config(1, config);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let strm = TcpStream::connect(lsnr.local_addr().unwrap()).unwrap();
+        strm.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut lines = BufReader::new(strm.try_clone().unwrap()).lines();
+        for typed in ["hello\r\n", "again\n"].iter() {
+            (&strm).write_all(typed.as_bytes()).unwrap();
+            assert_eq!(lines.next().unwrap().unwrap(), typed.trim_end());
+        }
+        lsnr.stop();
+        let _ = strm.shutdown(Shutdown::Both);
+        let _ = handle.join();
+    }
+    #[test]
+    fn bounded_send_queue() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new_with_config(
+            tcpl.local_addr().unwrap(), raw_peer().send_capacity(4)
+        ).unwrap();
+        assert_eq!(conn.send_capacity(), Some(4));
+        let (strm, _) = tcpl.accept().unwrap();
+        for i in 0..16 {
+            let mut msg = Message::create_msg_str_fit(&format!("msg #{}", i));
+            msg.set_type(MessageType::FLUSH as u8);
+            conn.post_message(msg).unwrap();
+            assert!(conn.snd_queue.len() <= 4);
+        }
+        let mut reader = BufReader::new(strm);
+        for i in 0..16 {
+            let (rcvd, _): (Message, usize) = P::buf_recv_message(&mut reader).unwrap();
+            assert_eq!(rcvd.get_content_str().unwrap(), format!("msg #{}", i));
+        }
+    }
+    #[test]
+    fn nodelay_is_configurable() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcpl.local_addr().unwrap();
+        let conn = Connector::<P,M,L>::new_with_config(addr, raw_peer()).unwrap();
+        let nodelay = |c: &Connector<P,M,L>| {
+            c.stream.lock().unwrap().as_ref().unwrap().nodelay().unwrap()
+        };
+        assert!(nodelay(&conn));
+        let bulk = Connector::<P,M,L>::new_with_config(
+            addr, raw_peer().nodelay(false)
+        ).unwrap();
+        assert!(!nodelay(&bulk));
+    }
+    #[test]
+    fn small_round_trips_avoid_nagle_delay() {
+        let mut lsnr = Listener::<P,L>::new(2);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        let n = 20;
+        let start = Instant::now();
+        for _ in 0..n {
+            let mut msg = Message::create_msg_str_fit("x");
+            msg.set_type(MessageType::FLUSH as u8);
+            conn.post_message(msg).unwrap();
+            let _ = conn.get_message().unwrap();
+        }
+        /*-- Nagle plus delayed ack costs ~40 ms per round trip --*/
+        assert!(start.elapsed() < Duration::from_millis(20 * n));
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    /*-- custom processing type, replies in upper case --*/
+    #[derive(Debug, Copy, Clone, Default)]
+    struct Upper;
+    impl Sndr<M> for Upper {
+        fn send_message<T: Transport>(msg: &M, stream: &mut T) -> Result<()> {
+            P::send_message(msg, stream)
+        }
+        fn buf_send_message<T: Transport>(msg: &M, stream: &mut BufWriter<T>) -> Result<usize> {
+            P::buf_send_message(msg, stream)
+        }
+    }
+    impl Rcvr<M> for Upper {
+        fn recv_message<T: Transport>(stream: &mut T) -> Result<M> {
+            P::recv_message(stream)
+        }
+        fn buf_recv_message<T: Transport>(stream: &mut BufReader<T>) -> Result<(M, usize)> {
+            P::buf_recv_message(stream)
+        }
+    }
+    impl Process<M> for Upper {
+        fn process_message(msg: &mut M) {
+            let upper = msg.get_content_str().unwrap().to_uppercase();
+            let msg_type = msg.get_type();
+            *msg = Message::create_msg_str_fit(&upper);
+            msg.set_type(msg_type);
+        }
+    }
+    #[test]
+    fn listener_uses_custom_processing() {
+        let mut lsnr = Listener::<Upper,L>::new(2);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        let mut msg = Message::create_msg_str_fit("shout");
+        msg.set_type(MessageType::FLUSH as u8);
+        conn.post_message(msg).unwrap();
+        assert_eq!(conn.get_message().unwrap().get_content_str().unwrap(), "SHOUT");
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    /*-- stateful processing type, numbers each session's replies --*/
+    #[derive(Debug, Copy, Clone, Default)]
+    struct Counter {
+        count: u64,
+    }
+    impl Sndr<M> for Counter {
+        fn send_message<T: Transport>(msg: &M, stream: &mut T) -> Result<()> {
+            P::send_message(msg, stream)
+        }
+        fn buf_send_message<T: Transport>(msg: &M, stream: &mut BufWriter<T>) -> Result<usize> {
+            P::buf_send_message(msg, stream)
+        }
+    }
+    impl Rcvr<M> for Counter {
+        fn recv_message<T: Transport>(stream: &mut T) -> Result<M> {
+            P::recv_message(stream)
+        }
+        fn buf_recv_message<T: Transport>(stream: &mut BufReader<T>) -> Result<(M, usize)> {
+            P::buf_recv_message(stream)
+        }
+    }
+    impl StatefulProcess<M> for Counter {
+        fn process(&mut self, msg: &mut M) {
+            self.count += 1;
+            msg.set_content_str(&self.count.to_string());
+            msg.set_type(MessageType::REPLY as u8);
+        }
+    }
+    #[test]
+    fn stateful_processing_is_per_session() {
+        let mut lsnr = Listener::<Counter,L>::new(2);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let first = Connector::<P,M,L>::new(addr).unwrap();
+        let second = Connector::<P,M,L>::new(addr).unwrap();
+        let dur = Duration::from_secs(5);
+        let mut msg = Message::create_msg_str_fit("count");
+        msg.set_type(MessageType::FLUSH as u8);
+        for n in 1..=3 {
+            let reply = first.post_and_wait(msg.clone(), dur).unwrap();
+            assert_eq!(reply.get_content_str().unwrap(), n.to_string());
+        }
+        let reply = second.post_and_wait(msg, dur).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "1");
+        first.shut_down();
+        second.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn listener_stop_is_prompt_and_repeatable() {
+        let mut lsnr = Listener::<P,L>::new(2);
+        assert!(lsnr.local_addr().is_none());
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        assert_ne!(lsnr.local_addr().unwrap().port(), 0);
+        lsnr.stop();
+        lsnr.stop();
+        let start = Instant::now();
+        let _ = handle.join();
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+    #[test]
+    fn max_connections_holds_extra_clients() {
+        let mut lsnr = Listener::<P,L>::new_bounded(2, 1);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let post = |conn: &Connector<P,M,L>, s: &str| {
+            let mut msg = Message::create_msg_str_fit(s);
+            msg.set_type(MessageType::FLUSH as u8);
+            conn.post_message(msg).unwrap();
+        };
+        let first = Connector::<P,M,L>::new(addr).unwrap();
+        post(&first, "first");
+        assert!(first.get_message_timeout(Duration::from_secs(5)).is_some());
+        assert_eq!(lsnr.active_connections(), 1);
+
+        /*-- second client waits, unaccepted, for its handshake --*/
+        let second = thread::spawn(move || Connector::<P,M,L>::new(addr).unwrap());
+        thread::sleep(Duration::from_millis(200));
+        assert!(!second.is_finished());
+
+        first.shut_down();
+        let second = second.join().unwrap();
+        post(&second, "second");
+        let reply = second.get_message_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "second");
+        second.shut_down();
+        assert!(wait_until(|| lsnr.active_connections() == 0));
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn pool_size_bounds_concurrent_sessions() {
+        let mut lsnr = Listener::<P,L>::new(1);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let flush = || {
+            let mut msg = Message::create_msg_str_fit("anyone there?");
+            msg.set_type(MessageType::FLUSH as u8);
+            msg
+        };
+        let first = Connector::<P,M,L>::new(addr).unwrap();
+        first.post_message(flush()).unwrap();
+        assert!(first.get_message_timeout(Duration::from_secs(5)).is_some());
+
+        /*-- one worker, busy with first, so second waits --*/
+        let second = Connector::<P,M,L>::new(addr).unwrap();
+        second.post_message(flush()).unwrap();
+        assert!(second.get_message_timeout(Duration::from_millis(200)).is_none());
+        first.shut_down();
+        assert!(second.get_message_timeout(Duration::from_secs(5)).is_some());
+        second.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn joining_start_handle_waits_for_sessions() {
+        let ended = Arc::new(AtomicBool::new(false));
+        let mut lsnr = Listener::<P,L>::new(2);
+        let on_disc = Arc::clone(&ended);
+        lsnr.on_disconnect(move |_| {
+            thread::sleep(Duration::from_millis(50));
+            on_disc.store(true, Ordering::SeqCst);
+        });
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        assert!(wait_until(|| lsnr.active_connections() == 1));
+        lsnr.stop();
+        let client = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            conn.shut_down();
+        });
+        let _ = handle.join();
+        assert!(ended.load(Ordering::SeqCst));
+        assert_eq!(lsnr.active_connections(), 0);
+        let _ = client.join();
+    }
+    #[test]
+    fn connect_callbacks_see_peer_address() {
+        let seen = Arc::new(Mutex::new(Vec::<(bool, SocketAddr)>::new()));
+        let mut lsnr = Listener::<P,L>::new(2);
+        let on_conn = Arc::clone(&seen);
+        lsnr.on_connect(move |a| on_conn.lock().unwrap().push((true, a)));
+        let on_disc = Arc::clone(&seen);
+        lsnr.on_disconnect(move |a| on_disc.lock().unwrap().push((false, a)));
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        assert!(wait_until(|| seen.lock().unwrap().len() == 1));
+        conn.shut_down();
+        assert!(wait_until(|| seen.lock().unwrap().len() == 2));
+        let seen = seen.lock().unwrap();
+        assert!(seen[0].0 && !seen[1].0);
+        assert_eq!(seen[0].1, seen[1].1);
+        assert!(seen[0].1.ip().is_loopback());
+        assert_ne!(seen[0].1.port(), addr.port());
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn handler_sends_any_number_of_replies() {
+        let mut lsnr = Listener::<P,L>::new(2);
+        lsnr.set_handler(|msg: M, replies: &Sender<M>| {
+            let text = msg.get_content_str().unwrap();
+            let count = if text == "twice" { 2 } else { 0 };
+            for i in 0..count {
+                let mut reply = Message::create_msg_str_fit(&format!("{} {}", text, i));
+                reply.set_type(MessageType::FLUSH as u8);
+                let _ = replies.send(reply);
+            }
+        });
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        for text in ["quiet", "twice"] {
+            let mut msg = Message::create_msg_str_fit(text);
+            msg.set_type(MessageType::FLUSH as u8);
+            conn.post_message(msg).unwrap();
+        }
+        let dur = Duration::from_secs(5);
+        let first = conn.get_message_timeout(dur).unwrap();
+        assert_eq!(first.get_content_str().unwrap(), "twice 0");
+        let second = conn.get_message_timeout(dur).unwrap();
+        assert_eq!(second.get_content_str().unwrap(), "twice 1");
+        assert!(conn.get_message_timeout(Duration::from_millis(100)).is_none());
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn reply_handler_closes_over_state() {
+        let seen = Arc::new(Mutex::new(HashMap::<String, u32>::new()));
+        let cache = Arc::clone(&seen);
+        let mut lsnr = Listener::<P,L>::with_handler(2, move |msg: M| {
+            let key = msg.get_content_str().unwrap().to_string();
+            let mut cache = cache.lock().unwrap();
+            let count = cache.entry(key.clone()).or_insert(0);
+            *count += 1;
+            let mut reply = Message::create_msg_str_fit(&format!("{} {}", key, count));
+            reply.set_type(MessageType::FLUSH as u8);
+            reply
+        });
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        let dur = Duration::from_secs(5);
+        for expected in ["key 1", "key 2"] {
+            let mut msg = Message::create_msg_str_fit("key");
+            msg.set_type(MessageType::FLUSH as u8);
+            let reply = conn.post_and_wait(msg, dur).unwrap();
+            assert_eq!(reply.get_content_str().unwrap(), expected);
+        }
+        assert_eq!(seen.lock().unwrap()["key"], 2);
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn memory_transport_session_without_sockets() {
+        let mut lsnr = Listener::<P,L>::new(1);
+        lsnr.set_handler(|msg: M, replies: &Sender<M>| {
+            let text = msg.get_content_str().unwrap().to_uppercase();
+            let mut reply = Message::create_msg_str_fit(&text);
+            reply.set_type(MessageType::REPLY as u8);
+            let _ = replies.send(reply);
+        });
+        let (client, server) = MemoryTransport::pair();
+        let session = lsnr.serve(server).unwrap();
+        let conn = Connector::<P,M,L,MemoryTransport>::from_transport(client).unwrap();
+        assert!(wait_until(|| lsnr.active_connections() == 1));
+        let mut msg = Message::create_msg_str_fit("in memory");
+        msg.set_type(MessageType::FLUSH as u8);
+        let reply = conn.post_and_wait(msg, Duration::from_secs(5)).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "IN MEMORY");
+        conn.shut_down();
+        session.join().unwrap();
+        assert_eq!(lsnr.active_connections(), 0);
+    }
+    /*-- counts writes, the first held until gate opens --*/
+    #[derive(Debug, Clone)]
+    struct GatedTransport {
+        inner: MemoryTransport,
+        writes: Arc<AtomicUsize>,
+        gate: Arc<(Mutex<bool>, Condvar)>,
+    }
+    impl Read for GatedTransport {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+    impl Write for GatedTransport {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            let (lock, cv) = &*self.gate;
+            let _open = cv.wait_while(lock.lock().unwrap(), |open| !*open).unwrap();
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            self.inner.write(buf)
+        }
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+    impl Transport for GatedTransport {
+        fn try_clone(&self) -> Result<Self> {
+            Ok(self.clone())
+        }
+        fn shutdown(&self, how: Shutdown) -> Result<()> {
+            self.inner.shutdown(how)
+        }
+        fn peer_addr(&self) -> Result<SocketAddr> {
+            self.inner.peer_addr()
+        }
+        fn local_addr(&self) -> Result<SocketAddr> {
+            self.inner.local_addr()
+        }
+    }
+    #[test]
+    fn queued_messages_are_sent_in_one_batch() {
+        let (client, mut server) = MemoryTransport::pair();
+        let gate = Arc::new((Mutex::new(false), Condvar::new()));
+        let writes = Arc::new(AtomicUsize::new(0));
+        let client = GatedTransport { 
+            inner: client, writes: Arc::clone(&writes), gate: Arc::clone(&gate) 
+        };
+        let conn = Connector::<P,M,L,GatedTransport>::from_transport_with_config(client, raw_peer())
+            .unwrap();
+        /*-- FLUSH messages, each flushed when sent alone --*/
+        let n = 1000;
+        for i in 0..n {
+            let mut msg = Message::create_msg_str_fit(&format!("msg {}", i));
+            msg.set_type(MessageType::FLUSH as u8);
+            conn.post_message(msg).unwrap();
+        }
+        *gate.0.lock().unwrap() = true;
+        gate.1.notify_all();
+        for i in 0..n {
+            let msg: M = P::recv_message(&mut server).unwrap();
+            assert_eq!(msg.get_content_str().unwrap(), format!("msg {}", i));
+        }
+        /*-- n msgs of ~30 bytes fill a few 8 KB buffers --*/
+        assert!(writes.load(Ordering::Relaxed) < 20, "{} writes", writes.load(Ordering::Relaxed));
+        conn.shut_down();
+        let end: M = P::recv_message(&mut server).unwrap();
+        assert_eq!(end.get_type(), MessageType::END as u8);
+    }
+    #[test]
+    fn coalesce_drops_repeated_pings_not_data() {
+        let (client, mut server) = MemoryTransport::pair();
+        let gate = Arc::new((Mutex::new(false), Condvar::new()));
+        let client = GatedTransport { 
+            inner: client, writes: Arc::new(AtomicUsize::new(0)), gate: Arc::clone(&gate) 
+        };
+        /*-- TEXT isn't a control type, so is never coalesced --*/
+        let config = raw_peer()
+            .coalesce(vec![MessageType::PING, MessageType::SUBSCRIBE, MessageType::TEXT]);
+        let conn = Connector::<P,M,L,GatedTransport>::from_transport_with_config(client, config)
+            .unwrap();
+        let post = |mt: MessageType, body: &str| {
+            let mut msg = Message::create_msg_str_fit(body);
+            msg.set_type(mt as u8);
+            conn.post_message(msg).unwrap();
+        };
+        /*-- first write, larger than the buffer, holds the rest in the queue --*/
+        post(MessageType::TEXT, &"x".repeat(2 * BUFFER_SIZE));
+        for _ in 0..100 {
+            post(MessageType::PING, "");
+        }
+        for _ in 0..3 {
+            post(MessageType::TEXT, "same");
+        }
+        for _ in 0..50 {
+            post(MessageType::PING, "");
+        }
+        post(MessageType::PING, "other");
+        /*-- the topic is outside the body, but still tells them apart --*/
+        for topic in ["a", "a", "b"] {
+            let mut msg = Message::create_msg_str_fit("");
+            msg.set_type(MessageType::SUBSCRIBE as u8);
+            msg.set_topic(topic);
+            conn.post_message(msg).unwrap();
+        }
+        *gate.0.lock().unwrap() = true;
+        gate.1.notify_all();
+        assert!(conn.flush(Duration::from_secs(5)));
+        conn.shut_down();
+        let mut got = Vec::<(u8, String)>::new();
+        loop {
+            let msg: M = P::recv_message(&mut server).unwrap();
+            if msg.get_type() == MessageType::END as u8 {
+                break;
+            }
+            let text = if msg.get_type() == MessageType::SUBSCRIBE as u8 {
+                msg.get_topic()
+            } else {
+                msg.get_content_str().unwrap()
+            };
+            got.push((msg.get_type(), text.to_string()));
+        }
+        let (ping, text) = (MessageType::PING as u8, MessageType::TEXT as u8);
+        let sub = MessageType::SUBSCRIBE as u8;
+        let same = (text, "same".to_string());
+        assert_eq!(got[1..], [
+            (ping, String::new()), same.clone(), same.clone(), same, 
+            (ping, String::new()), (ping, "other".to_string()),
+            (sub, "a".to_string()), (sub, "b".to_string())
+        ]);
+        assert_eq!(got[0].1.len(), 2 * BUFFER_SIZE);
+    }
+    #[test]
+    fn failed_send_keeps_stranded_messages() {
+        let (client, server) = MemoryTransport::pair();
+        let config = raw_peer().dead_letters(true);
+        let conn = Connector::<P,M,L,MemoryTransport>::from_transport_with_config(client, config)
+            .unwrap();
+        let events = conn.events();
+        let post = |body: &str| {
+            let mut msg = Message::create_msg_str_fit(body);
+            msg.set_type(MessageType::FLUSH as u8);
+            conn.post_message(msg).unwrap();
+        };
+        /*-- park send thread with first, then peer goes away --*/
+        let lk = conn.link.0.lock().unwrap();
+        post("first");
+        while !conn.snd_queue.is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        post("second");
+        post("third");
+        server.shutdown(Shutdown::Both).unwrap();
+        drop(lk);
+        assert!(!conn.flush(Duration::from_secs(5)));
+        assert!(wait_until(|| !conn.is_connected()));
+        let dead = conn.take_undelivered();
+        let bodies: Vec<&str> = dead.iter().map(|m| m.get_content_str().unwrap()).collect();
+        assert_eq!(bodies, ["first", "second", "third"]);
+        assert!(conn.take_undelivered().is_empty());
+        assert_eq!(events.try_recv().unwrap(), ConnectionEvent::Connected);
+        let dur = Duration::from_secs(5);
+        assert_eq!(events.recv_timeout(dur).unwrap(), ConnectionEvent::Disconnected);
+        conn.shut_down();
+    }
+    #[test]
+    fn priority_messages_pass_queued_ones() {
+        let (client, mut server) = MemoryTransport::pair();
+        let conn = Connector::<P,M,L,MemoryTransport>::from_transport_with_config(client, raw_peer())
+            .unwrap();
+        let post = |body: &str, level: u8| {
+            let mut msg = Message::create_msg_str_fit(body);
+            msg.set_type(MessageType::FLUSH as u8);
+            conn.post_message_priority(msg, level).unwrap();
+        };
+        /*-- holding link parks send thread with first msg --*/
+        let lk = conn.link.0.lock().unwrap();
+        post("first", DEFAULT_PRIORITY);
+        while !conn.snd_queue.is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        post("data 1", DEFAULT_PRIORITY);
+        post("data 2", DEFAULT_PRIORITY);
+        post("control", 5);
+        post("urgent", 9);
+        drop(lk);
+        for want in ["first", "urgent", "control", "data 1", "data 2"].iter() {
+            let msg: M = P::recv_message(&mut server).unwrap();
+            assert_eq!(msg.get_content_str().unwrap(), *want);
+        }
+        conn.shut_down();
+    }
+    #[test]
+    fn small_buffers_carry_large_messages() {
+        let config = CommConfig::default().buffer_size(16);
+        let mut lsnr = Listener::<P,L>::new_with_config(1, config.clone());
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new_with_config(lsnr.local_addr().unwrap(), config).unwrap();
+        let body = "x".repeat(100_000);
+        let mut msg = Message::create_msg_str_fit(&body);
+        msg.set_type(MessageType::FLUSH as u8);
+        let reply = conn.post_and_wait(msg, Duration::from_secs(5)).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), body);
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    fn auth_listener() -> (Listener<P,L>, JoinHandle<()>) {
+        let mut lsnr = Listener::<P,L>::new(2);
+        lsnr.set_authenticator(|token: &[u8], peer: SocketAddr| {
+            token == b"secret" && peer.ip().is_loopback()
+        });
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        (lsnr, handle)
+    }
+    #[test]
+    fn authenticated_client_is_served() {
+        let (mut lsnr, handle) = auth_listener();
+        let config = CommConfig::default().auth_token("secret");
+        let conn = Connector::<P,M,L>::new_with_config(lsnr.local_addr().unwrap(), config).unwrap();
+        let mut msg = Message::create_msg_str_fit("let me in");
+        msg.set_type(MessageType::FLUSH as u8);
+        let reply = conn.post_and_wait(msg, Duration::from_secs(5)).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "let me in");
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn silent_peer_fails_authentication_in_time() {
+        /*-- accepts, but never answers AUTH, e.g., another service --*/
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = raw_peer().auth_token("secret").connect_timeout(Duration::from_millis(200));
+        let start = Instant::now();
+        let err = Connector::<P,M,L>::new_with_config(tcpl.local_addr().unwrap(), config)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert!(err.to_string().contains("no AUTH reply"), "{}", err);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+    #[test]
+    fn unauthenticated_clients_are_refused() {
+        let (mut lsnr, handle) = auth_listener();
+        let addr = lsnr.local_addr().unwrap();
+        let config = CommConfig::default().auth_token("guess");
+        let err = Connector::<P,M,L>::new_with_config(addr, config).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+
+        /*-- skipping AUTH gets AUTH_FAIL, then the socket closes --*/
+        let mut stream = TcpStream::connect(addr).unwrap();
+        hello(&mut stream);
+        let msg = Message::create_msg_str_fit("no token");
+        P::send_message(&msg, &mut stream).unwrap();
+        let answer: M = P::recv_message(&mut stream).unwrap();
+        assert_eq!(answer.get_type(), MessageType::AUTH_FAIL as u8);
+        let mut rest = Vec::new();
+        assert_eq!(stream.read_to_end(&mut rest).unwrap(), 0);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn rate_limit_slows_flooding_client() {
+        let config = CommConfig::default().rate_limit(50, 5);
+        let mut lsnr = Listener::<P,L>::new_with_config(1, config);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        let mut msg = Message::create_msg_str_fit("flood");
+        msg.set_type(MessageType::FLUSH as u8);
+        let start = Instant::now();
+        for _ in 0..15 {
+            conn.post_message(msg.clone()).unwrap();
+        }
+        for _ in 0..15 {
+            conn.get_message_timeout(Duration::from_secs(5)).unwrap();
+        }
+        /*-- burst of 5 is free, the other 10 take 1/50 sec each --*/
+        assert!(start.elapsed() >= Duration::from_millis(180));
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn stats_count_messages_and_bytes() {
+        let (client, mut server) = MemoryTransport::pair();
+        let conn = Connector::<P,M,L,MemoryTransport>::from_transport_with_config(client, raw_peer())
+            .unwrap();
+        assert_eq!(conn.stats(), ConnectorStats::default());
+        let mut msg = Message::create_msg_str_fit("counted");
+        msg.set_type(MessageType::FLUSH as u8);
+        for _ in 0..3 {
+            conn.post_message(msg.clone()).unwrap();
+        }
+        /*-- frame: 4 byte length, type, id, flags, seq, body --*/
+        let frame = 4 + 10 + 8 + "counted".len() as u64;
+        for _ in 0..3 {
+            let rcvd: M = P::recv_message(&mut server).unwrap();
+            P::send_message(&rcvd, &mut server).unwrap();
+        }
+        for _ in 0..3 {
+            conn.get_message_timeout(Duration::from_secs(5)).unwrap();
+        }
+        assert!(wait_until(|| conn.stats().bytes_sent == 3 * frame));
+        let stats = conn.stats();
+        assert_eq!(stats.messages_sent, 3);
+        assert_eq!(stats.messages_received, 3);
+        assert_eq!(stats.bytes_sent, 3 * frame);
+        assert_eq!(stats.bytes_received, 3 * frame);
+        assert_eq!(stats.send_queue_depth, 0);
+        conn.shut_down();
+    }
+    #[test]
+    fn server_stats_aggregate_sessions() {
+        let mut lsnr = Listener::<P,L>::new(2);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let mut msg = Message::create_msg_str_fit("tally");
+        msg.set_type(MessageType::FLUSH as u8);
+        let first = Connector::<P,M,L>::new(addr).unwrap();
+        let mut second = TcpStream::connect(addr).unwrap();
+        hello(&mut second);
+        /*-- served by the Listener, not counted as processed --*/
+        let mut sub = Message::create_msg_str_fit("");
+        sub.set_type(MessageType::SUBSCRIBE as u8);
+        sub.set_topic("news");
+        first.post_message(sub).unwrap();
+        for _ in 0..2 {
+            first.post_and_wait(msg.clone(), Duration::from_secs(5)).unwrap();
+        }
+        P::send_message(&msg, &mut second).unwrap();
+        let _: M = P::recv_message(&mut second).unwrap();
+        /*-- counted just after the write the client saw, replies are numbered --*/
+        let frame = 4 + 10 + 8 + "tally".len() as u64;
+        assert!(wait_until(|| lsnr.stats().bytes_sent == 3 * frame));
+        let stats = lsnr.stats();
+        assert_eq!(stats.active_connections, 2);
+        assert_eq!(stats.messages_processed, 3);
+        /*-- second's raw request isn't numbered --*/
+        assert!(stats.bytes_received >= 3 * frame - 8);
+
+        /*-- abrupt close still ends the session --*/
+        drop(second);
+        first.shut_down();
+        assert!(wait_until(|| lsnr.stats().active_connections == 0));
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn half_close_gets_replies_then_eof() {
+        let mut lsnr = Listener::<P,BufferLog>::new(1);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(lsnr.local_addr().unwrap()).unwrap();
+        hello(&mut stream);
+        let peer = stream.local_addr().unwrap();
+        for text in ["one", "two"] {
+            /*-- TEXT replies are buffered until the session ends --*/
+            let msg = Message::create_msg_str_fit(text);
+            P::send_message(&msg, &mut stream).unwrap();
+        }
+        stream.shutdown(Shutdown::Write).unwrap();
+        for text in ["one", "two"] {
+            let reply: M = P::recv_message(&mut stream).unwrap();
+            assert!(reply.get_content_str().unwrap().starts_with(text));
+        }
+        assert!(<P as Rcvr<M>>::recv_message(&mut stream).is_err());
+        let clean = format!("{}: client closed its end", peer);
+        assert!(wait_until(|| BufferLog::contents().contains(&clean)));
+        assert!(!BufferLog::contents().contains("connection reset"));
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[cfg(feature = "tls")]
+    fn tls_listener() -> (Listener<P,L>, JoinHandle<()>) {
+        let tls = server_config(
+            include_bytes!("../certs/server-cert.pem"),
+            include_bytes!("../certs/server-key.pem"),
+        ).unwrap();
+        let mut lsnr = Listener::<P,L>::new_with_config(1, CommConfig::default().tls_server(tls));
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        (lsnr, handle)
+    }
+    #[cfg(feature = "tls")]
+    #[test]
+    fn tls_round_trip() {
+        let (mut lsnr, handle) = tls_listener();
+        let tls = client_config(include_bytes!("../certs/ca-cert.pem")).unwrap();
+        let conn = Connector::<P,M,L,TlsStream>::new_tls(
+            lsnr.local_addr().unwrap(), "localhost", tls
+        ).unwrap();
+        let mut msg = Message::create_msg_str_fit("secret");
+        msg.set_type(MessageType::FLUSH as u8);
+        let reply = conn.post_and_wait(msg, Duration::from_secs(5)).unwrap();
+        assert!(reply.get_content_str().unwrap().starts_with("secret"));
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[cfg(feature = "tls")]
+    #[test]
+    fn tls_handshake_failure_is_reported() {
+        let (mut lsnr, handle) = tls_listener();
+        let tls = client_config(include_bytes!("../certs/ca-cert.pem")).unwrap();
+        /*-- certificate is for localhost, so validation fails --*/
+        let err = Connector::<P,M,L,TlsStream>::new_tls(
+            lsnr.local_addr().unwrap(), "example.com", tls
+        ).err().unwrap();
+        assert!(err.to_string().contains("TLS handshake with example.com failed"), "{}", err);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn handler_panic_keeps_pool_serving() {
+        /*-- one pool thread, so later clients need it restarted --*/
+        let mut lsnr = Listener::<P,L>::new(1);
+        lsnr.set_handler(|msg: M, replies: &Sender<M>| {
+            if msg.get_content_str().unwrap() == "panic" {
+                panic!("handler failed");
+            }
+            let _ = replies.send(msg);
+        });
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let bad = Connector::<P,M,L>::new(addr).unwrap();
+        let mut msg = Message::create_msg_str_fit("panic");
+        msg.set_type(MessageType::FLUSH as u8);
+        bad.post_message(msg).unwrap();
+        assert!(bad.get_message_timeout(Duration::from_secs(5)).is_none());
+        bad.shut_down();
+
+        let good = Connector::<P,M,L>::new(addr).unwrap();
+        let mut msg = Message::create_msg_str_fit("still here");
+        msg.set_type(MessageType::FLUSH as u8);
+        good.post_message(msg).unwrap();
+        let reply = good.get_message_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "still here");
+        good.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn broadcast_reaches_every_client() {
+        let mut lsnr = Listener::<P,L>::new(3);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let dur = Duration::from_secs(5);
+        let mut conns = Vec::new();
+        for _ in 0..2 {
+            /*-- round trip ensures session has been registered --*/
+            let conn = Connector::<P,M,L>::new(addr).unwrap();
+            let mut msg = Message::create_msg_str_fit("hello");
+            msg.set_type(MessageType::FLUSH as u8);
+            conn.post_message(msg).unwrap();
+            assert!(conn.get_message_timeout(dur).is_some());
+            conns.push(conn);
+        }
+        let news = Message::create_msg_str_fit("news");
+        assert_eq!(lsnr.broadcast(news.clone()), 2);
+        for conn in &conns {
+            let msg = conn.get_message_timeout(dur).unwrap();
+            assert_eq!(msg.get_content_str().unwrap(), "news");
+        }
+        conns.pop().unwrap().shut_down();
+        assert!(wait_until(|| lsnr.active_connections() == 1));
+        assert_eq!(lsnr.broadcast(news), 1);
+        assert!(conns[0].get_message_timeout(dur).is_some());
+        conns[0].shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn published_messages_reach_only_subscribers() {
+        let mut lsnr = Listener::<P,L>::new(3);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let dur = Duration::from_secs(5);
+        let topic_msg = |mt: MessageType, topic: &str| {
+            let mut msg = Message::create_msg_str_fit("");
+            msg.set_type(mt as u8);
+            msg.set_topic(topic);
+            msg
+        };
+        let mut flush = Message::create_msg_str_fit("sync");
+        flush.set_type(MessageType::FLUSH as u8);
+        let conns: Vec<_> = ["weather", "sports", "sports"].iter().map(|topic| {
+            let conn = Connector::<P,M,L>::new(addr).unwrap();
+            conn.post_message(topic_msg(MessageType::SUBSCRIBE, topic)).unwrap();
+            /*-- round trip ensures subscription was handled --*/
+            assert!(conn.post_and_wait(flush.clone(), dur).is_some());
+            conn
+        }).collect();
+
+        let mut news = topic_msg(MessageType::PUBLISH, "weather");
+        news.set_body_str("rain");
+        conns[1].post_message(news.clone()).unwrap();
+        let rcvd = conns[0].get_message_timeout(dur).unwrap();
+        assert_eq!(rcvd.get_type(), MessageType::PUBLISH as u8);
+        assert_eq!(rcvd.get_topic(), "weather");
+        assert_eq!(rcvd.get_body_str().unwrap(), "rain");
+        assert!(conns[1].get_message_timeout(Duration::from_millis(100)).is_none());
+
+        conns[2].post_message(topic_msg(MessageType::UNSUBSCRIBE, "sports")).unwrap();
+        assert!(conns[2].post_and_wait(flush.clone(), dur).is_some());
+        assert_eq!(lsnr.publish(topic_msg(MessageType::PUBLISH, "sports")), 1);
+
+        /*-- disconnected subscriber is pruned --*/
+        conns[0].shut_down();
+        assert!(wait_until(|| lsnr.active_connections() == 2));
+        assert_eq!(lsnr.publish(news), 0);
+        for conn in &conns[1..] {
+            conn.shut_down();
+        }
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn posted_bytes_are_sent_without_a_message() {
+        let mut lsnr = Listener::<P,L>::new(1);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        let body: Vec<u8> = (0..3 * CHUNK_SIZE + 7).map(|i| (i % 251) as u8).collect();
+        let id = conn.post_bytes(MessageType::FLUSH as u8, &body).unwrap();
+        let reply = conn.get_message_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(reply.get_id(), id);
+        assert!(reply.get_body_bytes().starts_with(&body));
+        assert_eq!(conn.stats().messages_sent, 1);
+
+        /*-- processing types without buf_write_bytes refuse --*/
+        let upper = Connector::<Upper,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        assert!(matches!(upper.post_bytes(MessageType::FLUSH as u8, b"x"), Err(CommError::Io(_))));
+        assert!(upper.is_connected());
+        upper.shut_down();
+        conn.shut_down();
+        assert!(matches!(conn.post_bytes(MessageType::TEXT as u8, b"x"), Err(CommError::NotConnected)));
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn app_types_reach_handler_intact() {
+        let mut lsnr = Listener::<P,L>::new(1);
+        lsnr.set_handler(|mut msg: M, replies: &Sender<M>| {
+            let code = MessageType::app_code(msg.get_type()).unwrap_or(0);
+            msg.set_body_str(&format!("app {}", code));
+            if msg.get_type() == MessageType::FLUSH as u8 {
+                msg.set_type(MessageType::REPLY as u8);
+            }
+            let _ = replies.send(msg);
+        });
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        let dur = Duration::from_secs(5);
+        let codes = [0, 42, APP_CODE_MAX];
+        for code in codes.iter() {
+            conn.post_message(Message::builder().app_type(*code).build().unwrap()).unwrap();
+        }
+        /*-- replies are buffered, as for TEXT, until a FLUSH --*/
+        let flush = Message::builder().msg_type(MessageType::FLUSH).build().unwrap();
+        assert!(conn.post_and_wait(flush, dur).is_some());
+        for code in codes.iter() {
+            let reply = conn.get_message_timeout(dur).unwrap();
+            assert_eq!(MessageType::app_code(reply.get_type()), Some(*code));
+            assert_eq!(reply.get_body_str().unwrap(), format!("app {}", code));
+        }
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn stop_graceful_finishes_in_flight_messages() {
+        let mut lsnr = Listener::<P,L>::new(2);
+        lsnr.set_handler(|msg: M, replies: &Sender<M>| {
+            thread::sleep(Duration::from_millis(300));
+            let _ = replies.send(msg);
+        });
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let busy = Connector::<P,M,L>::new(addr).unwrap();
+        let idle = Connector::<P,M,L>::new(addr).unwrap();
+        assert!(wait_until(|| lsnr.active_connections() == 2));
+        busy.post_message(Message::create_msg_str_fit("slow")).unwrap();
+        assert!(wait_until(|| lsnr.stats().messages_processed == 1));
+
+        let start = Instant::now();
+        assert!(lsnr.stop_graceful(Duration::from_secs(5)));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(lsnr.active_connections(), 0);
+        /*-- reply to in-flight message wasn't lost --*/
+        let reply = busy.get_message_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "slow");
+        assert!(wait_until(|| !idle.is_connected() && !busy.is_connected()));
+        let _ = handle.join();
+    }
+    #[test]
+    fn end_closes_session_only() {
+        let mut lsnr = Listener::<P,L>::new(2);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        let mut end = Message::new(HEADER_SIZE);
+        end.set_type(MessageType::END as u8);
+        conn.post_message(end).unwrap();
+        assert!(wait_until(|| !conn.is_connected()));
+        /*-- listener still serves new clients --*/
+        let next = Connector::<P,M,L>::new(addr).unwrap();
+        let mut msg = Message::create_msg_str_fit("still here");
+        msg.set_type(MessageType::FLUSH as u8);
+        assert!(next.post_and_wait(msg, Duration::from_secs(5)).is_some());
+        assert!(!handle.is_finished());
+        next.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn quit_closes_session_and_stops_listener() {
+        let mut lsnr = Listener::<P,L>::new(2);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        let mut quit = Message::new(HEADER_SIZE);
+        quit.set_type(MessageType::QUIT as u8);
+        conn.post_message(quit).unwrap();
+        assert!(wait_until(|| !conn.is_connected()));
+        /*-- accept loop and its pool have stopped --*/
+        assert!(wait_until(|| handle.is_finished()));
+        assert!(Connector::<P,M,L>::new(addr).is_err());
+        let _ = handle.join();
+    }
+    #[test]
+    fn idle_sessions_are_closed() {
+        let config = CommConfig::default().idle_timeout(Duration::from_millis(100));
+        let mut lsnr = Listener::<P,L>::new_with_config(1, config);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        assert!(wait_until(|| lsnr.active_connections() == 1));
+        assert!(wait_until(|| lsnr.active_connections() == 0));
+        assert!(wait_until(|| !conn.is_connected()));
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn heartbeat_detects_silent_peer() {
+        /*-- peer accepts but never answers PING --*/
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = raw_peer()
+            .heartbeat(Duration::from_millis(20), Duration::from_millis(60));
+        let conn = Connector::<P,M,L>::new_with_config(
+            tcpl.local_addr().unwrap(), config
+        ).unwrap();
+        let (_silent, _) = tcpl.accept().unwrap();
+        assert!(conn.is_connected());
+        assert!(wait_until(|| !conn.is_connected()));
+        conn.shut_down();
+        assert!(conn.is_finished());
+    }
+    #[test]
+    fn heartbeat_answered_by_listener() {
+        let mut lsnr = Listener::<P,L>::new(1);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let config = CommConfig::default()
+            .heartbeat(Duration::from_millis(20), Duration::from_millis(500));
+        let conn = Connector::<P,M,L>::new_with_config(
+            lsnr.local_addr().unwrap(), config
+        ).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        assert!(conn.is_connected());
+        /*-- PONGs aren't passed on to application --*/
+        assert!(conn.try_get_message().is_none());
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn incoming_ends_when_connection_closes() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new_with_config(tcpl.local_addr().unwrap(), raw_peer()).unwrap();
+        let (server, _) = tcpl.accept().unwrap();
+        let mut writer = BufWriter::new(server);
+        for i in 0..3 {
+            let mut msg = Message::create_msg_str_fit(&format!("reply {}", i));
+            msg.set_type(MessageType::TEXT as u8);
+            P::buf_send_message(&msg, &mut writer).unwrap();
+        }
+        /*-- peer closes after replying, loop sees every reply --*/
+        drop(writer);
+        let bodies: Vec<String> = conn.incoming()
+            .map(|msg| msg.get_content_str().unwrap().to_string())
+            .collect();
+        assert_eq!(bodies, vec!["reply 0", "reply 1", "reply 2"]);
+        conn.shut_down();
+        assert!(conn.incoming().next().is_none());
+    }
+    #[test]
+    fn peek_message_leaves_reply_queued() {
+        let mut lsnr = Listener::<P,L>::new(1);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        assert!(conn.peek_message().is_none());
+        let mut msg = Message::create_msg_str_fit("look first");
+        msg.set_type(MessageType::FLUSH as u8);
+        conn.post_message(msg).unwrap();
+        assert!(wait_until(|| conn.has_msg()));
+        let peeked = conn.peek_message().unwrap();
+        assert_eq!(peeked.get_content_str().unwrap(), "look first");
+        let rcvd = conn.get_message().unwrap();
+        assert_eq!(rcvd.get_id(), peeked.get_id());
+        assert!(conn.peek_message().is_none());
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn binary_body_round_trips_intact() {
+        /*-- xorshift bytes, includes nulls and newlines --*/
+        let mut x: u32 = 0x2545_f491;
+        let body: Vec<u8> = (0..10 * 1024).map(|_| {
+            x ^= x << 13; x ^= x >> 17; x ^= x << 5;
+            x as u8
+        }).collect();
+        assert!(body.contains(&0) && body.contains(&b'\n'));
+
+        let mut lsnr = Listener::<P,L>::new(1);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        let mut msg = Message::default();
+        msg.set_body_bytes(body.clone());
+        msg.set_type(MessageType::FLUSH as u8);
+        conn.post_message(msg).unwrap();
+        let reply = conn.get_message_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(reply.get_body_bytes(), &body[..]);
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn post_and_wait_takes_only_replies() {
+        let mut lsnr = Listener::<P,L>::new(1);
+        lsnr.set_handler(|mut msg: M, replies: &Sender<M>| {
+            let mut push = Message::create_msg_str_fit("pushed");
+            push.set_type(MessageType::TEXT as u8);
+            push.set_id(msg.get_id());
+            let _ = replies.send(push);
+            msg.set_type(MessageType::REPLY as u8);
+            let _ = replies.send(msg);
+        });
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        let request = Message::builder().msg_type(MessageType::FLUSH).body("asked").build().unwrap();
+        let dur = Duration::from_secs(5);
+        let reply = conn.post_and_wait(request, dur).unwrap();
+        assert_eq!(reply.get_type(), MessageType::REPLY as u8);
+        assert_eq!(reply.get_body_str().unwrap(), "asked");
+        /*-- push carrying the same id went to the queue --*/
+        let push = conn.get_message_timeout(dur).unwrap();
+        assert_eq!(push.get_body_str().unwrap(), "pushed");
+        assert_eq!(push.get_id(), reply.get_id());
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn timeouts_too_long_for_a_deadline_wait_untimed() {
+        let mut lsnr = Listener::<P,L>::new(1);
+        lsnr.set_handler(|mut msg: M, replies: &Sender<M>| {
+            msg.set_type(MessageType::REPLY as u8);
+            let _ = replies.send(msg);
+        });
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        let mut msg = Message::create_msg_str_fit("forever");
+        msg.set_type(MessageType::FLUSH as u8);
+        conn.post_message(msg.clone()).unwrap();
+        assert!(conn.flush(Duration::MAX));
+        let echo = conn.get_message_timeout(Duration::MAX).unwrap();
+        assert_eq!(echo.get_content_str().unwrap(), "forever");
+        let reply = conn.post_and_wait(msg, Duration::MAX).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "forever");
+        join_within(thread::spawn(|| {}), Some(Duration::MAX));
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn post_and_wait_matches_reply_by_id() {
+        /*-- replies to "slow" only after the next request --*/
+        let held = Arc::new(Mutex::new(None::<M>));
+        let mut lsnr = Listener::<P,L>::new(1);
+        let hold = Arc::clone(&held);
+        lsnr.set_handler(move |mut msg: M, replies: &Sender<M>| {
+            msg.set_type(MessageType::REPLY as u8);
+            if msg.get_content_str().unwrap() == "slow" {
+                *hold.lock().unwrap() = Some(msg);
+                return;
+            }
+            let _ = replies.send(msg);
+            if let Some(slow) = hold.lock().unwrap().take() {
+                let _ = replies.send(slow);
+            }
+        });
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let conn = Connector::<P,M,L>::new(lsnr.local_addr().unwrap()).unwrap();
+        let mut slow = Message::create_msg_str_fit("slow");
+        slow.set_type(MessageType::FLUSH as u8);
+        conn.post_message(slow).unwrap();
+
+        let mut fast = Message::create_msg_str_fit("fast");
+        fast.set_type(MessageType::FLUSH as u8);
+        let reply = conn.post_and_wait(fast, Duration::from_secs(5)).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "fast");
+        /*-- reply to slow, sent after fast's, is still queued --*/
+        let other = conn.get_message_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(other.get_content_str().unwrap(), "slow");
+        assert_ne!(other.get_id(), reply.get_id());
+
+        let mut lost = Message::create_msg_str_fit("slow");
+        lost.set_type(MessageType::FLUSH as u8);
+        assert!(conn.post_and_wait(lost, Duration::from_millis(50)).is_none());
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn shut_down_wakes_get_message() {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = Arc::new(Connector::<P,M,L>::new_with_config(tcpl.local_addr().unwrap(), raw_peer()).unwrap());
+        let _peer = tcpl.accept().unwrap();
+        let waiter = Arc::clone(&conn);
+        let handle = thread::spawn(move || waiter.get_message());
+        thread::sleep(Duration::from_millis(20));
+        conn.shut_down();
+        assert!(handle.join().unwrap().is_none());
+    }
+    #[test]
+    fn owned_string_addresses() {
+        /*-- e.g., port read from config or environment --*/
+        let addr = format!("127.0.0.1:{}", unused_addr().port());
+        let mut lsnr = Listener::<P,L>::new(2);
+        let handle = lsnr.start(addr.clone()).unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        let mut msg = Message::create_msg_str_fit("owned");
+        msg.set_type(MessageType::FLUSH as u8);
+        conn.post_message(msg).unwrap();
+        assert_eq!(conn.get_message().unwrap().get_content_str().unwrap(), "owned");
+        assert!(conn.get_message_timeout(Duration::from_millis(20)).is_none());
+        assert!(conn.try_get_message().is_none());
+        conn.shut_down();
+        lsnr.stop();
+        let _ = handle.join();
+    }
 }
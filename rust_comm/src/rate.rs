@@ -0,0 +1,59 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::rate.rs - per-session message rate limit     //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   TokenBucket:
+   - holds up to burst tokens, refilled at rate per second
+   - each message takes a token; when none is left take
+     returns how long the caller should delay, so a 
+     client is slowed to rate without losing messages
+   - a client that is quiet refills its bucket, so short
+     bursts of up to burst messages aren't delayed
+*/
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+impl TokenBucket {
+    /*-- starts full, rate and burst are at least 1 --*/
+    pub(crate) fn new(rate: u32, burst: u32) -> TokenBucket {
+        let burst = burst.max(1) as f64;
+        TokenBucket { rate: rate.max(1) as f64, burst, tokens: burst, last: Instant::now() }
+    }
+    /*-- take a token, returning the delay owed if there was none --*/
+    pub(crate) fn take(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.burst) - 1.0;
+        self.last = now;
+        if self.tokens >= 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(-self.tokens / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn burst_is_free_then_delays_at_rate() {
+        let mut bucket = TokenBucket::new(10, 5);
+        for _ in 0..5 {
+            assert!(bucket.take().is_none());
+        }
+        let wait = bucket.take().unwrap();
+        assert!(wait > Duration::from_millis(80) && wait <= Duration::from_millis(100));
+        /*-- debt accumulates until client slows down --*/
+        assert!(bucket.take().unwrap() > wait);
+    }
+}
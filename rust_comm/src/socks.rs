@@ -0,0 +1,203 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::socks.rs - connect through a SOCKS5 proxy    //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   With CommConfig::proxy(addr), a Connector connects to
+   the proxy, then asks it, with RFC 1928's CONNECT, to
+   open the connection to the Listener:
+   - offers no authentication, and username/password,
+     RFC 1929, when proxy_auth is set
+   - fails, with the proxy's reason, if it picks neither,
+     refuses the credentials, or can't reach the Listener
+   - the handshake must finish within the connect
+     timeout; once it has, the stream carries messages
+     as a direct connection does
+*/
+
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::{SocketAddr, TcpStream};
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const USER_PASS: u8 = 2;
+const NO_METHOD: u8 = 0xff;
+const AUTH_VERSION: u8 = 1;
+const CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+fn refused(why: String) -> Error {
+    Error::new(ErrorKind::ConnectionRefused, format!("socks5 proxy: {}", why))
+}
+fn reply_reason(code: u8) -> &'static str {
+    match code {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/*-- negotiate a CONNECT to target over stream, already connected to the proxy --*/
+pub(crate) fn connect(
+    stream: &mut TcpStream, target: &SocketAddr, auth: Option<&(String, String)>
+) -> Result<()> {
+    /*-- greeting, offering the methods we support --*/
+    match auth {
+        Some(_) => stream.write_all(&[VERSION, 2, NO_AUTH, USER_PASS])?,
+        None => stream.write_all(&[VERSION, 1, NO_AUTH])?,
+    }
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice)?;
+    if choice[0] != VERSION {
+        return Err(refused(format!("unexpected version {}", choice[0])));
+    }
+    match (choice[1], auth) {
+        (NO_AUTH, _) => {}
+        (USER_PASS, Some((user, pass))) => authenticate(stream, user, pass)?,
+        (NO_METHOD, _) => return Err(refused("no acceptable authentication method".to_string())),
+        (method, _) => return Err(refused(format!("unoffered method {}", method))),
+    }
+    /*-- CONNECT request --*/
+    let mut request = vec![VERSION, CONNECT, 0];
+    match target {
+        SocketAddr::V4(v4) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&v4.ip().octets());
+        }
+        SocketAddr::V6(v6) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&v6.ip().octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request)?;
+    /*-- reply: version, code, reserved, then bound address, discarded --*/
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != VERSION {
+        return Err(refused(format!("unexpected version {}", reply[0])));
+    }
+    if reply[1] != 0 {
+        return Err(refused(format!("can't connect to {}: {}", target, reply_reason(reply[1]))));
+    }
+    let addr_len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        atyp => return Err(refused(format!("unknown address type {}", atyp))),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound)?;
+    Ok(())
+}
+/*-- RFC 1929 username/password sub-negotiation --*/
+fn authenticate(stream: &mut TcpStream, user: &str, pass: &str) -> Result<()> {
+    if user.len() > 255 || pass.len() > 255 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput, "socks5 username and password are limited to 255 bytes"
+        ));
+    }
+    let mut request = vec![AUTH_VERSION, user.len() as u8];
+    request.extend_from_slice(user.as_bytes());
+    request.push(pass.len() as u8);
+    request.extend_from_slice(pass.as_bytes());
+    stream.write_all(&request)?;
+    let mut status = [0u8; 2];
+    stream.read_exact(&mut status)?;
+    if status[1] != 0 {
+        return Err(refused("username or password refused".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::net::TcpListener;
+    type L = MuteLog;
+    type M = Message;
+    type P = CommProcessing<L>;
+
+    /*-- SOCKS5 proxy accepting only user "app", password "secret", relaying to target --*/
+    fn proxy() -> SocketAddr {
+        let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcpl.local_addr().unwrap();
+        thread::spawn(move || {
+            for client in tcpl.incoming() {
+                let mut client = client.unwrap();
+                let mut hdr = [0u8; 2];
+                client.read_exact(&mut hdr).unwrap();
+                let mut methods = vec![0u8; hdr[1] as usize];
+                client.read_exact(&mut methods).unwrap();
+                if !methods.contains(&2) {
+                    client.write_all(&[5, 0xff]).unwrap();
+                    continue;
+                }
+                client.write_all(&[5, 2]).unwrap();
+                let field = |client: &mut TcpStream| {
+                    let mut len = [0u8; 1];
+                    client.read_exact(&mut len).unwrap();
+                    let mut text = vec![0u8; len[0] as usize];
+                    client.read_exact(&mut text).unwrap();
+                    String::from_utf8(text).unwrap()
+                };
+                let mut ver = [0u8; 1];
+                client.read_exact(&mut ver).unwrap();
+                let creds = (field(&mut client), field(&mut client));
+                if creds != ("app".to_string(), "secret".to_string()) {
+                    client.write_all(&[1, 1]).unwrap();
+                    continue;
+                }
+                client.write_all(&[1, 0]).unwrap();
+                let mut req = [0u8; 10];
+                client.read_exact(&mut req).unwrap();
+                let ip = std::net::Ipv4Addr::new(req[4], req[5], req[6], req[7]);
+                let target = SocketAddr::from((ip, u16::from_be_bytes([req[8], req[9]])));
+                let upstream = TcpStream::connect(target).unwrap();
+                client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+                let (mut c_in, mut u_out) = (client.try_clone().unwrap(), upstream.try_clone().unwrap());
+                thread::spawn(move || std::io::copy(&mut c_in, &mut u_out));
+                let (mut u_in, mut c_out) = (upstream, client);
+                thread::spawn(move || std::io::copy(&mut u_in, &mut c_out));
+            }
+        });
+        addr
+    }
+    #[test]
+    fn connector_reaches_listener_through_proxy() {
+        let mut lsnr = Listener::<P,L>::new(2);
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let target = lsnr.local_addr().unwrap();
+        let proxy = proxy();
+
+        let config = CommConfig::default().proxy(proxy).proxy_auth("app", "secret");
+        let conn = Connector::<P,M,L>::new_with_config(target, config).unwrap();
+        let mut msg = Message::create_msg_str_fit("via proxy");
+        msg.set_type(MessageType::FLUSH as u8);
+        let reply = conn.post_and_wait(msg, Duration::from_secs(5)).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "via proxy");
+        conn.shut_down();
+
+        let wrong = CommConfig::default().proxy(proxy).proxy_auth("app", "guess");
+        let err = Connector::<P,M,L>::new_with_config(target, wrong).err().unwrap();
+        assert!(err.to_string().contains("username or password refused"), "{}", err);
+        let none = CommConfig::default().proxy(proxy);
+        let err = Connector::<P,M,L>::new_with_config(target, none).err().unwrap();
+        assert!(err.to_string().contains("no acceptable authentication method"), "{}", err);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+}
@@ -0,0 +1,127 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::stats.rs - traffic counters                  //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   ConnectorStats:
+   - snapshot of a Connector's traffic, returned by
+     Connector::stats
+   - message counts include heartbeat PINGs and PONGs
+   - byte counts are bytes passed to and from the
+     Connector's transport, counted by Metered, so they
+     include framing and survive reconnects
+
+   ServerStats:
+   - snapshot of traffic across all of a Listener's
+     sessions, returned by Listener::stats
+   - messages_processed counts messages passed to the
+     handler, not END, QUIT, PING, AUTH, or the pub/sub
+     and file transfer messages the Listener serves itself
+   - byte counts include framing, like ConnectorStats
+
+   Counters are atomics, updated without locks by the
+   threads moving messages.
+*/
+
+use rust_traits::Transport;
+use std::io::{Read, Result, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ConnectorStats {
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub send_queue_depth: usize,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    pub active_connections: usize,
+    pub messages_processed: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+}
+
+/*-- live counters shared by a Connector or Listener and its threads --*/
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub(crate) messages_sent: AtomicU64,
+    pub(crate) messages_received: AtomicU64,
+    pub(crate) bytes_sent: AtomicU64,
+    pub(crate) bytes_received: AtomicU64,
+}
+impl Counters {
+    pub(crate) fn count(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+    pub(crate) fn snapshot(&self, send_queue_depth: usize) -> ConnectorStats {
+        ConnectorStats {
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            send_queue_depth,
+        }
+    }
+    /*-- Listener's view, messages_received counts those processed --*/
+    pub(crate) fn server_snapshot(&self, active_connections: usize) -> ServerStats {
+        ServerStats {
+            active_connections,
+            messages_processed: self.messages_received.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/*-- Transport counting bytes read and written into Counters --*/
+#[derive(Debug)]
+pub(crate) struct Metered<T: Transport> {
+    inner: T,
+    counters: Arc<Counters>,
+}
+impl<T: Transport> Metered<T> {
+    pub(crate) fn new(inner: T, counters: &Arc<Counters>) -> Metered<T> {
+        Metered { inner, counters: Arc::clone(counters) }
+    }
+}
+impl<T: Transport> Read for Metered<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.inner.read(buf)?;
+        Counters::count(&self.counters.bytes_received, n as u64);
+        Ok(n)
+    }
+}
+impl<T: Transport> Write for Metered<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let n = self.inner.write(buf)?;
+        Counters::count(&self.counters.bytes_sent, n as u64);
+        Ok(n)
+    }
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+impl<T: Transport> Transport for Metered<T> {
+    fn try_clone(&self) -> Result<Self> {
+        Ok(Metered { inner: self.inner.try_clone()?, counters: Arc::clone(&self.counters) })
+    }
+    fn shutdown(&self, how: Shutdown) -> Result<()> {
+        self.inner.shutdown(how)
+    }
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+    fn local_addr(&self) -> Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+    fn set_read_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(dur)
+    }
+}
@@ -0,0 +1,183 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::tls.rs - TLS Transport, feature "tls"        //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   TlsStream:
+   - Transport encrypting a TcpStream with rustls
+   - clones share one TLS session, so Connector's and
+     Listener's reader and writer threads work as they do
+     over TcpStream
+   - the reader waits on the socket without holding the
+     session lock, so writers aren't blocked by a
+     pending read
+   - connect completes the handshake before returning,
+     accept lets the first read complete it
+
+   server_config and client_config build rustls configs
+   from PEM data.  The certs directory holds a test CA and
+   localhost certificate, for tests only.
+*/
+
+use rust_traits::Transport;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, Connection, RootCertStore};
+use rustls::{ServerConfig, ServerConnection};
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/*-- rustls and PEM errors as io errors --*/
+fn tls_err<E: std::fmt::Display>(context: &str, e: E) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{}: {}", context, e))
+}
+
+/*-- server config presenting cert_pem chain, with key_pem --*/
+pub fn server_config(cert_pem: &[u8], key_pem: &[u8]) -> Result<Arc<ServerConfig>> {
+    let certs = CertificateDer::pem_slice_iter(cert_pem)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| tls_err("bad certificate PEM", e))?;
+    let key = PrivateKeyDer::from_pem_slice(key_pem)
+        .map_err(|e| tls_err("bad private key PEM", e))?;
+    let config = ServerConfig::builder_with_provider(
+            Arc::new(rustls::crypto::ring::default_provider())
+        )
+        .with_safe_default_protocol_versions()
+        .map_err(|e| tls_err("TLS config", e))?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| tls_err("TLS config", e))?;
+    Ok(Arc::new(config))
+}
+/*-- client config trusting the CA certificates in ca_pem --*/
+pub fn client_config(ca_pem: &[u8]) -> Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(ca_pem) {
+        let cert = cert.map_err(|e| tls_err("bad CA certificate PEM", e))?;
+        roots.add(cert).map_err(|e| tls_err("bad CA certificate", e))?;
+    }
+    let config = ClientConfig::builder_with_provider(
+            Arc::new(rustls::crypto::ring::default_provider())
+        )
+        .with_safe_default_protocol_versions()
+        .map_err(|e| tls_err("TLS config", e))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+#[derive(Debug)]
+pub struct TlsStream {
+    conn: Arc<Mutex<Connection>>,
+    sock: TcpStream,
+}
+impl TlsStream {
+    /*-----------------------------------------------------
+      start TLS as client of server_name over sock
+      - fails if the handshake fails, e.g., the server's
+        certificate isn't trusted or doesn't match name
+    */
+    pub fn connect(
+        mut sock: TcpStream, server_name: &str, config: Arc<ClientConfig>
+    ) -> Result<TlsStream> {
+        let name = ServerName::try_from(server_name.to_string())
+            .map_err(|e| tls_err("bad server name", e))?;
+        let mut conn = ClientConnection::new(config, name)
+            .map_err(|e| tls_err("TLS client", e))?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut sock).map_err(|e| Error::new(
+                e.kind(), format!("TLS handshake with {} failed: {}", server_name, e)
+            ))?;
+        }
+        Ok(TlsStream { conn: Arc::new(Mutex::new(conn.into())), sock })
+    }
+    /*-- start TLS as server over sock, handshake runs on first read --*/
+    pub fn accept(sock: TcpStream, config: Arc<ServerConfig>) -> Result<TlsStream> {
+        let conn = ServerConnection::new(config)
+            .map_err(|e| tls_err("TLS server", e))?;
+        Ok(TlsStream { conn: Arc::new(Mutex::new(conn.into())), sock })
+    }
+    /*-- underlying socket, e.g., to set options --*/
+    pub fn socket(&self) -> &TcpStream {
+        &self.sock
+    }
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+/*-- send records rustls has queued, e.g., handshake or alerts --*/
+fn write_pending(conn: &mut Connection, mut sock: &TcpStream) -> Result<()> {
+    while conn.wants_write() {
+        conn.write_tls(&mut sock)?;
+    }
+    Ok(())
+}
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut tls = [0u8; 16 * 1024];
+        loop {
+            {
+                let mut conn = self.lock();
+                match conn.reader().read(buf) {
+                    Ok(n) => return Ok(n),  // 0 after peer's close_notify
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e),
+                }
+                write_pending(&mut conn, &self.sock)?;
+            }
+            /*-- wait for bytes without holding the session --*/
+            let n = (&self.sock).read(&mut tls)?;
+            let mut conn = self.lock();
+            let mut rd = &tls[..n];
+            loop {
+                conn.read_tls(&mut rd)?;  // 0 bytes records EOF
+                conn.process_new_packets().map_err(|e| tls_err("TLS error", e))?;
+                if rd.is_empty() {
+                    break;
+                }
+            }
+            write_pending(&mut conn, &self.sock)?;
+        }
+    }
+}
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut conn = self.lock();
+        let n = conn.writer().write(buf)?;
+        write_pending(&mut conn, &self.sock)?;
+        Ok(n)
+    }
+    fn flush(&mut self) -> Result<()> {
+        let mut conn = self.lock();
+        conn.writer().flush()?;
+        write_pending(&mut conn, &self.sock)
+    }
+}
+impl Transport for TlsStream {
+    fn try_clone(&self) -> Result<Self> {
+        Ok(TlsStream { conn: Arc::clone(&self.conn), sock: self.sock.try_clone()? })
+    }
+    /*-- tells peer with close_notify before closing socket --*/
+    fn shutdown(&self, how: Shutdown) -> Result<()> {
+        if how != Shutdown::Read {
+            let mut conn = self.lock();
+            conn.send_close_notify();
+            let _ = write_pending(&mut conn, &self.sock);
+        }
+        self.sock.shutdown(how)
+    }
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        self.sock.peer_addr()
+    }
+    fn local_addr(&self) -> Result<SocketAddr> {
+        self.sock.local_addr()
+    }
+    /*-- a timed out read has consumed no TLS records, so may be retried --*/
+    fn set_read_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        self.sock.set_read_timeout(dur)
+    }
+}
@@ -0,0 +1,289 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::transport.rs - in-memory and session streams //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   MemoryTransport:
+   - one end of a connected pair of in-memory pipes, made
+     by MemoryTransport::pair()
+   - implements Transport like TcpStream, so Connector and
+     Listener sessions run over it without a socket, e.g.,
+     in tests that must not bind ports
+   - the pair shares an unspecified address, 0.0.0.0:n,
+     with n unique to the pair, reported by peer_addr and
+     local_addr
+   - reads block until bytes arrive and return Ok(0) once
+     the writing side shuts down, writes fail after the
+     reading side shuts down
+   - dropping the last clone of an end shuts it down
+
+   - reads don't time out, set_read_timeout is ignored
+
+   NetStream:
+   - stream of a Listener session, plain TCP or, with
+     feature "tls", TLS
+
+   Polled<T>, used by Connector's recv thread:
+   - retries reads that time out, so a quiet peer, or one
+     pausing mid-message, isn't taken for a failed one
+   - fails a timed out read once its stop flag is set,
+     so the thread ends without the peer closing
+*/
+
+use rust_traits::Transport;
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/*-- bytes flowing one way, closed by either side --*/
+#[derive(Debug, Default)]
+struct Pipe {
+    state: Mutex<(VecDeque<u8>, bool)>,  // (bytes, closed)
+    cv: Condvar,
+}
+impl Pipe {
+    fn close(&self) {
+        self.state.lock().unwrap().1 = true;
+        self.cv.notify_all();
+    }
+}
+
+/*-- shared by clones of one end --*/
+#[derive(Debug)]
+struct End {
+    rx: Arc<Pipe>,
+    tx: Arc<Pipe>,
+    addr: SocketAddr,
+}
+impl Drop for End {
+    fn drop(&mut self) {
+        self.rx.close();
+        self.tx.close();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryTransport {
+    end: Arc<End>,
+}
+impl MemoryTransport {
+    /*-- two connected ends, bytes written to one are read from the other --*/
+    pub fn pair() -> (MemoryTransport, MemoryTransport) {
+        static NEXT_PORT: AtomicU16 = AtomicU16::new(1);
+        let addr = SocketAddr::from(([0, 0, 0, 0], NEXT_PORT.fetch_add(1, Ordering::Relaxed)));
+        let a_to_b = Arc::new(Pipe::default());
+        let b_to_a = Arc::new(Pipe::default());
+        let a = End { rx: Arc::clone(&b_to_a), tx: Arc::clone(&a_to_b), addr };
+        let b = End { rx: a_to_b, tx: b_to_a, addr };
+        (
+            MemoryTransport { end: Arc::new(a) },
+            MemoryTransport { end: Arc::new(b) },
+        )
+    }
+}
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let pipe = &self.end.rx;
+        let mut state = pipe.state.lock().unwrap();
+        while state.0.is_empty() && !state.1 {
+            state = pipe.cv.wait(state).unwrap();
+        }
+        let n = buf.len().min(state.0.len());
+        for (dst, src) in buf.iter_mut().zip(state.0.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let pipe = &self.end.tx;
+        let mut state = pipe.state.lock().unwrap();
+        if state.1 {
+            return Err(Error::new(ErrorKind::BrokenPipe, "memory transport closed"));
+        }
+        state.0.extend(buf);
+        pipe.cv.notify_all();
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+impl Transport for MemoryTransport {
+    fn try_clone(&self) -> Result<Self> {
+        Ok(self.clone())
+    }
+    fn shutdown(&self, how: Shutdown) -> Result<()> {
+        if how != Shutdown::Write {
+            self.end.rx.close();
+        }
+        if how != Shutdown::Read {
+            self.end.tx.close();
+        }
+        Ok(())
+    }
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        Ok(self.end.addr)
+    }
+    fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.end.addr)
+    }
+}
+
+#[derive(Debug)]
+pub enum NetStream {
+    Tcp(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(crate::TlsStream),
+}
+impl Read for NetStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            NetStream::Tcp(s) => s.read(buf),
+            #[cfg(feature = "tls")]
+            NetStream::Tls(s) => s.read(buf),
+        }
+    }
+}
+impl Write for NetStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        match self {
+            NetStream::Tcp(s) => s.write(buf),
+            #[cfg(feature = "tls")]
+            NetStream::Tls(s) => s.write(buf),
+        }
+    }
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            NetStream::Tcp(s) => s.flush(),
+            #[cfg(feature = "tls")]
+            NetStream::Tls(s) => s.flush(),
+        }
+    }
+}
+impl Transport for NetStream {
+    fn try_clone(&self) -> Result<Self> {
+        match self {
+            NetStream::Tcp(s) => s.try_clone().map(NetStream::Tcp),
+            #[cfg(feature = "tls")]
+            NetStream::Tls(s) => s.try_clone().map(NetStream::Tls),
+        }
+    }
+    fn shutdown(&self, how: Shutdown) -> Result<()> {
+        match self {
+            NetStream::Tcp(s) => s.shutdown(how),
+            #[cfg(feature = "tls")]
+            NetStream::Tls(s) => s.shutdown(how),
+        }
+    }
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        match self {
+            NetStream::Tcp(s) => s.peer_addr(),
+            #[cfg(feature = "tls")]
+            NetStream::Tls(s) => s.peer_addr(),
+        }
+    }
+    fn local_addr(&self) -> Result<SocketAddr> {
+        match self {
+            NetStream::Tcp(s) => s.local_addr(),
+            #[cfg(feature = "tls")]
+            NetStream::Tls(s) => Transport::local_addr(s),
+        }
+    }
+    fn set_read_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        match self {
+            NetStream::Tcp(s) => s.set_read_timeout(dur),
+            #[cfg(feature = "tls")]
+            NetStream::Tls(s) => Transport::set_read_timeout(s, dur),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Polled<T: Transport> {
+    inner: T,
+    stop: Arc<AtomicBool>,
+}
+impl<T: Transport> Polled<T> {
+    pub(crate) fn new(inner: T, stop: &Arc<AtomicBool>) -> Polled<T> {
+        Polled { inner, stop: Arc::clone(stop) }
+    }
+}
+impl<T: Transport> Read for Polled<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            match self.inner.read(buf) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock
+                    || e.kind() == ErrorKind::TimedOut => {
+                    if self.stop.load(Ordering::Relaxed) {
+                        return Err(Error::new(ErrorKind::TimedOut, "read stopped by shut_down"));
+                    }
+                }
+                rslt => return rslt,
+            }
+        }
+    }
+}
+impl<T: Transport> Write for Polled<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.inner.write(buf)
+    }
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+impl<T: Transport> Transport for Polled<T> {
+    fn try_clone(&self) -> Result<Self> {
+        Ok(Polled { inner: self.inner.try_clone()?, stop: Arc::clone(&self.stop) })
+    }
+    fn shutdown(&self, how: Shutdown) -> Result<()> {
+        self.inner.shutdown(how)
+    }
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+    fn local_addr(&self) -> Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+    fn set_read_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(dur)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn pair_carries_bytes_both_ways() {
+        let (mut a, mut b) = MemoryTransport::pair();
+        a.write_all(b"ping").unwrap();
+        let mut buf = [0u8; 4];
+        b.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        b.write_all(b"pong").unwrap();
+        a.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"pong");
+        assert_eq!(a.peer_addr().unwrap(), b.peer_addr().unwrap());
+    }
+    #[test]
+    fn write_shutdown_and_drop_end_stream() {
+        let (mut a, mut b) = MemoryTransport::pair();
+        a.write_all(b"last").unwrap();
+        a.shutdown(Shutdown::Write).unwrap();
+        let mut text = Vec::new();
+        b.read_to_end(&mut text).unwrap();
+        assert_eq!(text, b"last");
+        /*-- a can still read until b goes away --*/
+        b.write_all(b"reply").unwrap();
+        drop(b);
+        text.clear();
+        a.read_to_end(&mut text).unwrap();
+        assert_eq!(text, b"reply");
+        assert_eq!(a.write(b"more").unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+}
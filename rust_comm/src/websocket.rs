@@ -0,0 +1,333 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::websocket.rs - browser clients for Listener  //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   With CommConfig::websocket(true), a Listener session
+   whose client opens with 'G', which can't start a
+   native frame, is read as an HTTP request:
+   - an Upgrade: websocket request is answered with the
+     RFC 6455 handshake, anything else with 400 Bad
+     Request, closing the session
+   - after the handshake, each text or binary message
+     the client sends, fragmented or not, is a TEXT
+     message whose body is its payload
+   - each message sent to the client is one frame, text
+     if its body is utf-8, else binary, flushed at once;
+     type, id, flags, and topic aren't sent
+   - PING frames are answered with PONG, and a CLOSE
+     frame is answered with CLOSE and ends the session,
+     as END does
+   - client frames must be masked, and messages longer
+     than MAX_MESSAGE_SIZE are refused, as for native
+     sessions
+   Other sessions are handled by the Listener's P as
+   always.
+*/
+
+use crate::*;
+
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/*-- longest HTTP upgrade request accepted --*/
+const MAX_REQUEST: usize = 8 * 1024;
+
+const OP_CONT: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+fn invalid(what: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("websocket: {}", what))
+}
+/*-- does the client open with an HTTP request? blocks for its first bytes --*/
+pub(crate) fn wants_upgrade<R: BufRead>(reader: &mut R) -> bool {
+    matches!(reader.fill_buf(), Ok(buf) if buf.first() == Some(&b'G'))
+}
+/*-----------------------------------------------------
+  read the client's upgrade request and answer it
+  - Err, after answering 400 Bad Request, if it isn't
+    a websocket upgrade
+*/
+pub(crate) fn accept<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> Result<()> {
+    let mut key = None;
+    let mut upgrade = false;
+    let mut read = 0;
+    let mut first = true;
+    loop {
+        let mut line = String::new();
+        let n = reader.by_ref().take((MAX_REQUEST - read) as u64).read_line(&mut line)?;
+        read += n;
+        if n == 0 || read >= MAX_REQUEST {
+            return refuse(writer, "upgrade request too long or cut short");
+        }
+        let line = line.trim_end();
+        if first {
+            if !line.starts_with("GET ") {
+                return refuse(writer, "not a GET request");
+            }
+            first = false;
+            continue;
+        }
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("upgrade") {
+                upgrade = value.eq_ignore_ascii_case("websocket");
+            }
+            else if name.eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.to_string());
+            }
+        }
+    }
+    let Some(key) = key.filter(|_| upgrade) else {
+        return refuse(writer, "not a websocket upgrade");
+    };
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    );
+    writer.write_all(response.as_bytes())?;
+    writer.flush()
+}
+fn refuse<W: Write>(writer: &mut W, why: &str) -> Result<()> {
+    let _ = writer.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n");
+    let _ = writer.flush();
+    Err(invalid(why))
+}
+/*-- Sec-WebSocket-Accept answering key, RFC 6455 section 4.2.2 --*/
+fn accept_key(key: &str) -> String {
+    let mut sha = sha1_smol::Sha1::new();
+    sha.update(key.as_bytes());
+    sha.update(WS_GUID.as_bytes());
+    base64(&sha.digest().bytes())
+}
+fn base64(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let b = [group[0], *group.get(1).unwrap_or(&0), *group.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(DIGITS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+/*-----------------------------------------------------
+  read frames until a whole text or binary message has
+  arrived, returned as a TEXT message
+  - answers PING on writer, and returns END for CLOSE,
+    after answering it
+*/
+pub(crate) fn read_message<M, R, T>(reader: &mut R, writer: &SharedWriter<T>) -> Result<M>
+where M: Msg + Default, R: Read, T: Transport
+{
+    let mut body = Vec::<u8>::new();
+    let mut started = false;
+    loop {
+        let (fin, opcode, payload) = read_frame(reader, MAX_MESSAGE_SIZE - body.len())?;
+        match opcode {
+            /*-- control frames may come between fragments --*/
+            OP_PING => {
+                let mut w = writer.lock().unwrap();
+                write_frame(&mut **w, OP_PONG, &payload)?;
+                w.flush()?;
+                continue;
+            }
+            OP_PONG => continue,
+            OP_CLOSE => {
+                let mut w = writer.lock().unwrap();
+                let _ = write_frame(&mut **w, OP_CLOSE, &payload).and_then(|_| w.flush());
+                let mut end = M::new(HEADER_SIZE);
+                end.set_type(MessageType::END as u8);
+                return Ok(end);
+            }
+            OP_TEXT | OP_BINARY if !started => {
+                started = true;
+                body.extend_from_slice(&payload);
+            }
+            OP_CONT if started => body.extend_from_slice(&payload),
+            _ => return Err(invalid("unexpected frame")),
+        }
+        if fin {
+            let mut msg = M::new(HEADER_SIZE + body.len());
+            msg.set_type(MessageType::TEXT as u8);
+            msg.set_content_bytes(&body);
+            return Ok(msg);
+        }
+    }
+}
+/*-- fin bit, opcode, and unmasked payload of one client frame --*/
+fn read_frame<R: Read>(reader: &mut R, max: usize) -> Result<(bool, u8, Vec<u8>)> {
+    let mut hdr = [0u8; 2];
+    reader.read_exact(&mut hdr)?;
+    let fin = hdr[0] & 0x80 != 0;
+    let opcode = hdr[0] & 0x0f;
+    if hdr[1] & 0x80 == 0 {
+        return Err(invalid("client frame not masked"));
+    }
+    let len = match hdr[1] & 0x7f {
+        126 => {
+            let mut ext = [0u8; 2];
+            reader.read_exact(&mut ext)?;
+            u16::from_be_bytes(ext) as u64
+        }
+        127 => {
+            let mut ext = [0u8; 8];
+            reader.read_exact(&mut ext)?;
+            u64::from_be_bytes(ext)
+        }
+        n => n as u64,
+    };
+    if len > max as u64 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            CommError::FrameTooLarge { len: len as usize, max }
+        ));
+    }
+    let mut mask = [0u8; 4];
+    reader.read_exact(&mut mask)?;
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+    Ok((fin, opcode, payload))
+}
+/*-- send msg's body as one frame, then flush --*/
+pub(crate) fn write_message<M: Msg, W: Write>(msg: &M, writer: &mut W) -> Result<()> {
+    let body = msg.get_content_bytes();
+    let opcode = if std::str::from_utf8(body).is_ok() { OP_TEXT } else { OP_BINARY };
+    write_frame(writer, opcode, body)?;
+    writer.flush()
+}
+/*-- server frames are sent whole and unmasked --*/
+fn write_frame<W: Write>(writer: &mut W, opcode: u8, payload: &[u8]) -> Result<()> {
+    let mut hdr = Vec::<u8>::with_capacity(10);
+    hdr.push(0x80 | opcode);
+    match payload.len() {
+        n if n < 126 => hdr.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            hdr.push(126);
+            hdr.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            hdr.push(127);
+            hdr.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    writer.write_all(&hdr)?;
+    writer.write_all(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    type L = MuteLog;
+    type P = CommProcessing<L>;
+
+    /*-- masked client frame --*/
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [1u8, 2, 3, 4];
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        match payload.len() {
+            n if n < 126 => frame.push(0x80 | n as u8),
+            n => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+    /*-- opcode and payload of an unmasked server frame --*/
+    fn server_frame<R: Read>(reader: &mut R) -> (u8, Vec<u8>) {
+        let mut hdr = [0u8; 2];
+        reader.read_exact(&mut hdr).unwrap();
+        assert_eq!(hdr[1] & 0x80, 0);
+        let len = match hdr[1] {
+            126 => {
+                let mut ext = [0u8; 2];
+                reader.read_exact(&mut ext).unwrap();
+                u16::from_be_bytes(ext) as usize
+            }
+            n => n as usize,
+        };
+        let mut payload = vec![0u8; len];
+        reader.read_exact(&mut payload).unwrap();
+        (hdr[0] & 0x0f, payload)
+    }
+    #[test]
+    fn browser_and_native_clients_share_a_listener() {
+        /*-- example from RFC 6455 section 1.3 --*/
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+        let mut lsnr = Listener::<P,L>::new_with_config(2, CommConfig::default().websocket(true));
+        let handle = lsnr.start("127.0.0.1:0").unwrap();
+        let addr = lsnr.local_addr().unwrap();
+        let mut ws = TcpStream::connect(addr).unwrap();
+        ws.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        ws.write_all(
+            b"GET /chat HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+              Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+              Sec-WebSocket-Version: 13\r\n\r\n"
+        ).unwrap();
+        let mut reader = BufReader::new(ws.try_clone().unwrap());
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8; 1];
+            reader.read_exact(&mut byte).unwrap();
+            response.push(byte[0]);
+        }
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+
+        ws.write_all(&client_frame(true, OP_TEXT, b"hello")).unwrap();
+        assert_eq!(server_frame(&mut reader), (OP_TEXT, b"hello".to_vec()));
+        /*-- fragmented, with a PING between fragments --*/
+        let long = vec![b'x'; 300];
+        ws.write_all(&client_frame(false, OP_TEXT, &long[..100])).unwrap();
+        ws.write_all(&client_frame(true, OP_PING, b"beat")).unwrap();
+        ws.write_all(&client_frame(true, OP_CONT, &long[100..])).unwrap();
+        assert_eq!(server_frame(&mut reader), (OP_PONG, b"beat".to_vec()));
+        assert_eq!(server_frame(&mut reader), (OP_TEXT, long));
+
+        /*-- native clients still use P's framing --*/
+        let conn = Connector::<P,Message,L>::new(addr).unwrap();
+        let msg = Message::builder().msg_type(MessageType::FLUSH).body("native").build().unwrap();
+        let reply = conn.post_and_wait(msg, Duration::from_secs(5)).unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "native");
+        assert_eq!(lsnr.broadcast(Message::create_msg_str_fit("all")), 2);
+        assert_eq!(server_frame(&mut reader), (OP_TEXT, b"all".to_vec()));
+        conn.shut_down();
+
+        ws.write_all(&client_frame(true, OP_CLOSE, &[])).unwrap();
+        assert_eq!(server_frame(&mut reader).0, OP_CLOSE);
+        let mut rest = Vec::new();
+        assert_eq!(reader.read_to_end(&mut rest).unwrap(), 0);
+
+        /*-- an HTTP request that isn't an upgrade is refused --*/
+        let mut http = TcpStream::connect(addr).unwrap();
+        http.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut answer = String::new();
+        http.read_to_string(&mut answer).unwrap();
+        assert!(answer.starts_with("HTTP/1.1 400"));
+        lsnr.stop();
+        let _ = handle.join();
+    }
+}
@@ -3,9 +3,32 @@
 //                                                         //
 // Jim Fawcett, https://JimFawcett.github.io, 20 Jul 2020  //
 /////////////////////////////////////////////////////////////
+/*
+   MuteLog discards messages, VerboseLog prints them, and
+   FileLog appends them to the file given to FileLog::open.
+   VerboseLog starts each line with an RFC 3339 UTC time,
+   formatted here or, with feature "chrono", by chrono.
+   Logger::write is an associated function, so FileLog's
+   file is held in a process-wide static.
+
+   Every write is whole: VerboseLog and FileLog write under
+   a lock, so text from concurrent writers never mixes.
+   ChannelLog hands text to a logging thread that prints
+   it, so callers never wait on a slow console.
+
+   VerboseLog and ChannelLog print to stdout unless given
+   another target by VerboseLog::set_target.  BufferLog
+   collects text in memory, for tests to inspect.
+*/
 
 use rust_traits::*;
 use std::fmt::*;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::time::SystemTime;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::mpsc::{channel, Sender};
 
 #[derive(Debug, Copy, Clone, Default)]
 pub struct Logr<L: Logger> {
@@ -28,14 +51,286 @@ impl Logger for MuteLog {
 pub struct VerboseLog {}
 impl Logger for  VerboseLog {
     fn write(msg: &str) {
-        print!("{}", msg);
+        print_whole(&timestamp_lines(msg, &rfc3339(SystemTime::now())));
+    }
+}
+
+/*-- where VerboseLog and ChannelLog text is written --*/
+pub type LogTarget = Arc<Mutex<dyn Write + Send>>;
+
+fn verbose_target() -> &'static Mutex<LogTarget> {
+    static TARGET: OnceLock<Mutex<LogTarget>> = OnceLock::new();
+    TARGET.get_or_init(|| Mutex::new(stdout_target()))
+}
+fn stdout_target() -> LogTarget {
+    Arc::new(Mutex::new(std::io::stdout()))
+}
+impl VerboseLog {
+    /*-- send later writes to target instead of stdout --*/
+    pub fn set_target(target: LogTarget) {
+        *verbose_target().lock().unwrap_or_else(|e| e.into_inner()) = target;
+    }
+    /*-- go back to writing to stdout --*/
+    pub fn reset_target() {
+        Self::set_target(stdout_target());
+    }
+}
+
+/*-- target's lock keeps concurrent lines whole --*/
+fn print_whole(text: &str) {
+    let target = verbose_target().lock().unwrap_or_else(|e| e.into_inner()).clone();
+    write_whole(&target, text);
+}
+fn write_whole(target: &Mutex<dyn Write + Send>, text: &str) {
+    let mut w = target.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = w.write_all(text.as_bytes());
+    let _ = w.flush();
+}
+
+/*-- text written by BufferLog --*/
+fn log_buffer() -> &'static Mutex<Vec<u8>> {
+    static BUFFER: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+    &BUFFER
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct BufferLog {}
+impl BufferLog {
+    /*-- everything written since last take --*/
+    pub fn contents() -> String {
+        let buf = log_buffer().lock().unwrap_or_else(|e| e.into_inner());
+        String::from_utf8_lossy(&buf).into_owned()
+    }
+    /*-- return contents and empty buffer --*/
+    pub fn take() -> String {
+        let buf = std::mem::take(&mut *log_buffer().lock().unwrap_or_else(|e| e.into_inner()));
+        String::from_utf8_lossy(&buf).into_owned()
+    }
+}
+impl Logger for BufferLog {
+    fn write(msg: &str) {
+        write_whole(log_buffer(), msg);
+    }
+}
+
+enum LogCmd {
+    Text(String),
+    Flush(Sender<()>),
+}
+/*-- starts logging thread on first use --*/
+fn log_thread() -> &'static Mutex<Sender<LogCmd>> {
+    static SENDER: OnceLock<Mutex<Sender<LogCmd>>> = OnceLock::new();
+    SENDER.get_or_init(|| {
+        let (tx, rx) = channel::<LogCmd>();
+        let _ = std::thread::Builder::new()
+            .name("commlog".to_string())
+            .spawn(move || {
+                for cmd in rx {
+                    match cmd {
+                        LogCmd::Text(text) => print_whole(&text),
+                        LogCmd::Flush(done) => { let _ = done.send(()); }
+                    }
+                }
+            });
+        Mutex::new(tx)
+    })
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ChannelLog {}
+impl ChannelLog {
+    /*-- wait until everything written so far is printed --*/
+    pub fn flush() {
+        let (tx, rx) = channel();
+        let sent = log_thread().lock().unwrap_or_else(|e| e.into_inner())
+                               .send(LogCmd::Flush(tx));
+        if sent.is_ok() {
+            let _ = rx.recv();
+        }
+    }
+}
+impl Logger for ChannelLog {
+    /*-- timestamped when written, printed by logging thread --*/
+    fn write(msg: &str) {
+        let text = timestamp_lines(msg, &rfc3339(SystemTime::now()));
+        let _ = log_thread().lock().unwrap_or_else(|e| e.into_inner())
+                            .send(LogCmd::Text(text));
+    }
+}
+
+/*-- put stamp after each newline, where a log line starts --*/
+fn timestamp_lines(msg: &str, stamp: &str) -> String {
+    msg.replace('\n', &format!("\n{} ", stamp))
+}
+
+/*-- UTC time as yyyy-mm-ddThh:mm:ss.mmmZ --*/
+#[cfg(feature = "chrono")]
+fn rfc3339(t: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(t)
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+#[cfg(not(feature = "chrono"))]
+fn rfc3339(t: SystemTime) -> String {
+    let d = t.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    /* civil date from days since 1970-01-01, proleptic Gregorian */
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, rem / 3600, rem % 3600 / 60, rem % 60, d.subsec_millis()
+    )
+}
+
+/*-- file shared by all FileLog writes, None until opened --*/
+fn log_file() -> &'static Mutex<Option<BufWriter<File>>> {
+    static FILE: OnceLock<Mutex<Option<BufWriter<File>>>> = OnceLock::new();
+    FILE.get_or_init(|| Mutex::new(None))
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FileLog {}
+impl FileLog {
+    /*-----------------------------------------------------
+      open, or create, file at path and append all later
+      FileLog writes to it
+      - replaces any file opened earlier
+      - file is flushed when the returned guard drops
+    */
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<FileLogGuard> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let old = log_file().lock().unwrap_or_else(|e| e.into_inner())
+                            .replace(BufWriter::new(file));
+        if let Some(mut old) = old {
+            let _ = old.flush();
+        }
+        Ok(FileLogGuard {})
+    }
+    /*-- write any buffered text to the file --*/
+    pub fn flush() {
+        if let Some(w) = log_file().lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            let _ = w.flush();
+        }
+    }
+}
+impl Logger for FileLog {
+    /*-- line buffered: flushes when msg ends a line --*/
+    fn write(msg: &str) {
+        if let Some(w) = log_file().lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            let _ = w.write_all(msg.as_bytes());
+            if msg.contains('\n') {
+                let _ = w.flush();
+            }
+        }
+    }
+}
+
+/*-- returned by FileLog::open, flushes log file on drop --*/
+#[derive(Debug)]
+pub struct FileLogGuard {}
+impl Drop for FileLogGuard {
+    fn drop(&mut self) {
+        FileLog::flush();
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+    #[test]
+    fn rfc3339_formats_utc_millis() {
+        let t = SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(1_600_000_000_123);
+        assert_eq!(rfc3339(t), "2020-09-13T12:26:40.123Z");
+        let t = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(951_782_400);
+        assert_eq!(rfc3339(t), "2000-02-29T00:00:00.000Z");
+        assert_eq!(rfc3339(SystemTime::UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+    #[test]
+    fn timestamp_starts_each_line() {
+        assert_eq!(timestamp_lines("\n  sent", "T"), "\nT   sent");
+        assert_eq!(timestamp_lines("no newline", "T"), "no newline");
+    }
+    /*-- FileLog writes to one file per process, so its tests take turns --*/
+    static FILE_TEST: Mutex<()> = Mutex::new(());
+    #[test]
+    fn buffer_log_captures_writes() {
+        BufferLog::write("\n  one");
+        BufferLog::write("\n  two");
+        assert_eq!(BufferLog::contents(), "\n  one\n  two");
+        assert_eq!(BufferLog::take(), "\n  one\n  two");
+        assert!(BufferLog::contents().is_empty());
+    }
+    #[test]
+    fn verbose_log_writes_to_target() {
+        let buf = Arc::new(Mutex::new(Vec::<u8>::new()));
+        VerboseLog::set_target(buf.clone());
+        VerboseLog::write("\n  to target");
+        VerboseLog::reset_target();
+        let text = String::from_utf8(buf.lock().unwrap().clone()).unwrap();
+        assert!(text.contains("Z   to target"), "{:?}", text);
+    }
+    #[test]
+    fn channel_log_flush_returns() {
+        ChannelLog::write("\n  channel log test line");
+        ChannelLog::flush();
+    }
+    #[test]
+    fn concurrent_writes_are_not_interleaved() {
+        let _turn = FILE_TEST.lock().unwrap_or_else(|e| e.into_inner());
+        let path = std::env::temp_dir()
+            .join(format!("rust_comm_logger_stress_{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let _guard = FileLog::open(&path).unwrap();
+            let handles: Vec<_> = (0..16).map(|t| {
+                std::thread::spawn(move || {
+                    for i in 0..1000 {
+                        FileLog::write(&format!("\nthread {:02} line {:04} end", t, i));
+                    }
+                })
+            }).collect();
+            for h in handles {
+                h.join().unwrap();
+            }
+        }
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = text.split('\n').skip(1).collect();
+        assert_eq!(lines.len(), 16_000);
+        let mut counts = [0usize; 16];
+        for line in lines {
+            let parts: Vec<&str> = line.split(' ').collect();
+            assert_eq!(parts.len(), 5, "corrupted line: {:?}", line);
+            assert_eq!((parts[0], parts[2], parts[4]), ("thread", "line", "end"));
+            counts[parts[1].parse::<usize>().unwrap()] += 1;
+        }
+        assert!(counts.iter().all(|n| *n == 1000));
+    }
+    #[test]
+    fn file_log_writes_to_file() {
+        let _turn = FILE_TEST.lock().unwrap_or_else(|e| e.into_inner());
+        let path = std::env::temp_dir()
+            .join(format!("rust_comm_logger_{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let _guard = FileLog::open(&path).unwrap();
+            FileLog::write("\n  first line");
+            FileLog::write("\n  second line");
+        }
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text, "\n  first line\n  second line");
+        std::fs::remove_file(&path).unwrap();
+    }
 }
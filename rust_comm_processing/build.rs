@@ -0,0 +1,23 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::build.rs - generate proto types   //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   With feature "proto", compiles proto/message.proto
+   into Rust types for ProtoCodec, using protox, so no
+   protoc install is needed.
+*/
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "proto")]
+    {
+        println!("cargo:rerun-if-changed=proto/message.proto");
+        let fds = protox::compile(["proto/message.proto"], ["proto"])
+            .expect("proto/message.proto should compile");
+        prost_build::Config::new()
+            .compile_fds(fds)
+            .expect("proto types should generate");
+    }
+}
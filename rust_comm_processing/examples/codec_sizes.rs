@@ -0,0 +1,61 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::codec_sizes.rs - wire size bench  //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   Encodes a representative message, a short text quote
+   with a topic and correlation id, and a small binary
+   reading, with each codec, printing bytes on the wire
+   and time to encode and decode a hundred thousand.
+
+   cargo run --release --example codec_sizes
+*/
+
+use rust_traits::*;
+use rust_comm_processing::*;
+use rust_message::*;
+use std::time::Instant;
+
+const N: usize = 100_000;
+
+fn measure<C: Codec<Message>>(name: &str, msgs: &[Message]) {
+    let mut sizes = Vec::<usize>::new();
+    for msg in msgs {
+        let mut wire = Vec::<u8>::new();
+        C::encode(msg, &mut wire).unwrap();
+        sizes.push(wire.len());
+    }
+    let start = Instant::now();
+    let mut wire = Vec::<u8>::new();
+    for i in 0..N {
+        C::encode(&msgs[i % msgs.len()], &mut wire).unwrap();
+    }
+    let mut reader = std::io::Cursor::new(&wire[..]);
+    for _ in 0..N {
+        let _: Message = C::decode(&mut reader).unwrap();
+    }
+    print!(
+        "\n  {:<13} text {:>4} bytes, binary {:>4} bytes, {:>5} ms",
+        name, sizes[0], sizes[1], start.elapsed().as_millis()
+    );
+}
+
+fn main() {
+    let mut text = Message::create_msg_str_fit("ACME bid 101.25 ask 101.30 size 500");
+    text.set_type(MessageType::TEXT as u8);
+    text.set_topic("quotes/ACME");
+    text.set_id(73_481);
+    let reading: Vec<u8> = (0..64u8).map(|i| i.wrapping_mul(37) | 0x80).collect();
+    let mut binary = Message::create_msg_bytes_fit(&reading);
+    binary.set_type(MessageType::DEFAULT as u8);
+    binary.set_id(73_482);
+
+    print!("\n  -- wire size of each codec, {} round trips timed --", N);
+    let msgs = [text, binary];
+    measure::<FrameCodec>("FrameCodec", &msgs);
+    measure::<BinaryCodec>("BinaryCodec", &msgs);
+    measure::<JsonCodec>("JsonCodec", &msgs);
+    measure::<MsgPackCodec>("MsgPackCodec", &msgs);
+    println!("\n");
+}
@@ -0,0 +1,64 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::pool_bench.rs - buffer pool bench //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   Decodes a million small FrameCodec messages, with
+   buffer pooling off, then on, counting heap allocations
+   with a counting global allocator.
+
+   cargo run --release --example pool_bench
+*/
+
+use rust_traits::*;
+use rust_comm_processing::*;
+use rust_message::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct Counting;
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const N: usize = 1_000_000;
+
+fn decode_all(wire: &[u8]) -> (usize, u128) {
+    let mut reader = std::io::Cursor::new(wire);
+    let before = ALLOCS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..N {
+        let msg: Message = FrameCodec::<MAX_FRAME_LEN>::decode(&mut reader).unwrap();
+        assert_eq!(msg.get_content_size(), 32);
+    }
+    (ALLOCS.load(Ordering::Relaxed) - before, start.elapsed().as_millis())
+}
+
+fn main() {
+    print!("\n  -- decoding {} messages with 32 byte bodies --", N);
+    let msg = Message::create_msg_bytes_fit(&[7u8; 32]);
+    let mut wire = Vec::<u8>::new();
+    for _ in 0..N {
+        FrameCodec::<MAX_FRAME_LEN>::encode(&msg, &mut wire).unwrap();
+    }
+    let size = buffer_pool_size();
+    set_buffer_pool_size(0);
+    let (off, off_ms) = decode_all(&wire);
+    print!("\n  pooling off: {:>8} allocations, {:>5} ms", off, off_ms);
+    set_buffer_pool_size(size);
+    let (on, on_ms) = decode_all(&wire);
+    print!("\n  pooling on:  {:>8} allocations, {:>5} ms", on, on_ms);
+    print!("\n  {:.1}% fewer allocations\n\n", 100.0 * (off - on) as f64 / off as f64);
+}
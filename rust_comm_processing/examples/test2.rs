@@ -20,30 +20,27 @@ fn handle_client(stream: &TcpStream) -> std::io::Result<()> {
     let mut buf_writer = BufWriter::new(stream.try_clone()?);
     let mut buf_reader = BufReader::new(stream.try_clone()?);
 
-    let rslt:Result<Message> = CommProcessing::<Log>::buf_recv_message(&mut buf_reader);
-    if rslt.is_err() {
+    let rslt:Result<(Message, usize)> = CommProcessing::<Log>::buf_recv_message(&mut buf_reader);
+    let Ok((msg, _)) = rslt else {
         print!("\n  recv_message error");
-        let err = std::io::Error::new(ErrorKind::Other, "recv error");
+        let err = std::io::Error::other("recv error");
         return Err(err);
-    }
-    else {
-        print!("\n  receiver received msg");
-        let msg = rslt.unwrap();
-        msg.show_message(8);
-        CommProcessing::<Log>::buf_send_message(&msg, &mut buf_writer)?;
-    }
+    };
+    print!("\n  receiver received msg");
+    msg.show_message(8);
+    CommProcessing::<Log>::buf_send_message(&msg, &mut buf_writer)?;
     Ok(())
 }
 fn start_listener(end_point: &str) -> std::io::Result<()> {
     let tcpl = TcpListener::bind(end_point)?;
-    for stream in tcpl.incoming() {
+    /*-- only one connection for testing --*/
+    if let Some(stream) = tcpl.incoming().next() {
         print!("\n  listener accepted connection");
         let rslt = handle_client(&stream?);
         if rslt.is_err() {
             print!("\n  error in handle_client");
             let _ = std::io::stdout().flush();
         }
-        break;  // only one connection for testing
     }
     Ok(())
 }
@@ -65,12 +62,12 @@ fn construction(addr: &'static str) -> Result<()> {
     let stream = TcpStream::connect(addr)?;
     let mut buf_writer = BufWriter::new(stream.try_clone()?);
     let mut buf_reader = BufReader::new(stream.try_clone()?);
-    CommProcessing::<Log>::buf_send_message(&msg, &mut buf_writer)?;
-    Log::write(&format!("\n  sent message with len: {:?}",msg.len()));
+    let sent = CommProcessing::<Log>::buf_send_message(&msg, &mut buf_writer)?;
+    Log::write(&format!("\n  sent message with len: {:?}, {} bytes on the wire",msg.len(), sent));
     let _ = std::io::stdout().flush();
     println!();
 
-    let mut msg:Message = CommProcessing::<Log>::buf_recv_message(&mut buf_reader)?;
+    let (mut msg, _):(Message, usize) = CommProcessing::<Log>::buf_recv_message(&mut buf_reader)?;
     print!("\n\n  connector received reply msg");
     let _ = std::io::stdout().flush();
 
@@ -80,7 +77,7 @@ fn construction(addr: &'static str) -> Result<()> {
 
     msg.set_type(MessageType::QUIT as u8);
     CommProcessing::<Log>::buf_send_message(&msg, &mut buf_writer)?;
-    let msg:Message = CommProcessing::<Log>::buf_recv_message(&mut buf_reader)?;
+    let (msg, _):(Message, usize) = CommProcessing::<Log>::buf_recv_message(&mut buf_reader)?;
     msg.show_message(8);
     let _ = handle.join();
     Ok(())
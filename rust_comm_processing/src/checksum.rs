@@ -0,0 +1,72 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::checksum.rs - CRC32 of bodies     //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   A sender sets FLAG_CRC32 on a message to have its body
+   checked:
+   - the body is sent followed by its 4 byte big-endian
+     CRC32, computed after compression, so the check
+     covers the bytes on the wire
+   - the receiver strips the CRC32 and fails with
+     CommError::ChecksumMismatch if it doesn't match
+   - messages without the flag are sent as before, so
+     peers that don't use checksums interoperate
+   - the flag stays set on the received message, so a
+     reply built from it is checked too
+*/
+
+use rust_traits::*;
+use rust_message::*;
+use std::io::{Result, Error, ErrorKind};
+
+const CRC_SIZE: usize = 4;
+
+/*-- copy of msg with CRC32 appended to body, if flagged --*/
+pub(crate) fn seal<M: Msg>(msg: &M) -> Option<M> {
+    if msg.get_flags() & FLAG_CRC32 == 0 {
+        return None;
+    }
+    let body = msg.get_content_bytes();
+    let mut sealed = M::new(HEADER_SIZE + body.len() + CRC_SIZE);
+    sealed.set_type(msg.get_type());
+    sealed.set_id(msg.get_id());
+    sealed.set_flags(msg.get_flags());
+    sealed.set_topic(msg.get_topic());
+    sealed.set_seq(msg.get_seq());
+    let mut content = Vec::with_capacity(body.len() + CRC_SIZE);
+    content.extend_from_slice(body);
+    content.extend_from_slice(&crc32fast::hash(body).to_be_bytes());
+    sealed.set_content_bytes(&content);
+    Some(sealed)
+}
+
+/*-- msg with CRC32 checked and stripped, if flagged --*/
+pub(crate) fn verify<M: Msg>(msg: M) -> Result<M> {
+    if msg.get_flags() & FLAG_CRC32 == 0 {
+        return Ok(msg);
+    }
+    let content = msg.get_content_bytes();
+    if content.len() < CRC_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "checksum flagged but missing"));
+    }
+    let (body, crc) = content.split_at(content.len() - CRC_SIZE);
+    let mut sent = [0u8; CRC_SIZE];
+    sent.copy_from_slice(crc);
+    let expected = u32::from_be_bytes(sent);
+    let actual = crc32fast::hash(body);
+    if expected != actual {
+        return Err(Error::new(
+            ErrorKind::InvalidData, CommError::ChecksumMismatch { expected, actual }
+        ));
+    }
+    let mut out = M::new(HEADER_SIZE + body.len());
+    out.set_type(msg.get_type());
+    out.set_id(msg.get_id());
+    out.set_flags(msg.get_flags());
+    out.set_topic(msg.get_topic());
+    out.set_seq(msg.get_seq());
+    out.set_content_bytes(body);
+    Ok(out)
+}
@@ -0,0 +1,190 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::chunk.rs - large message chunking //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   Messages with bodies longer than CHUNK_SIZE are sent as
+   a sequence of chunk messages:
+   - types CHUNK_START, CHUNK_CONT, ..., CHUNK_END
+   - each chunk carries the original message's id and
+     flags, and CHUNK_START its topic
+   - each chunk body holds the original message type, a
+     4 byte big-endian chunk index, then up to CHUNK_SIZE
+     bytes of the original body
+   The receiver reassembles the chunks into the original
+   message, refusing to grow one past its max length, or
+   to decode a frame longer than that, so a sender can't
+   exhaust receiver memory.
+
+   A writer must send all of one message's chunks before
+   starting another message, which rust_comm ensures by
+   holding the writer's lock for each send.
+*/
+
+use rust_traits::*;
+use rust_message::*;
+use std::io::{Read, Result, Write, Error, ErrorKind};
+
+/*-- largest body sent in a single message --*/
+pub const CHUNK_SIZE: usize = 64 * 1024;
+/*-- default limit on a reassembled message's body --*/
+pub const MAX_REASSEMBLY_LEN: usize = 256 * 1024 * 1024;
+const CHUNK_HDR_SIZE: usize = 1 + 4;
+
+fn is_chunk(msg_type: u8) -> bool {
+    msg_type == MessageType::CHUNK_START as u8
+        || msg_type == MessageType::CHUNK_CONT as u8
+        || msg_type == MessageType::CHUNK_END as u8
+}
+/*-- encode msg, in chunks if its body is large --*/
+pub(crate) fn send_chunked<M, C, W>(msg: &M, writer: &mut W) -> Result<()>
+where 
+    M: Msg + Clone + Send + Default,
+    C: Codec<M>,
+    W: Write
+{
+    let body = msg.get_content_bytes();
+    if body.len() <= CHUNK_SIZE {
+        return C::encode(msg, writer);
+    }
+    let last = (body.len() - 1) / CHUNK_SIZE;
+    for (index, piece) in body.chunks(CHUNK_SIZE).enumerate() {
+        let chunk_type = match index {
+            0 => MessageType::CHUNK_START,
+            i if i == last => MessageType::CHUNK_END,
+            _ => MessageType::CHUNK_CONT,
+        };
+        let mut content = Vec::<u8>::with_capacity(CHUNK_HDR_SIZE + piece.len());
+        content.push(msg.get_type());
+        content.extend_from_slice(&(index as u32).to_be_bytes());
+        content.extend_from_slice(piece);
+        let mut chunk = M::new(HEADER_SIZE + content.len());
+        chunk.set_type(chunk_type as u8);
+        chunk.set_id(msg.get_id());
+        chunk.set_flags(msg.get_flags());
+        if index == 0 {
+            chunk.set_topic(msg.get_topic());
+        }
+        chunk.set_content_bytes(&content);
+        C::encode(&chunk, writer)?;
+    }
+    Ok(())
+}
+/*-----------------------------------------------------
+  encode a message from its parts, in chunks if body is
+  large, as send_chunked does
+  - body is written from the borrowed slice when C has
+    an encode_header, else copied into an M
+*/
+pub(crate) fn send_chunked_bytes<M, C, W>(
+    msg_type: u8, id: u64, flags: u8, body: &[u8], writer: &mut W
+) -> Result<()>
+where 
+    M: Msg + Clone + Send + Default,
+    C: Codec<M>,
+    W: Write
+{
+    if body.len() <= CHUNK_SIZE {
+        return send_parts::<M,C,_>(msg_type, id, flags, &[body], writer);
+    }
+    let last = (body.len() - 1) / CHUNK_SIZE;
+    for (index, piece) in body.chunks(CHUNK_SIZE).enumerate() {
+        let chunk_type = match index {
+            0 => MessageType::CHUNK_START,
+            i if i == last => MessageType::CHUNK_END,
+            _ => MessageType::CHUNK_CONT,
+        };
+        let mut chunk_hdr = [0u8; CHUNK_HDR_SIZE];
+        chunk_hdr[0] = msg_type;
+        chunk_hdr[1..].copy_from_slice(&(index as u32).to_be_bytes());
+        send_parts::<M,C,_>(chunk_type as u8, id, flags, &[&chunk_hdr, piece], writer)?;
+    }
+    Ok(())
+}
+/*-- one message whose content is parts, concatenated --*/
+fn send_parts<M, C, W>(
+    msg_type: u8, id: u64, flags: u8, parts: &[&[u8]], writer: &mut W
+) -> Result<()>
+where 
+    M: Msg + Clone + Send + Default,
+    C: Codec<M>,
+    W: Write
+{
+    let len = parts.iter().map(|p| p.len()).sum();
+    if let Some(hdr) = C::encode_header(msg_type, id, flags, len)? {
+        writer.write_all(&hdr)?;
+        for part in parts {
+            writer.write_all(part)?;
+        }
+        return Ok(());
+    }
+    let mut msg = M::new(HEADER_SIZE + len);
+    msg.set_type(msg_type);
+    msg.set_id(id);
+    msg.set_flags(flags);
+    msg.set_content_bytes(&parts.concat());
+    C::encode(&msg, writer)
+}
+/*-- decode one message, reassembling it if chunked --*/
+pub(crate) fn recv_chunked<M, C, R>(reader: &mut R, max_len: usize) -> Result<M>
+where 
+    M: Msg + Clone + Send + Default,
+    C: Codec<M>,
+    R: Read
+{
+    let first: M = C::decode_max(reader, max_len)?;
+    let first_type = first.get_type();
+    if !is_chunk(first_type) {
+        return Ok(first);
+    }
+    if first_type != MessageType::CHUNK_START as u8 {
+        return Err(bad_chunk("chunk received without CHUNK_START"));
+    }
+    let (msg_type, _) = parse_chunk(&first, 0)?;
+    let mut body = first.get_content_bytes()[CHUNK_HDR_SIZE..].to_vec();
+    let mut index = 0u32;
+    loop {
+        let chunk: M = C::decode_max(reader, max_len)?;
+        let chunk_type = chunk.get_type();
+        if chunk_type != MessageType::CHUNK_CONT as u8 
+            && chunk_type != MessageType::CHUNK_END as u8 {
+            return Err(bad_chunk("message interleaved with chunks"));
+        }
+        index += 1;
+        let (_, piece) = parse_chunk(&chunk, index)?;
+        if body.len() + piece.len() > max_len {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                CommError::FrameTooLarge { len: body.len() + piece.len(), max: max_len }
+            ));
+        }
+        body.extend_from_slice(piece);
+        if chunk_type == MessageType::CHUNK_END as u8 {
+            break;
+        }
+    }
+    let mut msg = M::new(HEADER_SIZE + body.len());
+    msg.set_type(msg_type);
+    msg.set_id(first.get_id());
+    msg.set_flags(first.get_flags());
+    msg.set_topic(first.get_topic());
+    msg.set_content_bytes(&body);
+    Ok(msg)
+}
+/*-- returns original type and body piece, checking index --*/
+fn parse_chunk<M: Msg>(chunk: &M, expected: u32) -> Result<(u8, &[u8])> {
+    let content = chunk.get_content_bytes();
+    if content.len() < CHUNK_HDR_SIZE {
+        return Err(bad_chunk("chunk too short"));
+    }
+    let mut idx = [0u8; 4];
+    idx.copy_from_slice(&content[1..CHUNK_HDR_SIZE]);
+    if u32::from_be_bytes(idx) != expected {
+        return Err(bad_chunk("chunk out of sequence"));
+    }
+    Ok((content[0], &content[CHUNK_HDR_SIZE..]))
+}
+fn bad_chunk(what: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, what)
+}
@@ -0,0 +1,547 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::codec.rs - wire formats           //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   Codecs implement rust_traits::Codec<M>, used by
+   CommProcessing<L,C> to frame messages on the wire.
+
+   FrameCodec<MAX>:
+   - default format, 4 byte big-endian frame length, 
+     then that many bytes: 1 byte type, 8 byte 
+     big-endian correlation id, 1 byte flags, and the
+     content
+   - a message with a topic has FLAG_TOPIC set and its
+     topic, 1 byte length then utf-8 text, precedes its
+     content, so frames without topics are unchanged
+   - frames longer than MAX, MAX_FRAME_LEN by default,
+     are rejected with CommError::FrameTooLarge, so a 
+     corrupt or hostile length can't force a huge 
+     allocation, as are frames past decode_max's limit
+
+   BinaryCodec:
+   - message bytes as held by Msg: 1 byte type, 8 byte
+     big-endian content size, 8 byte big-endian id, 1 
+     byte flags, then topic, if any, as FrameCodec, then
+     content
+   - content sizes past MAX_FRAME_LEN, or decode_max's
+     limit, are rejected as FrameCodec rejects lengths
+
+   JsonCodec:
+   - newline-delimited JSON, one object per message:
+       {"type":1,"id":7,"body":"some text"}
+   - id and flags are omitted when 0, and topic when
+     empty
+   - content that isn't utf-8 is sent as "bytes", an 
+     array of numbers, in place of "body"
+   - newlines in the body are escaped by JSON, so each
+     message is exactly one line
+   - decode_max stops reading a line too long to hold
+     max bytes of content, escaped
+
+   MsgPackCodec:
+   - compact binary alternative to JsonCodec, 4 byte
+     big-endian length, then the message as a MessagePack
+     array: type, id, flags, topic, and content bytes
+   - lengths past MAX_FRAME_LEN, or decode_max's limit,
+     are rejected as FrameCodec rejects them
+
+   ProtoCodec, feature "proto":
+   - protobuf WireMessage, defined in proto/message.proto,
+     with type, body, correlation id, flags, and topic
+   - each preceded by its varint length, protobuf's
+     standard delimiting, so Go and Java services read
+     and write it with their own generated types
+   - lengths past MAX_FRAME_LEN, or decode_max's limit,
+     are rejected before the message is read
+
+   TextLineCodec:
+   - plain text lines, for talking to a Listener with
+     telnet or netcat, with CommConfig::handshake(false)
+     as they send no protocol preamble
+   - each line received, less its \n or \r\n, is a TEXT
+     message with the line as body
+   - each message sent is its body then \n, flushed at
+     once so a person typing sees the reply
+   - type, id, flags, topic, and seq aren't sent, so it
+     suits debugging, not Connectors
+   - lines longer than CHUNK_SIZE are refused, replies to
+     them would be chunked
+*/
+
+use rust_traits::*;
+use rust_message::*;
+use std::io::{Read, Result, Write, Error, ErrorKind};
+use serde::{Serialize, Deserialize};
+use crate::pool::*;
+use crate::chunk::CHUNK_SIZE;
+
+/*-- default limit on FrameCodec frame length --*/
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+const LEN_SIZE: usize = 4;
+/*-- type, id, and flags precede content in a frame --*/
+const FRAME_HDR_SIZE: usize = TYPE_SIZE + ID_SIZE + FLAGS_SIZE;
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FrameCodec<const MAX: usize = MAX_FRAME_LEN>;
+
+fn too_large(kind: ErrorKind, len: usize, max: usize) -> Error {
+    Error::new(kind, CommError::FrameTooLarge { len, max })
+}
+/*-- msg's flags as sent, and its length-prefixed topic, if any --*/
+fn wire_topic<M: Msg>(msg: &M) -> Result<(u8, Vec<u8>)> {
+    let topic = msg.get_topic().as_bytes();
+    let flags = msg.get_flags() & !FLAG_TOPIC;
+    if topic.is_empty() {
+        return Ok((flags, Vec::new()));
+    }
+    if topic.len() > TOPIC_MAX {
+        return Err(Error::new(ErrorKind::InvalidInput, format!(
+            "topic of {} bytes exceeds limit of {}", topic.len(), TOPIC_MAX
+        )));
+    }
+    let mut prefixed = Vec::with_capacity(1 + topic.len());
+    prefixed.push(topic.len() as u8);
+    prefixed.extend_from_slice(topic);
+    Ok((flags | FLAG_TOPIC, prefixed))
+}
+/*-- topic at start of data, if flagged, and the rest of data --*/
+fn split_topic(flags: u8, data: &[u8]) -> Result<(&str, &[u8])> {
+    if flags & FLAG_TOPIC == 0 {
+        return Ok(("", data));
+    }
+    let len = *data.first()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "topic flagged but missing"))? as usize;
+    if data.len() < 1 + len {
+        return Err(Error::new(ErrorKind::InvalidData, "topic truncated"));
+    }
+    let topic = std::str::from_utf8(&data[1..1 + len])
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok((topic, &data[1 + len..]))
+}
+impl<M, const MAX: usize> Codec<M> for FrameCodec<MAX>
+where M: Msg + Clone + Send + Default
+{
+    fn encode<W: Write>(msg: &M, writer: &mut W) -> Result<()> {
+        let content = msg.get_content_bytes();
+        let (flags, topic) = wire_topic(msg)?;
+        let len = FRAME_HDR_SIZE + topic.len() + content.len();
+        if len > MAX || len > u32::MAX as usize {
+            return Err(too_large(ErrorKind::InvalidInput, len, MAX));
+        }
+        let mut frame = Vec::<u8>::with_capacity(LEN_SIZE + len);
+        frame.extend_from_slice(&(len as u32).to_be_bytes());
+        frame.push(msg.get_type());
+        frame.extend_from_slice(&msg.get_id().to_be_bytes());
+        frame.push(flags);
+        frame.extend_from_slice(&topic);
+        frame.extend_from_slice(content);
+        writer.write_all(&frame)
+    }
+    fn encode_header(
+        msg_type: u8, id: u64, flags: u8, content_len: usize
+    ) -> Result<Option<Vec<u8>>> {
+        let len = FRAME_HDR_SIZE + content_len;
+        if len > MAX || len > u32::MAX as usize {
+            return Err(too_large(ErrorKind::InvalidInput, len, MAX));
+        }
+        let mut hdr = Vec::<u8>::with_capacity(LEN_SIZE + FRAME_HDR_SIZE);
+        hdr.extend_from_slice(&(len as u32).to_be_bytes());
+        hdr.push(msg_type);
+        hdr.extend_from_slice(&id.to_be_bytes());
+        hdr.push(flags & !FLAG_TOPIC);
+        Ok(Some(hdr))
+    }
+    fn decode<R: Read>(reader: &mut R) -> Result<M> {
+        Self::decode_max(reader, MAX)
+    }
+    fn decode_max<R: Read>(reader: &mut R, max: usize) -> Result<M> {
+        let mut len_buf = [0u8; LEN_SIZE];
+        reader.read_exact(&mut len_buf)?;
+        let len = u32::from_be_bytes(len_buf) as usize;
+        if len < FRAME_HDR_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "frame too short"));
+        }
+        if len > MAX {
+            return Err(too_large(ErrorKind::InvalidData, len, MAX));
+        }
+        if len - FRAME_HDR_SIZE > max {
+            return Err(too_large(ErrorKind::InvalidData, len - FRAME_HDR_SIZE, max));
+        }
+        let mut frame = take_buffer(len);
+        reader.read_exact(&mut frame)?;
+        let flags = frame[TYPE_SIZE + ID_SIZE];
+        let (topic, body) = split_topic(flags, &frame[FRAME_HDR_SIZE..])?;
+        let mut id = [0u8; ID_SIZE];
+        id.copy_from_slice(&frame[TYPE_SIZE..TYPE_SIZE + ID_SIZE]);
+        let mut msg = M::new(HEADER_SIZE + body.len());
+        msg.set_type(frame[0]);
+        msg.set_id(u64::from_be_bytes(id));
+        msg.set_flags(flags & !FLAG_TOPIC);
+        msg.set_topic(topic);
+        msg.set_content_bytes(body);
+        recycle(frame);
+        Ok(msg)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct BinaryCodec;
+
+impl<M> Codec<M> for BinaryCodec
+where M: Msg + Clone + Send + Default
+{
+    fn encode<W: Write>(msg: &M, writer: &mut W) -> Result<()> {
+        let (flags, topic) = wire_topic(msg)?;
+        if topic.is_empty() {
+            return writer.write_all(msg.get_ref());
+        }
+        let bytes = msg.get_bytes();
+        let mut buf = Vec::<u8>::with_capacity(bytes.len() + topic.len());
+        buf.extend_from_slice(&bytes[..HEADER_SIZE]);
+        buf[FLAGS_OFFSET] = flags;
+        buf.extend_from_slice(&topic);
+        buf.extend_from_slice(msg.get_content_bytes());
+        writer.write_all(&buf)
+    }
+    fn encode_header(
+        msg_type: u8, id: u64, flags: u8, content_len: usize
+    ) -> Result<Option<Vec<u8>>> {
+        let mut hdr = vec![0u8; HEADER_SIZE];
+        hdr[0] = msg_type;
+        hdr[TYPE_SIZE..ID_OFFSET].copy_from_slice(&content_len.to_be_bytes());
+        hdr[ID_OFFSET..FLAGS_OFFSET].copy_from_slice(&id.to_be_bytes());
+        hdr[FLAGS_OFFSET] = flags & !FLAG_TOPIC;
+        Ok(Some(hdr))
+    }
+    fn decode<R: Read>(reader: &mut R) -> Result<M> {
+        Self::decode_max(reader, MAX_FRAME_LEN)
+    }
+    fn decode_max<R: Read>(reader: &mut R, max: usize) -> Result<M> {
+        let buf = &mut [0u8; HEADER_SIZE];
+        reader.read_exact(buf)?;
+        let msgtype = buf[0];
+        let sz_slice = &buf[TYPE_SIZE..ID_OFFSET];
+        let mut dst = [0u8;8];
+        dst.clone_from_slice(sz_slice); // array from byte slice
+        let bdysz = usize::from_be_bytes(dst);   // usize from byte array
+        let max = max.min(MAX_FRAME_LEN);
+        if bdysz > max {
+            return Err(too_large(ErrorKind::InvalidData, bdysz, max));
+        }
+        let mut id = [0u8;8];
+        id.clone_from_slice(&buf[ID_OFFSET..FLAGS_OFFSET]);
+        let flags = buf[FLAGS_OFFSET];
+        let mut topic = Vec::new();
+        if flags & FLAG_TOPIC != 0 {
+            let mut len = [0u8; 1];
+            reader.read_exact(&mut len)?;
+            topic.push(len[0]);
+            topic.resize(1 + len[0] as usize, 0);
+            reader.read_exact(&mut topic[1..])?;
+        }
+        let (topic, _) = split_topic(flags, &topic)?;
+
+        let mut bdy = take_buffer(bdysz);
+        reader.read_exact(&mut bdy)?;        
+        let msg_size = HEADER_SIZE + bdysz;
+        let mut msg = M::new(msg_size);
+        msg.set_type(msgtype);
+        msg.set_id(u64::from_be_bytes(id));
+        msg.set_flags(flags & !FLAG_TOPIC);
+        msg.set_topic(topic);
+        msg.set_content_bytes(&bdy);
+        recycle(bdy);
+        Ok(msg)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct JsonCodec;
+
+/*-- JSON form of a message --*/
+#[derive(Serialize, Deserialize)]
+struct JsonMsg {
+    #[serde(rename = "type")]
+    msg_type: u8,
+    #[serde(default, skip_serializing_if = "is_unset")]
+    id: u64,
+    #[serde(default, skip_serializing_if = "no_flags")]
+    flags: u8,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    topic: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bytes: Option<Vec<u8>>,
+}
+
+/*-- room for a JSON line's field names, type, id, flags, and topic --*/
+const JSON_LINE_SLACK: usize = 1024;
+
+fn is_unset(id: &u64) -> bool {
+    *id == 0
+}
+fn no_flags(flags: &u8) -> bool {
+    *flags == 0
+}
+impl<M> Codec<M> for JsonCodec
+where M: Msg + Clone + Send + Default
+{
+    fn encode<W: Write>(msg: &M, writer: &mut W) -> Result<()> {
+        let content = msg.get_content_bytes();
+        let (body, bytes) = match std::str::from_utf8(content) {
+            Ok(s) => (Some(s.to_string()), None),
+            Err(_) => (None, Some(content.to_vec())),
+        };
+        let jmsg = JsonMsg { 
+            msg_type: msg.get_type(), id: msg.get_id(), flags: msg.get_flags(), 
+            topic: msg.get_topic().to_string(), body, bytes 
+        };
+        let mut line = serde_json::to_vec(&jmsg)?;
+        line.push(b'\n');
+        writer.write_all(&line)
+    }
+    /*-- reads up to and including newline --*/
+    fn decode<R: Read>(reader: &mut R) -> Result<M> {
+        Self::decode_max(reader, usize::MAX)
+    }
+    fn decode_max<R: Read>(reader: &mut R, max: usize) -> Result<M> {
+        /*-- escaping grows content at most 6 times, e.g., \u0000 --*/
+        let max_line = max.saturating_mul(6).saturating_add(JSON_LINE_SLACK);
+        let mut line = Vec::<u8>::new();
+        let mut byte = [0u8; 1];
+        loop {
+            if line.len() > max_line {
+                return Err(too_large(ErrorKind::InvalidData, line.len(), max_line));
+            }
+            match reader.read(&mut byte) {
+                Ok(0) => return Err(Error::new(
+                    ErrorKind::UnexpectedEof, "stream closed before end of JSON line"
+                )),
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+            if byte[0] == b'\n' {
+                break;
+            }
+            line.push(byte[0]);
+        }
+        let jmsg: JsonMsg = serde_json::from_slice(&line)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let content = match (&jmsg.body, &jmsg.bytes) {
+            (Some(s), _) => s.as_bytes(),
+            (None, Some(b)) => &b[..],
+            (None, None) => &[],
+        };
+        if content.len() > max {
+            return Err(too_large(ErrorKind::InvalidData, content.len(), max));
+        }
+        let mut msg = M::new(HEADER_SIZE + content.len());
+        msg.set_type(jmsg.msg_type);
+        msg.set_id(jmsg.id);
+        msg.set_flags(jmsg.flags);
+        msg.set_topic(&jmsg.topic);
+        msg.set_content_bytes(content);
+        Ok(msg)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MsgPackCodec;
+
+/*-- MessagePack form of a message, an array in field order --*/
+#[derive(Serialize, Deserialize)]
+struct PackMsg<'a> {
+    msg_type: u8,
+    id: u64,
+    flags: u8,
+    topic: &'a str,
+    #[serde(with = "serde_bytes")]
+    content: &'a [u8],
+}
+/*-- room for a packed message's fields other than content --*/
+const PACK_SLACK: usize = 32 + TOPIC_MAX;
+
+impl<M> Codec<M> for MsgPackCodec
+where M: Msg + Clone + Send + Default
+{
+    fn encode<W: Write>(msg: &M, writer: &mut W) -> Result<()> {
+        let pmsg = PackMsg {
+            msg_type: msg.get_type(), id: msg.get_id(), flags: msg.get_flags(),
+            topic: msg.get_topic(), content: msg.get_content_bytes()
+        };
+        let packed = rmp_serde::to_vec(&pmsg)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        if packed.len() > MAX_FRAME_LEN {
+            return Err(too_large(ErrorKind::InvalidInput, packed.len(), MAX_FRAME_LEN));
+        }
+        let mut frame = Vec::<u8>::with_capacity(LEN_SIZE + packed.len());
+        frame.extend_from_slice(&(packed.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&packed);
+        writer.write_all(&frame)
+    }
+    fn decode<R: Read>(reader: &mut R) -> Result<M> {
+        Self::decode_max(reader, MAX_FRAME_LEN)
+    }
+    fn decode_max<R: Read>(reader: &mut R, max: usize) -> Result<M> {
+        let mut len_buf = [0u8; LEN_SIZE];
+        reader.read_exact(&mut len_buf)?;
+        let len = u32::from_be_bytes(len_buf) as usize;
+        let max = max.saturating_add(PACK_SLACK).min(MAX_FRAME_LEN);
+        if len > max {
+            return Err(too_large(ErrorKind::InvalidData, len, max));
+        }
+        let mut packed = take_buffer(len);
+        reader.read_exact(&mut packed)?;
+        let pmsg: PackMsg = rmp_serde::from_slice(&packed)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        if pmsg.content.len() > max {
+            return Err(too_large(ErrorKind::InvalidData, pmsg.content.len(), max));
+        }
+        let mut msg = M::new(HEADER_SIZE + pmsg.content.len());
+        msg.set_type(pmsg.msg_type);
+        msg.set_id(pmsg.id);
+        msg.set_flags(pmsg.flags);
+        msg.set_topic(pmsg.topic);
+        msg.set_content_bytes(pmsg.content);
+        recycle(packed);
+        Ok(msg)
+    }
+}
+
+/*-- types generated by build.rs from proto/message.proto --*/
+#[cfg(feature = "proto")]
+#[allow(clippy::all)]
+mod wire {
+    include!(concat!(env!("OUT_DIR"), "/rust_comm.rs"));
+}
+
+#[cfg(feature = "proto")]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ProtoCodec;
+
+/*-- longest varint length prefix --*/
+#[cfg(feature = "proto")]
+const VARINT_MAX: usize = 10;
+/*-- room for a WireMessage's fields other than body --*/
+#[cfg(feature = "proto")]
+const PROTO_SLACK: usize = 32 + TOPIC_MAX;
+
+#[cfg(feature = "proto")]
+impl<M> Codec<M> for ProtoCodec
+where M: Msg + Clone + Send + Default
+{
+    fn encode<W: Write>(msg: &M, writer: &mut W) -> Result<()> {
+        use prost::Message;
+        let wmsg = wire::WireMessage {
+            r#type: msg.get_type() as i32,
+            body: msg.get_content_bytes().to_vec(),
+            correlation_id: msg.get_id(),
+            flags: msg.get_flags() as u32,
+            topic: msg.get_topic().to_string(),
+        };
+        if wmsg.encoded_len() > MAX_FRAME_LEN {
+            return Err(too_large(ErrorKind::InvalidInput, wmsg.encoded_len(), MAX_FRAME_LEN));
+        }
+        writer.write_all(&wmsg.encode_length_delimited_to_vec())
+    }
+    fn decode<R: Read>(reader: &mut R) -> Result<M> {
+        Self::decode_max(reader, MAX_FRAME_LEN)
+    }
+    fn decode_max<R: Read>(reader: &mut R, max: usize) -> Result<M> {
+        use prost::Message;
+        use std::convert::TryFrom;
+        let invalid = |why: &str| Error::new(ErrorKind::InvalidData, why.to_string());
+        let mut varint = [0u8; VARINT_MAX];
+        let mut n = 0;
+        loop {
+            if n == VARINT_MAX {
+                return Err(invalid("bad protobuf length prefix"));
+            }
+            reader.read_exact(&mut varint[n..n + 1])?;
+            n += 1;
+            if varint[n - 1] & 0x80 == 0 {
+                break;
+            }
+        }
+        let len = prost::decode_length_delimiter(&varint[..n])
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let max_len = max.saturating_add(PROTO_SLACK).min(MAX_FRAME_LEN);
+        if len > max_len {
+            return Err(too_large(ErrorKind::InvalidData, len, max_len));
+        }
+        let mut buf = take_buffer(len);
+        reader.read_exact(&mut buf)?;
+        let wmsg = wire::WireMessage::decode(&buf[..])
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        recycle(buf);
+        let msg_type = u8::try_from(wmsg.r#type)
+            .map_err(|_| invalid("protobuf message type out of range"))?;
+        let flags = u8::try_from(wmsg.flags)
+            .map_err(|_| invalid("protobuf message flags out of range"))?;
+        if wmsg.body.len() > max {
+            return Err(too_large(ErrorKind::InvalidData, wmsg.body.len(), max));
+        }
+        let mut msg = M::new(HEADER_SIZE + wmsg.body.len());
+        msg.set_type(msg_type);
+        msg.set_id(wmsg.correlation_id);
+        msg.set_flags(flags);
+        msg.set_topic(&wmsg.topic);
+        msg.set_content_bytes(&wmsg.body);
+        Ok(msg)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct TextLineCodec;
+
+impl<M> Codec<M> for TextLineCodec
+where M: Msg + Clone + Send + Default
+{
+    fn encode<W: Write>(msg: &M, writer: &mut W) -> Result<()> {
+        let mut body = msg.get_content_bytes();
+        if msg.get_flags() & FLAG_SEQ != 0 {
+            body = body.get(crate::sequence::SEQ_SIZE..).unwrap_or_default();
+        }
+        writer.write_all(body)?;
+        writer.write_all(b"\n")?;
+        writer.flush()
+    }
+    fn decode<R: Read>(reader: &mut R) -> Result<M> {
+        Self::decode_max(reader, CHUNK_SIZE)
+    }
+    /*-- a last line without newline ends at end of stream --*/
+    fn decode_max<R: Read>(reader: &mut R, max: usize) -> Result<M> {
+        let max = max.min(CHUNK_SIZE);
+        let mut line = Vec::<u8>::new();
+        let mut byte = [0u8; 1];
+        loop {
+            match reader.read(&mut byte) {
+                Ok(0) if line.is_empty() => return Err(Error::new(
+                    ErrorKind::UnexpectedEof, "stream closed"
+                )),
+                Ok(0) => break,
+                Ok(_) if byte[0] == b'\n' => break,
+                Ok(_) => line.push(byte[0]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+            if line.len() > max + 1 {  // + 1 for \r
+                return Err(too_large(ErrorKind::InvalidData, line.len(), max));
+            }
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        if line.len() > max {
+            return Err(too_large(ErrorKind::InvalidData, line.len(), max));
+        }
+        let mut msg = M::new(HEADER_SIZE + line.len());
+        msg.set_type(MessageType::TEXT as u8);
+        msg.set_content_bytes(&line);
+        Ok(msg)
+    }
+}
@@ -0,0 +1,115 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::compress.rs - body compression    //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   Compression<Z> parameter of CommProcessing:
+   - NoCompression, the default, sends bodies as they are
+   - Gzip<MIN>, feature "gzip", compresses bodies of at
+     least MIN bytes, 1024 unless another is named, when
+     that makes them smaller
+   - a compressed message has FLAG_GZIP set in its header,
+     so each message says how it's encoded and peers using
+     different settings interoperate
+   - any receiver built with feature "gzip" decompresses
+     flagged messages, whatever its own Z, refusing to
+     inflate a body past its max length
+   - compression happens before chunking, so a large body
+     is compressed once, then sent in chunks
+*/
+
+use rust_traits::*;
+use rust_message::*;
+use std::io::{Result, Error, ErrorKind};
+
+pub trait Compression: Send {
+    /*-- compressed body, or None to send body as is --*/
+    fn compress(body: &[u8]) -> Option<Vec<u8>>;
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct NoCompression;
+
+impl Compression for NoCompression {
+    fn compress(_body: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Gzip<const MIN: usize = 1024>;
+
+#[cfg(feature = "gzip")]
+impl<const MIN: usize> Compression for Gzip<MIN> {
+    fn compress(body: &[u8]) -> Option<Vec<u8>> {
+        use std::io::Write;
+        if body.len() < MIN {
+            return None;
+        }
+        let mut enc = flate2::write::GzEncoder::new(
+            Vec::with_capacity(body.len() / 4), flate2::Compression::default()
+        );
+        enc.write_all(body).ok()?;
+        let packed = enc.finish().ok()?;
+        if packed.len() < body.len() { Some(packed) } else { None }
+    }
+}
+
+/*-- copy of msg with its body compressed by Z, if worthwhile --*/
+pub(crate) fn deflate<M, Z>(msg: &M) -> Option<M>
+where M: Msg, Z: Compression
+{
+    if msg.get_flags() & FLAG_GZIP != 0 {
+        return None;
+    }
+    let packed = Z::compress(msg.get_content_bytes())?;
+    let mut zmsg = M::new(HEADER_SIZE + packed.len());
+    zmsg.set_type(msg.get_type());
+    zmsg.set_id(msg.get_id());
+    zmsg.set_flags(msg.get_flags() | FLAG_GZIP);
+    zmsg.set_topic(msg.get_topic());
+    zmsg.set_seq(msg.get_seq());
+    zmsg.set_content_bytes(&packed);
+    Some(zmsg)
+}
+
+/*-- msg with its body decompressed, if FLAG_GZIP is set --*/
+pub(crate) fn inflate<M: Msg>(msg: M, max_len: usize) -> Result<M> {
+    if msg.get_flags() & FLAG_GZIP == 0 {
+        return Ok(msg);
+    }
+    let body = gunzip(msg.get_content_bytes(), max_len)?;
+    let mut out = M::new(HEADER_SIZE + body.len());
+    out.set_type(msg.get_type());
+    out.set_id(msg.get_id());
+    out.set_flags(msg.get_flags() & !FLAG_GZIP);
+    out.set_topic(msg.get_topic());
+    out.set_seq(msg.get_seq());
+    out.set_content_bytes(&body);
+    Ok(out)
+}
+
+#[cfg(feature = "gzip")]
+fn gunzip(packed: &[u8], max_len: usize) -> Result<Vec<u8>> {
+    use std::io::Read;
+    let mut body = Vec::new();
+    flate2::read::GzDecoder::new(packed)
+        .take(max_len as u64 + 1)
+        .read_to_end(&mut body)?;
+    if body.len() > max_len {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            CommError::FrameTooLarge { len: body.len(), max: max_len }
+        ));
+    }
+    Ok(body)
+}
+#[cfg(not(feature = "gzip"))]
+fn gunzip(_packed: &[u8], _max_len: usize) -> Result<Vec<u8>> {
+    Err(Error::new(
+        ErrorKind::InvalidData,
+        "gzip compressed message received, build with feature \"gzip\" to accept it"
+    ))
+}
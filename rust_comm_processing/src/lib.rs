@@ -4,15 +4,49 @@
 // Jim Fawcett, https://JimFawcett.github.io, 19 Jul 2020  //
 /////////////////////////////////////////////////////////////
 /*
-   CommProcessing<L>:
+   CommProcessing<L,C,Z>:
    - defines send_message, recv_message, and process_message
    - each of these needs to be tailored to the specifics of
      the Message class
+   - wire format is delegated to codec C, defined in 
+     module codec, e.g., FrameCodec, BinaryCodec,
+     JsonCodec, MsgPackCodec, ProtoCodec, or
+     TextLineCodec
+   - bodies are compressed by Z, see module compress,
+     NoCompression unless another is named
+   - bodies of messages flagged FLAG_CRC32 are checked
+     with a CRC32, see module checksum
+   - a message's seq, if set, travels ahead of its body,
+     see module sequence
+   - large messages are split into chunks and 
+     reassembled, see module chunk
+   - received messages longer than MAX, MAX_MESSAGE_SIZE
+     by default, are refused with CommError::FrameTooLarge
+     before their bodies are read, so the connection is
+     closed rather than memory exhausted
+   - decode buffers are reused, see module pool
+   - write_message, write_bytes, and read_message apply
+     all of these
+     over any Write and Read, for transports Sndr and
+     Rcvr don't cover, e.g., async sockets
 */
 
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
+mod codec;
+pub use codec::*;
+mod chunk;
+pub use chunk::*;
+mod compress;
+pub use compress::*;
+mod checksum;
+use checksum::*;
+mod sequence;
+use sequence::*;
+mod pool;
+pub use pool::*;
+
 /*-- RustComm facilities --*/
 use rust_traits::*;
 use rust_message::*;
@@ -25,116 +59,206 @@ use std::net::{TcpStream};
 use std::io::prelude::*;
 use std::io::{BufReader, BufWriter, Write};
 use std::convert::{TryInto};
+use std::marker::PhantomData;
 
 type M = Message;
 
+/*-- default limit on a received message's body --*/
+pub const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
 /*---------------------------------------------------------
-  CommProcessing<L> 
+  CommProcessing<L,C,Z> 
   - defines application specific processing for the
     appliczation's message type
   - L is a logger type the must implement the Logger trait
+  - C is the Codec defining the wire format, FrameCodec
+    unless another is named
+  - Z is the Compression applied to bodies before framing,
+    NoCompression unless another is named
+  - MAX is the longest body received, before it's
+    decompressed and after, e.g.,
+    CommProcessing<L, FrameCodec, NoCompression, 65536>
 */
 #[derive(Debug, Copy, Clone, Default)]
-pub struct CommProcessing<L>
+pub struct CommProcessing<L, C = FrameCodec, Z = NoCompression, const MAX: usize = MAX_MESSAGE_SIZE>
 where L: Logger + Debug + Copy + Clone + Default {
     log: L,
+    codec: PhantomData<C>,
+    compress: PhantomData<Z>,
 }
-impl<L> CommProcessing<L>
+impl<L,C,Z,const MAX: usize> CommProcessing<L,C,Z,MAX>
 where L: Logger + Debug + Copy + Clone + Default
 {
-    pub fn new() -> CommProcessing<L> {
+    pub fn new() -> CommProcessing<L,C,Z,MAX> {
         CommProcessing {
             log: L::default(),
+            codec: PhantomData,
+            compress: PhantomData,
         }
     }
 }
-impl<M,L> Sndr<M> for CommProcessing<L>
+/*-- compress, if Z chooses to, checksum, if flagged, number, then frame msg onto writer --*/
+pub fn write_message<M, C, Z, W>(msg: &M, writer: &mut W) -> std::io::Result<()>
+where M: Msg + Clone + Send + Default, C: Codec<M>, Z: Compression, W: Write
+{
+    let zmsg = deflate::<M,Z>(msg);
+    let msg = zmsg.as_ref().unwrap_or(msg);
+    let smsg = seal(msg);
+    let msg = smsg.as_ref().unwrap_or(msg);
+    match stamp(msg) {
+        Some(tmsg) => send_chunked::<M,C,_>(&tmsg, writer),
+        None => send_chunked::<M,C,_>(msg, writer),
+    }
+}
+/*-----------------------------------------------------
+  frame a message of type msg_type from borrowed body,
+  as write_message frames one built from it
+  - body is written as is, unless Z compresses it
+  - sent without flags, so with no checksum
+*/
+pub fn write_bytes<M, C, Z, W>(msg_type: u8, id: u64, body: &[u8], writer: &mut W) 
+    -> std::io::Result<()>
+where M: Msg + Clone + Send + Default, C: Codec<M>, Z: Compression, W: Write
+{
+    match Z::compress(body) {
+        Some(packed) => send_chunked_bytes::<M,C,_>(msg_type, id, FLAG_GZIP, &packed, writer),
+        None => send_chunked_bytes::<M,C,_>(msg_type, id, 0, body, writer),
+    }
+}
+/*-- read one message from reader, verifying and decompressing as flagged --*/
+pub fn read_message<M, C, R>(reader: &mut R) -> std::io::Result<M>
+where M: Msg + Clone + Send + Default, C: Codec<M>, R: Read
+{
+    read_message_max::<M,C,_>(reader, MAX_MESSAGE_SIZE)
+}
+/*-- same, refusing with FrameTooLarge a body longer than max --*/
+pub fn read_message_max<M, C, R>(reader: &mut R, max: usize) -> std::io::Result<M>
+where M: Msg + Clone + Send + Default, C: Codec<M>, R: Read
+{
+    /*-- a checksummed, numbered body carries 4 + 8 more bytes --*/
+    let msg = recv_chunked::<M,C,_>(reader, max.saturating_add(4 + SEQ_SIZE))?;
+    let msg = verify(unstamp(msg)?)?;
+    let len = msg.get_content_bytes().len();
+    if len > max {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData, CommError::FrameTooLarge { len, max }
+        ));
+    }
+    inflate(msg, max)
+}
+/*-- counts bytes passing through to inner, for Sndr and Rcvr results --*/
+struct Counted<S> {
+    inner: S,
+    bytes: usize,
+}
+impl<S> Counted<S> {
+    fn new(inner: S) -> Counted<S> {
+        Counted { inner, bytes: 0 }
+    }
+}
+impl<S: Write> Write for Counted<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n;
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+impl<S: Read> Read for Counted<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes += n;
+        Ok(n)
+    }
+}
+impl<M,L,C,Z,const MAX: usize> Sndr<M> for CommProcessing<L,C,Z,MAX>
 where 
     M: Msg + Clone + Send + Default,
-    L: Logger + Debug + Copy + Clone + Default
+    L: Logger + Debug + Copy + Clone + Default,
+    C: Codec<M>,
+    Z: Compression
 {
-    fn send_message(msg: &M, stream: &mut TcpStream) -> std::io::Result<()>
+    fn send_message<T: Transport>(msg: &M, stream: &mut T) -> std::io::Result<()>
     {
-        L::write(&format!("\n  msg.len(): {}", msg.len()));
-        stream.write(&msg.get_ref())?;
-        Ok(())
+        L::debug(&format!("\n  msg.len(): {}", msg.len()));
+        write_message::<M,C,Z,_>(msg, stream)
     }
-    fn buf_send_message(msg: &M, stream: &mut BufWriter<TcpStream>) -> std::io::Result<()>
+    fn buf_send_message<T: Transport>(msg: &M, stream: &mut BufWriter<T>) 
+        -> std::io::Result<usize>
     {
-        L::write(&format!("\n  msg.len(): {}", msg.len()));
-        stream.write(&msg.get_ref())?;
+        let sent = Self::buf_write_message(msg, stream)?;
         let msg_type = msg.get_type(); 
+        /*-- heartbeats are flushed so they aren't held back --*/
         if msg_type == MessageType::FLUSH as u8 
             || msg_type == MessageType::END as u8 
             || msg_type == MessageType::QUIT as u8 
+            || msg_type == MessageType::PING as u8 
+            || msg_type == MessageType::PONG as u8 
         {
-            L::write("\n  flushing stream");
+            L::debug("\n  flushing stream");
             let _ = stream.flush();
         }
-        Ok(())
+        Ok(sent)
+    }
+    fn buf_write_message<T: Transport>(msg: &M, stream: &mut BufWriter<T>) 
+        -> std::io::Result<usize>
+    {
+        L::debug(&format!("\n  msg.len(): {}", msg.len()));
+        let mut counted = Counted::new(stream);
+        write_message::<M,C,Z,_>(msg, &mut counted)?;
+        Ok(counted.bytes)
+    }
+    fn buf_write_bytes<T: Transport>(
+        msg_type: u8, id: u64, body: &[u8], stream: &mut BufWriter<T>
+    ) -> std::io::Result<usize>
+    {
+        L::debug(&format!("\n  body.len(): {}", body.len()));
+        let mut counted = Counted::new(stream);
+        write_bytes::<M,C,Z,_>(msg_type, id, body, &mut counted)?;
+        Ok(counted.bytes)
     }
 }
-impl<M,L> Rcvr<M> for CommProcessing<L>
+impl<M,L,C,Z,const MAX: usize> Rcvr<M> for CommProcessing<L,C,Z,MAX>
 where 
     M: Msg + Clone + Send + Default,
-    L: Logger + Debug + Copy + Clone + Default
+    L: Logger + Debug + Copy + Clone + Default,
+    C: Codec<M>,
+    Z: Send
 {
     /*-- reads message and enques in supplied BlockingQueue<M> --*/
-    fn recv_message(stream: &mut TcpStream) -> std::io::Result<M> 
+    fn recv_message<T: Transport>(stream: &mut T) -> std::io::Result<M> 
     {
-        L::write("\n  attempting to receive msg in commProc");
-        let buf = &mut [0u8; HEADER_SIZE];
-        stream.read_exact(buf)?;
-        let msgtype = buf[0];
-        let sz_slice = &buf[1..HEADER_SIZE];
-        let mut dst = [0u8;8];
-        dst.clone_from_slice(sz_slice); // array from byte slice
-        let bdysz = usize::from_be_bytes(dst);   // usize from byte array
-
-        let mut bdy = vec![0u8;bdysz];
-        stream.read_exact(&mut bdy)?;        
-        let msg_size = TYPE_SIZE + CONTENT_SIZE + bdysz;
-        let mut msg = M::new(msg_size);
-        msg.set_type(msgtype);
-        msg.set_content_bytes(&bdy);
-        Ok(msg)
-    }
-    /*-- same as above but uses buffered reader --*/
-    fn buf_recv_message(stream: &mut BufReader<TcpStream>) -> std::io::Result<M> 
+        L::debug("\n  attempting to receive msg in commProc");
+        read_message_max::<M,C,_>(stream, MAX)
+    }
+    /*-- same as above but uses buffered reader, also returning bytes read --*/
+    fn buf_recv_message<T: Transport>(stream: &mut BufReader<T>) -> std::io::Result<(M, usize)> 
     {
-        L::write("\n  attempting to receive msg in commProc");
-        let buf = &mut [0u8; HEADER_SIZE];
-        stream.read_exact(buf)?;
-        let msgtype = buf[0];
-        let sz_slice = &buf[1..HEADER_SIZE];
-        let mut dst = [0u8;8];
-        dst.clone_from_slice(sz_slice); // array from byte slice
-        let bdysz = usize::from_be_bytes(dst);   // usize from byte array
-
-        let mut bdy = vec![0u8;bdysz];
-        stream.read_exact(&mut bdy)?;        
-        let msg_size = TYPE_SIZE + CONTENT_SIZE + bdysz;
-        let mut msg = M::new(msg_size);
-        msg.set_type(msgtype);
-        msg.set_content_bytes(&bdy);
-        Ok(msg)
+        L::debug("\n  attempting to receive msg in commProc");
+        let mut counted = Counted::new(stream);
+        let msg = read_message_max::<M,C,_>(&mut counted, MAX)?;
+        Ok((msg, counted.bytes))
     }
 }
 /*---------------------------------------------------------
   Process<M> handles processing of each message on 
   Listener<P,L>
 */
-impl<M,L> Process<M> for CommProcessing<L>
+impl<M,L,C,Z,const MAX: usize> Process<M> for CommProcessing<L,C,Z,MAX>
 where 
     M: Msg + Clone + Send + Default,
-    L: Logger + Debug + Copy + Clone + Default
+    L: Logger + Debug + Copy + Clone + Default,
+    C: Send,
+    Z: Send
 {
+    /*-- echo msg back as a REPLY, the Listener flushes replies to FLUSH --*/
     fn process_message(msg: &mut M) 
     {
-        L::write("\n--entered process_message--");
+        L::debug("\n--entered process_message--");
         let msg_type = msg.get_type();
-        if msg_type != MessageType::FLUSH as u8 
-            && msg_type != MessageType::END as u8 
+        if msg_type != MessageType::END as u8 
             && msg_type != MessageType::QUIT as u8 
         {
             msg.set_type(MessageType::REPLY as u8);
@@ -154,4 +278,457 @@ mod tests {
         let _ = CommProcessing::<MuteLog>::send_message(&msg, &mut stream);
         assert_eq!(2 + 2, 4);
     }
+    #[test]
+    fn binary_codec_round_trip() {
+        let mut msg = Message::create_msg_str_fit("round trip");
+        msg.set_type(MessageType::TEXT as u8);
+        msg.set_id(7);
+        let mut wire = Vec::<u8>::new();
+        BinaryCodec::encode(&msg, &mut wire).unwrap();
+        BinaryCodec::encode(&msg, &mut wire).unwrap();
+        let mut reader = std::io::Cursor::new(wire);
+        for _ in 0..2 {
+            let rcvd: Message = BinaryCodec::decode(&mut reader).unwrap();
+            assert_eq!(rcvd.get_type(), MessageType::TEXT as u8);
+            assert_eq!(rcvd.get_id(), 7);
+            assert_eq!(rcvd.get_content_str().unwrap(), "round trip");
+        }
+    }
+    #[test]
+    fn frame_codec_round_trip() {
+        let body: Vec<u8> = (0..=255).collect();
+        let mut msg = Message::create_msg_bytes_fit(&body);
+        msg.set_type(MessageType::TEXT as u8);
+        msg.set_id(42);
+        let mut wire = Vec::<u8>::new();
+        FrameCodec::<MAX_FRAME_LEN>::encode(&msg, &mut wire).unwrap();
+        /*-- length counts type, id, flags, and body --*/
+        assert_eq!(&wire[..4], &266u32.to_be_bytes());
+        assert_eq!(wire.len(), 4 + 266);
+
+        /*-- arrives in small pieces, as over a segmented stream --*/
+        let mut reader = std::io::BufReader::with_capacity(7, std::io::Cursor::new(wire));
+        let rcvd: Message = FrameCodec::<MAX_FRAME_LEN>::decode(&mut reader).unwrap();
+        assert_eq!(rcvd.get_type(), MessageType::TEXT as u8);
+        assert_eq!(rcvd.get_id(), 42);
+        assert_eq!(rcvd.get_content_bytes(), &body[..]);
+    }
+    #[test]
+    fn frame_codec_rejects_oversized_frames() {
+        let msg = Message::create_msg_bytes_fit(&[0u8; 100]);
+        let mut wire = Vec::<u8>::new();
+        let err = FrameCodec::<64>::encode(&msg, &mut wire).unwrap_err();
+        assert!(wire.is_empty());
+        assert!(matches!(
+            err.get_ref().unwrap().downcast_ref::<CommError>(),
+            Some(CommError::FrameTooLarge { len: 110, max: 64 })
+        ));
+
+        /*-- length claims 4 GB, rejected without reading body --*/
+        let mut reader = std::io::Cursor::new(vec![0xff, 0xff, 0xff, 0xff, 1]);
+        let rslt: std::io::Result<Message> = FrameCodec::<1024>::decode(&mut reader);
+        assert_eq!(rslt.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+    #[test]
+    fn forged_sizes_are_refused_before_allocating() {
+        let too_large = |rslt: std::io::Result<Message>| matches!(
+            rslt.unwrap_err().get_ref().and_then(|e| e.downcast_ref::<CommError>()),
+            Some(CommError::FrameTooLarge { .. })
+        );
+        /*-- 32 MB frame, within MAX_FRAME_LEN, past MAX_MESSAGE_SIZE --*/
+        let mut wire = ((32 * 1024 * 1024) as u32).to_be_bytes().to_vec();
+        wire.extend_from_slice(&[1u8; 16]);
+        assert!(too_large(read_message::<_, FrameCodec, _>(&mut std::io::Cursor::new(wire))));
+
+        /*-- BinaryCodec header claiming an exabyte body --*/
+        let mut hdr = vec![0u8; HEADER_SIZE];
+        hdr[0] = MessageType::TEXT as u8;
+        hdr[TYPE_SIZE..ID_OFFSET].copy_from_slice(&(1usize << 60).to_be_bytes());
+        assert!(too_large(read_message::<_, BinaryCodec, _>(&mut std::io::Cursor::new(hdr))));
+
+        /*-- a limit is a body length, checksummed or not --*/
+        let mut msg = Message::create_msg_bytes_fit(&[7u8; 100]);
+        msg.set_flags(FLAG_CRC32);
+        let mut wire = Vec::<u8>::new();
+        write_message::<_, FrameCodec, NoCompression, _>(&msg, &mut wire).unwrap();
+        let fits: Message = read_message_max::<_, FrameCodec, _>(
+            &mut std::io::Cursor::new(wire.clone()), 100
+        ).unwrap();
+        assert_eq!(fits.get_content_bytes(), &[7u8; 100][..]);
+        assert!(too_large(read_message_max::<_, FrameCodec, _>(&mut std::io::Cursor::new(wire), 99)));
+        let mut wire = Vec::<u8>::new();
+        write_message::<_, JsonCodec, NoCompression, _>(&msg, &mut wire).unwrap();
+        assert!(too_large(read_message_max::<_, JsonCodec, _>(&mut std::io::Cursor::new(wire), 99)));
+    }
+    /*-- yields one byte per read, interrupted before each --*/
+    struct Trickle {
+        data: std::io::Cursor<Vec<u8>>,
+        interrupt: bool,
+    }
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            let n = buf.len().min(1);
+            self.data.read(&mut buf[..n])
+        }
+    }
+    fn trickle_round_trip<C: Codec<Message>>() {
+        let mut big = Message::create_msg_bytes_fit(&vec![3u8; CHUNK_SIZE + 100]);
+        big.set_type(MessageType::TEXT as u8);
+        big.set_flags(FLAG_CRC32);
+        let mut small = Message::create_msg_str_fit("small");
+        small.set_topic("news");
+        let mut wire = Vec::<u8>::new();
+        for msg in [&big, &small].iter() {
+            write_message::<_, C, NoCompression, _>(*msg, &mut wire).unwrap();
+        }
+        let mut reader = Trickle { data: std::io::Cursor::new(wire), interrupt: false };
+        let rcvd: Message = read_message::<_, C, _>(&mut reader).unwrap();
+        assert_eq!(rcvd.get_content_bytes(), big.get_content_bytes());
+        let rcvd: Message = read_message::<_, C, _>(&mut reader).unwrap();
+        assert_eq!(rcvd.get_content_str().unwrap(), "small");
+        assert_eq!(rcvd.get_topic(), "news");
+        let eof: std::io::Result<Message> = read_message::<_, C, _>(&mut reader);
+        assert_eq!(eof.unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+    #[test]
+    fn messages_survive_byte_at_a_time_reads() {
+        trickle_round_trip::<FrameCodec>();
+        trickle_round_trip::<BinaryCodec>();
+        trickle_round_trip::<JsonCodec>();
+        trickle_round_trip::<MsgPackCodec>();
+    }
+    #[test]
+    fn large_bodies_are_chunked_and_reassembled() {
+        let body: Vec<u8> = (0..3 * CHUNK_SIZE + 10).map(|i| i as u8).collect();
+        let mut msg = Message::create_msg_bytes_fit(&body);
+        msg.set_type(MessageType::TEXT as u8);
+        msg.set_id(9);
+        let small = Message::create_msg_str_fit("small");
+        let mut wire = Vec::<u8>::new();
+        send_chunked::<Message, FrameCodec, _>(&msg, &mut wire).unwrap();
+        send_chunked::<Message, FrameCodec, _>(&small, &mut wire).unwrap();
+
+        let mut reader = std::io::Cursor::new(wire.clone());
+        let rcvd: Message = FrameCodec::<MAX_FRAME_LEN>::decode(&mut reader).unwrap();
+        assert_eq!(rcvd.get_type(), MessageType::CHUNK_START as u8);
+
+        let mut reader = std::io::Cursor::new(wire.clone());
+        let rcvd: Message = recv_chunked::<_, FrameCodec, _>(&mut reader, MAX_REASSEMBLY_LEN).unwrap();
+        assert_eq!(rcvd.get_type(), MessageType::TEXT as u8);
+        assert_eq!(rcvd.get_id(), 9);
+        assert_eq!(rcvd.get_body_bytes(), &body[..]);
+        let rcvd: Message = recv_chunked::<_, FrameCodec, _>(&mut reader, MAX_REASSEMBLY_LEN).unwrap();
+        assert_eq!(rcvd.get_body_str().unwrap(), "small");
+
+        /*-- receiver's limit stops reassembly --*/
+        let mut reader = std::io::Cursor::new(wire);
+        let rslt: std::io::Result<Message> = 
+            recv_chunked::<_, FrameCodec, _>(&mut reader, 2 * CHUNK_SIZE);
+        let err = rslt.unwrap_err();
+        assert!(matches!(
+            err.get_ref().unwrap().downcast_ref::<CommError>(),
+            Some(CommError::FrameTooLarge { .. })
+        ));
+    }
+    #[test]
+    fn json_codec_round_trip() {
+        let mut msg = Message::create_msg_str_fit("line one\nline \"two\"");
+        msg.set_type(MessageType::TEXT as u8);
+        let mut binary = Message::create_msg_bytes_fit(&[0xff, 0, 10]);
+        binary.set_id(3);
+        let mut wire = Vec::<u8>::new();
+        JsonCodec::encode(&msg, &mut wire).unwrap();
+        JsonCodec::encode(&binary, &mut wire).unwrap();
+        /*-- one line per message, embedded newline escaped --*/
+        assert_eq!(wire.iter().filter(|b| **b == b'\n').count(), 2);
+        let text = std::str::from_utf8(&wire).unwrap();
+        assert!(text.starts_with("{\"type\":1,\"body\":\"line one\\nline"));
+
+        let mut reader = std::io::Cursor::new(wire);
+        let rcvd: Message = JsonCodec::decode(&mut reader).unwrap();
+        assert_eq!(rcvd.get_type(), MessageType::TEXT as u8);
+        assert_eq!(rcvd.get_content_str().unwrap(), "line one\nline \"two\"");
+        let rcvd: Message = JsonCodec::decode(&mut reader).unwrap();
+        assert_eq!(rcvd.get_id(), 3);
+        assert_eq!(rcvd.get_content_bytes(), &[0xff, 0, 10]);
+        let eof: std::io::Result<Message> = JsonCodec::decode(&mut reader);
+        assert!(eof.is_err());
+    }
+    #[test]
+    fn msgpack_codec_round_trip_is_smaller_than_json() {
+        let mut msg = Message::create_msg_str_fit("price 101.25");
+        msg.set_type(MessageType::TEXT as u8);
+        msg.set_id(1234);
+        msg.set_topic("quotes");
+        let binary = Message::create_msg_bytes_fit(&[0xff, 0, 10]);
+        let mut wire = Vec::<u8>::new();
+        MsgPackCodec::encode(&msg, &mut wire).unwrap();
+        let packed_len = wire.len();
+        MsgPackCodec::encode(&binary, &mut wire).unwrap();
+        let mut json = Vec::<u8>::new();
+        JsonCodec::encode(&msg, &mut json).unwrap();
+        assert!(packed_len < json.len());
+
+        let mut reader = std::io::Cursor::new(wire);
+        let rcvd: Message = MsgPackCodec::decode(&mut reader).unwrap();
+        assert_eq!(rcvd.get_type(), MessageType::TEXT as u8);
+        assert_eq!(rcvd.get_id(), 1234);
+        assert_eq!(rcvd.get_topic(), "quotes");
+        assert_eq!(rcvd.get_content_str().unwrap(), "price 101.25");
+        let rcvd: Message = MsgPackCodec::decode(&mut reader).unwrap();
+        assert_eq!(rcvd.get_content_bytes(), &[0xff, 0, 10]);
+    }
+    #[cfg(feature = "proto")]
+    #[test]
+    fn proto_codec_round_trip_is_varint_delimited() {
+        let mut msg = Message::create_msg_bytes_fit(&[0xff, 0, 10]);
+        msg.set_type(MessageType::app(5).unwrap());
+        msg.set_id(300);
+        msg.set_topic("orders");
+        let mut wire = Vec::<u8>::new();
+        write_message::<Message, ProtoCodec, NoCompression, _>(&msg, &mut wire).unwrap();
+        /*-- short message, one byte varint gives the rest's length --*/
+        assert_eq!(wire[0] as usize, wire.len() - 1);
+        let mut big = Message::create_msg_bytes_fit(&vec![3u8; 3 * CHUNK_SIZE]);
+        big.set_type(MessageType::TEXT as u8);
+        write_message::<Message, ProtoCodec, NoCompression, _>(&big, &mut wire).unwrap();
+
+        let mut reader = std::io::Cursor::new(wire);
+        let rcvd: Message = read_message::<_, ProtoCodec, _>(&mut reader).unwrap();
+        assert_eq!(MessageType::app_code(rcvd.get_type()), Some(5));
+        assert_eq!(rcvd.get_id(), 300);
+        assert_eq!(rcvd.get_topic(), "orders");
+        assert_eq!(rcvd.get_content_bytes(), &[0xff, 0, 10]);
+        let rcvd: Message = read_message::<_, ProtoCodec, _>(&mut reader).unwrap();
+        assert_eq!(rcvd.get_content_bytes(), big.get_content_bytes());
+        trickle_round_trip::<ProtoCodec>();
+    }
+    #[test]
+    fn text_line_codec_reads_and_writes_lines() {
+        let mut reader = std::io::Cursor::new(b"hello\r\n\nlast".to_vec());
+        for line in ["hello", "", "last"].iter() {
+            let rcvd: Message = TextLineCodec::decode(&mut reader).unwrap();
+            assert_eq!(rcvd.get_type(), MessageType::TEXT as u8);
+            assert_eq!(rcvd.get_content_str().unwrap(), *line);
+        }
+        let eof: std::io::Result<Message> = TextLineCodec::decode(&mut reader);
+        assert_eq!(eof.unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+
+        let mut reply = Message::create_msg_str_fit("hello");
+        reply.set_type(MessageType::REPLY as u8);
+        reply.set_id(9);
+        let mut wire = Vec::<u8>::new();
+        TextLineCodec::encode(&reply, &mut wire).unwrap();
+        assert_eq!(wire, b"hello\n");
+
+        let long = format!("{}\n", "x".repeat(CHUNK_SIZE + 1));
+        let rslt: std::io::Result<Message> = 
+            TextLineCodec::decode(&mut std::io::Cursor::new(long.into_bytes()));
+        assert_eq!(rslt.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_shrinks_compressible_bodies() {
+        let body = "all work and no play ".repeat(50_000);
+        let mut msg = Message::create_msg_str_fit(&body);
+        msg.set_type(MessageType::TEXT as u8);
+        msg.set_id(5);
+        let small = Message::create_msg_str_fit("below threshold");
+        let mut wire = Vec::<u8>::new();
+        write_message::<Message, FrameCodec, Gzip, _>(&msg, &mut wire).unwrap();
+        let sent = wire.len();
+        assert!(sent < body.len() / 50, "{} bytes on the wire", sent);
+        write_message::<Message, FrameCodec, Gzip, _>(&small, &mut wire).unwrap();
+        /*-- tiny message skips compression --*/
+        assert_eq!(wire.len() - sent, 4 + 10 + "below threshold".len());
+
+        let mut reader = std::io::Cursor::new(wire);
+        let rcvd: Message = read_message::<_, FrameCodec, _>(&mut reader).unwrap();
+        assert_eq!(rcvd.get_type(), MessageType::TEXT as u8);
+        assert_eq!(rcvd.get_id(), 5);
+        assert_eq!(rcvd.get_flags(), 0);
+        assert_eq!(rcvd.get_content_str().unwrap(), body);
+        let rcvd: Message = read_message::<_, FrameCodec, _>(&mut reader).unwrap();
+        assert_eq!(rcvd.get_content_str().unwrap(), "below threshold");
+    }
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn gzip_flag_rejected_without_feature() {
+        let mut msg = Message::create_msg_str_fit("not really gzip");
+        msg.set_flags(FLAG_GZIP);
+        let mut wire = Vec::<u8>::new();
+        write_message::<Message, FrameCodec, NoCompression, _>(&msg, &mut wire).unwrap();
+        let mut reader = std::io::Cursor::new(wire);
+        let rslt: std::io::Result<Message> = read_message::<_, FrameCodec, _>(&mut reader);
+        assert_eq!(rslt.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+    #[test]
+    fn checksum_detects_corrupted_body() {
+        let mut msg = Message::create_msg_str_fit("checked body");
+        msg.set_type(MessageType::TEXT as u8);
+        msg.set_flags(FLAG_CRC32);
+        let mut wire = Vec::<u8>::new();
+        write_message::<Message, FrameCodec, NoCompression, _>(&msg, &mut wire).unwrap();
+        /*-- len, type, id, flags, body, then crc --*/
+        assert_eq!(wire.len(), 4 + 10 + "checked body".len() + 4);
+
+        let mut reader = std::io::Cursor::new(wire.clone());
+        let rcvd: Message = read_message::<_, FrameCodec, _>(&mut reader).unwrap();
+        assert_eq!(rcvd.get_content_str().unwrap(), "checked body");
+        assert_eq!(rcvd.get_flags(), FLAG_CRC32);
+
+        wire[4 + 10 + 3] ^= 0x20;
+        let mut reader = std::io::Cursor::new(wire);
+        let rslt: std::io::Result<Message> = read_message::<_, FrameCodec, _>(&mut reader);
+        let err = rslt.unwrap_err();
+        assert!(matches!(
+            err.get_ref().unwrap().downcast_ref::<CommError>(),
+            Some(CommError::ChecksumMismatch { .. })
+        ));
+    }
+    #[test]
+    fn buffered_send_and_recv_report_wire_bytes() {
+        type P = CommProcessing<MuteLog>;
+        let tcpl = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = std::net::TcpStream::connect(tcpl.local_addr().unwrap()).unwrap();
+        let (server, _) = tcpl.accept().unwrap();
+        let mut writer = BufWriter::new(client);
+        let mut reader = BufReader::new(server);
+        let mut small = Message::create_msg_str_fit("counted");
+        small.set_type(MessageType::TEXT as u8);
+        let mut large = Message::create_msg_bytes_fit(&vec![3u8; 2 * CHUNK_SIZE]);
+        large.set_type(MessageType::FLUSH as u8);
+        for msg in [small, large].iter() {
+            let mut wire = Vec::<u8>::new();
+            write_message::<Message, FrameCodec, NoCompression, _>(msg, &mut wire).unwrap();
+            assert_eq!(P::buf_send_message(msg, &mut writer).unwrap(), wire.len());
+            writer.flush().unwrap();
+            let (rcvd, read): (Message, usize) = P::buf_recv_message(&mut reader).unwrap();
+            assert_eq!(read, wire.len());
+            assert_eq!(rcvd.get_content_bytes(), msg.get_content_bytes());
+        }
+    }
+    #[test]
+    fn sequence_numbers_ride_ahead_of_body() {
+        fn round_trip<C: Codec<Message>>(msg: &Message) -> Message {
+            let mut wire = Vec::<u8>::new();
+            write_message::<Message, C, NoCompression, _>(msg, &mut wire).unwrap();
+            read_message::<_, C, _>(&mut std::io::Cursor::new(wire)).unwrap()
+        }
+        let mut msg = Message::create_msg_str_fit("numbered");
+        msg.set_type(MessageType::TEXT as u8);
+        msg.set_flags(FLAG_CRC32);
+        msg.set_seq(41);
+        for rcvd in [
+            round_trip::<FrameCodec>(&msg),
+            round_trip::<BinaryCodec>(&msg),
+            round_trip::<JsonCodec>(&msg),
+            round_trip::<MsgPackCodec>(&msg)
+        ].iter() {
+            assert_eq!(rcvd.get_seq(), 41);
+            assert_eq!(rcvd.get_flags(), FLAG_CRC32);
+            assert_eq!(rcvd.get_content_str().unwrap(), "numbered");
+        }
+        /*-- chunked body keeps seq --*/
+        msg.set_body_bytes(vec![9u8; 2 * CHUNK_SIZE]);
+        let rcvd = round_trip::<FrameCodec>(&msg);
+        assert_eq!(rcvd.get_seq(), 41);
+        assert_eq!(rcvd.get_content_bytes(), msg.get_content_bytes());
+
+        /*-- text lines leave it out, unnumbered frames are unchanged --*/
+        msg.set_body_str("plain");
+        msg.set_flags(0);
+        let mut wire = Vec::<u8>::new();
+        write_message::<Message, TextLineCodec, NoCompression, _>(&msg, &mut wire).unwrap();
+        assert_eq!(wire, b"plain\n");
+        msg.set_seq(0);
+        let mut wire = Vec::<u8>::new();
+        write_message::<Message, FrameCodec, NoCompression, _>(&msg, &mut wire).unwrap();
+        assert_eq!(wire.len(), 4 + 10 + "plain".len());
+    }
+    #[test]
+    fn topics_survive_every_codec() {
+        fn round_trip<C: Codec<Message>>(msg: &Message) -> Message {
+            let mut wire = Vec::<u8>::new();
+            write_message::<Message, C, NoCompression, _>(msg, &mut wire).unwrap();
+            read_message::<_, C, _>(&mut std::io::Cursor::new(wire)).unwrap()
+        }
+        let mut msg = Message::create_msg_str_fit("sunny");
+        msg.set_type(MessageType::PUBLISH as u8);
+        msg.set_topic("weather");
+        msg.set_flags(FLAG_CRC32);
+        for rcvd in [
+            round_trip::<FrameCodec>(&msg), 
+            round_trip::<BinaryCodec>(&msg), 
+            round_trip::<JsonCodec>(&msg),
+            round_trip::<MsgPackCodec>(&msg)
+        ].iter() {
+            assert_eq!(rcvd.get_topic(), "weather");
+            assert_eq!(rcvd.get_flags(), FLAG_CRC32);
+            assert_eq!(rcvd.get_content_str().unwrap(), "sunny");
+        }
+        /*-- chunked body keeps topic --*/
+        msg.set_body_bytes(vec![7u8; 2 * CHUNK_SIZE]);
+        assert_eq!(round_trip::<FrameCodec>(&msg).get_topic(), "weather");
+
+        /*-- frames without a topic are unchanged --*/
+        let mut wire = Vec::<u8>::new();
+        msg.set_topic("");
+        msg.set_body_str("sunny");
+        FrameCodec::<MAX_FRAME_LEN>::encode(&msg, &mut wire).unwrap();
+        assert_eq!(wire.len(), 4 + 10 + "sunny".len());
+        msg.set_topic(&"x".repeat(TOPIC_MAX + 1));
+        assert!(FrameCodec::<MAX_FRAME_LEN>::encode(&msg, &mut wire).is_err());
+    }
+    #[test]
+    fn borrowed_bodies_frame_as_messages_do() {
+        fn both_ways<C: Codec<Message>>(body: &[u8]) -> (Vec<u8>, Vec<u8>) {
+            let mut msg = Message::create_msg_bytes_fit(body);
+            msg.set_type(MessageType::TEXT as u8);
+            msg.set_id(11);
+            let (mut owned, mut borrowed) = (Vec::new(), Vec::new());
+            write_message::<Message, C, NoCompression, _>(&msg, &mut owned).unwrap();
+            write_bytes::<Message, C, NoCompression, _>(
+                MessageType::TEXT as u8, 11, body, &mut borrowed
+            ).unwrap();
+            (owned, borrowed)
+        }
+        let big: Vec<u8> = (0..2 * CHUNK_SIZE + 3).map(|i| i as u8).collect();
+        for body in [&b"small"[..], &big[..]].iter() {
+            let (owned, borrowed) = both_ways::<FrameCodec>(body);
+            assert_eq!(owned, borrowed);
+            let (owned, borrowed) = both_ways::<BinaryCodec>(body);
+            assert_eq!(owned, borrowed);
+            /*-- JsonCodec has no header, so body is copied --*/
+            let (owned, borrowed) = both_ways::<JsonCodec>(body);
+            assert_eq!(owned, borrowed);
+        }
+    }
+    #[test]
+    fn decode_buffers_are_reused() {
+        let buf = take_buffer(100);
+        let ptr = buf.as_ptr();
+        recycle(buf);
+        /*-- same allocation, truncated then resized --*/
+        let buf = take_buffer(50);
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(buf, vec![0u8; 50]);
+        recycle(buf);
+
+        let msg = Message::create_msg_str_fit("pooled");
+        let mut wire = Vec::<u8>::new();
+        FrameCodec::<MAX_FRAME_LEN>::encode(&msg, &mut wire).unwrap();
+        FrameCodec::<MAX_FRAME_LEN>::encode(&msg, &mut wire).unwrap();
+        let mut reader = std::io::Cursor::new(wire);
+        for _ in 0..2 {
+            let rcvd: Message = FrameCodec::<MAX_FRAME_LEN>::decode(&mut reader).unwrap();
+            assert_eq!(rcvd.get_content_str().unwrap(), "pooled");
+        }
+        let buf = take_buffer(0);
+        assert_eq!(buf.as_ptr(), ptr);
+    }
 }
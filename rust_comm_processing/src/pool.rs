@@ -0,0 +1,59 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::pool.rs - decode buffer reuse     //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   FrameCodec and BinaryCodec read each frame into a
+   scratch buffer before copying it into the Message.
+   Those buffers come from a free list kept by each
+   thread, so a session's receiver reuses them from one
+   message to the next:
+   - a buffer is truncated when returned, keeping its
+     capacity, and resized when taken
+   - each thread holds at most buffer_pool_size() buffers,
+     8 by default, set_buffer_pool_size(0) turns pooling
+     off
+   - buffers that grew past POOLED_MAX_CAPACITY, e.g., for
+     a large frame, are dropped rather than kept
+*/
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/*-- largest buffer kept for reuse --*/
+pub const POOLED_MAX_CAPACITY: usize = 256 * 1024;
+
+static POOL_SIZE: AtomicUsize = AtomicUsize::new(8);
+
+thread_local! {
+    static FREE: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+/*-- most buffers each thread keeps for reuse --*/
+pub fn set_buffer_pool_size(n: usize) {
+    POOL_SIZE.store(n, Ordering::Relaxed);
+}
+pub fn buffer_pool_size() -> usize {
+    POOL_SIZE.load(Ordering::Relaxed)
+}
+
+/*-- zeroed buffer of len bytes, reused if one is free --*/
+pub(crate) fn take_buffer(len: usize) -> Vec<u8> {
+    let mut buf = FREE.with(|free| free.borrow_mut().pop()).unwrap_or_default();
+    buf.resize(len, 0);
+    buf
+}
+/*-- return buffer to this thread's free list --*/
+pub(crate) fn recycle(mut buf: Vec<u8>) {
+    if buf.capacity() > POOLED_MAX_CAPACITY {
+        return;
+    }
+    buf.truncate(0);
+    FREE.with(|free| {
+        let mut free = free.borrow_mut();
+        if free.len() < buffer_pool_size() {
+            free.push(buf);
+        }
+    });
+}
@@ -0,0 +1,64 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::sequence.rs - sequence numbers    //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   A message whose seq is set, e.g., by a Connector's send
+   thread, carries it on the wire:
+   - FLAG_SEQ is set and the 8 byte big-endian seq
+     precedes the body, after compression and checksum,
+     so they cover only the body
+   - the receiver strips it into the message's seq and
+     clears FLAG_SEQ, so any codec carrying flags and
+     body carries seq
+   - messages with seq 0 are sent as before
+*/
+
+use rust_traits::*;
+use rust_message::*;
+use std::io::{Result, Error, ErrorKind};
+
+pub(crate) const SEQ_SIZE: usize = 8;
+
+/*-- copy of msg with seq ahead of body, if seq is set --*/
+pub(crate) fn stamp<M: Msg>(msg: &M) -> Option<M> {
+    let seq = msg.get_seq();
+    if seq == 0 {
+        return None;
+    }
+    let body = msg.get_content_bytes();
+    let mut stamped = M::new(HEADER_SIZE + SEQ_SIZE + body.len());
+    stamped.set_type(msg.get_type());
+    stamped.set_id(msg.get_id());
+    stamped.set_flags(msg.get_flags() | FLAG_SEQ);
+    stamped.set_topic(msg.get_topic());
+    stamped.set_seq(seq);
+    let mut content = Vec::with_capacity(SEQ_SIZE + body.len());
+    content.extend_from_slice(&seq.to_be_bytes());
+    content.extend_from_slice(body);
+    stamped.set_content_bytes(&content);
+    Some(stamped)
+}
+
+/*-- msg with seq stripped from body, if flagged --*/
+pub(crate) fn unstamp<M: Msg>(msg: M) -> Result<M> {
+    if msg.get_flags() & FLAG_SEQ == 0 {
+        return Ok(msg);
+    }
+    let content = msg.get_content_bytes();
+    if content.len() < SEQ_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "sequence flagged but missing"));
+    }
+    let (seq, body) = content.split_at(SEQ_SIZE);
+    let mut sent = [0u8; SEQ_SIZE];
+    sent.copy_from_slice(seq);
+    let mut out = M::new(HEADER_SIZE + body.len());
+    out.set_type(msg.get_type());
+    out.set_id(msg.get_id());
+    out.set_flags(msg.get_flags() & !FLAG_SEQ);
+    out.set_topic(msg.get_topic());
+    out.set_seq(u64::from_be_bytes(sent));
+    out.set_content_bytes(body);
+    Ok(out)
+}
@@ -16,7 +16,7 @@ fn main() {
     print!("\n  -- demo writing directly to msg buffer --\n");
     let mut msg = Message::new(MESS_SIZE);
     print!("\n  msg len: {:?}",msg.get_ref().len());
-    for i in TYPE_SIZE + CONTENT_SIZE..MESS_SIZE {
+    for i in HEADER_SIZE..MESS_SIZE {
       msg.get_mut_ref()[i] = i as u8;
     }
     msg.set_content_size(MESS_SIZE);
@@ -32,8 +32,8 @@ fn main() {
     let sz = msg.get_content_size();
     print!("\n  content size: {:?}",sz);
     let rslt = msg.get_content_str();
-    if let Ok(_) = rslt {
-      print!("\n  contents: {:?}",rslt.unwrap());
+    if let Ok(contents) = rslt {
+      print!("\n  contents: {:?}",contents);
     }
     println!();
 
@@ -0,0 +1,148 @@
+/////////////////////////////////////////////////////////////
+// rust_message::builder.rs - fluent Message construction  //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   MessageBuilder, from Message::builder():
+     Message::builder()
+         .msg_type(MessageType::TEXT)
+         .body("hi")
+         .correlation_id(7)
+         .build()?
+   - app_type(code) sets an application-defined type in
+     place of msg_type
+   - build fails with CommError::InvalidMessage if the
+     type was never set, an app code is past
+     APP_CODE_MAX, the body is longer than
+     max_body, MAX_BODY_SIZE by default, or the topic is
+     longer than TOPIC_MAX
+   - unset id, flags, topic, and body are 0 or empty, as
+     for a new Message
+   - setters on Message remain for changing a message
+     after it's built
+*/
+
+use crate::*;
+
+/*-- default body limit, matches receivers' reassembly limit --*/
+pub const MAX_BODY_SIZE: usize = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct MessageBuilder {
+    msg_type: Option<u16>,  // app codes past APP_CODE_MAX exceed u8
+    id: u64,
+    flags: u8,
+    topic: String,
+    body: Vec<u8>,
+    max_body: usize,
+}
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        MessageBuilder {
+            msg_type: None,
+            id: 0,
+            flags: 0,
+            topic: String::new(),
+            body: Vec::new(),
+            max_body: MAX_BODY_SIZE,
+        }
+    }
+}
+impl Message {
+    pub fn builder() -> MessageBuilder {
+        MessageBuilder::default()
+    }
+}
+impl MessageBuilder {
+    pub fn msg_type(mut self, mt: MessageType) -> Self {
+        self.msg_type = Some(mt as u16);
+        self
+    }
+    /*-- application-defined type, see MessageType::app --*/
+    pub fn app_type(mut self, code: u8) -> Self {
+        self.msg_type = Some(APP_TYPE_MIN as u16 + code as u16);
+        self
+    }
+    /*-- utf-8 text or any bytes --*/
+    pub fn body<B: AsRef<[u8]>>(mut self, body: B) -> Self {
+        self.body = body.as_ref().to_vec();
+        self
+    }
+    pub fn correlation_id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+    pub fn flags(mut self, flags: u8) -> Self {
+        self.flags = flags;
+        self
+    }
+    pub fn topic(mut self, topic: &str) -> Self {
+        self.topic = topic.to_string();
+        self
+    }
+    /*-- longest body build accepts, e.g., a peer's smaller limit --*/
+    pub fn max_body(mut self, max: usize) -> Self {
+        self.max_body = max;
+        self
+    }
+    pub fn build(self) -> Result<Message, CommError> {
+        let invalid = |why: String| Err(CommError::InvalidMessage(why));
+        let Some(msg_type) = self.msg_type else {
+            return invalid("message type not set".to_string());
+        };
+        if msg_type > u8::MAX as u16 {
+            return invalid(format!(
+                "app code {} exceeds limit of {}", msg_type - APP_TYPE_MIN as u16, APP_CODE_MAX
+            ));
+        }
+        if self.body.len() > self.max_body {
+            return invalid(format!(
+                "body of {} bytes exceeds limit of {} bytes", self.body.len(), self.max_body
+            ));
+        }
+        if self.topic.len() > TOPIC_MAX {
+            return invalid(format!(
+                "topic of {} bytes exceeds limit of {} bytes", self.topic.len(), TOPIC_MAX
+            ));
+        }
+        let mut msg = Message::create_msg_bytes_fit(&self.body);
+        msg.set_type(msg_type as u8);
+        msg.set_id(self.id);
+        msg.set_flags(self.flags);
+        msg.set_topic(&self.topic);
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn builder_sets_fields_and_checks_them() {
+        let msg = Message::builder()
+            .msg_type(MessageType::TEXT)
+            .body("hi")
+            .correlation_id(7)
+            .build()
+            .unwrap();
+        assert_eq!(msg.get_type(), MessageType::TEXT as u8);
+        assert_eq!(msg.get_body_str().unwrap(), "hi");
+        assert_eq!(msg.get_id(), 7);
+        assert_eq!(msg.len(), HEADER_SIZE + 2);
+
+        let untyped = Message::builder().body("no type").build();
+        assert!(matches!(untyped, Err(CommError::InvalidMessage(_))));
+        let big = Message::builder()
+            .msg_type(MessageType::TEXT)
+            .body(vec![0u8; 11])
+            .max_body(10)
+            .build();
+        assert!(matches!(big, Err(CommError::InvalidMessage(_))));
+
+        let app = Message::builder().app_type(APP_CODE_MAX).build().unwrap();
+        assert_eq!(MessageType::app_code(app.get_type()), Some(APP_CODE_MAX));
+        let bad = Message::builder().app_type(APP_CODE_MAX + 1).build();
+        assert!(matches!(bad, Err(CommError::InvalidMessage(_))));
+    }
+}
@@ -6,15 +6,48 @@
 /*
    Message:
    - fixed size header holding a MessageType attribute:
-     TEXT, BYTES, END, QUIT, REPLY
-   - body holds utf-8 text or arbitrary byte sequence
+     TEXT, BYTES, END, QUIT, REPLY, FLUSH, PING, PONG,
+     the content size, a u64 correlation id, 0 when
+     unset, and a flags byte describing how the body is
+     encoded, e.g., FLAG_GZIP or FLAG_CRC32
+   - FILE_START, FILE_CHUNK, and FILE_END carry a file
+     sent by Connector::send_file, see rust_comm
+   - type bytes from APP_TYPE_MIN up, made with
+     MessageType::app(code), are the application's own,
+     never treated as control by rust_comm
+   - END closes the connection it's sent on, QUIT also
+     stops the Listener receiving it, see rust_comm
+   - PING and PONG are heartbeat messages handled by
+     rust_comm, never seen by applications
+   - CHUNK_START, CHUNK_CONT, and CHUNK_END carry pieces
+     of a large message, reassembled by the receiver's
+     processing layer, also never seen by applications
+   - AUTH carries a Connector's token, answered with
+     AUTH_OK or AUTH_FAIL by a Listener, see rust_comm
+   - topic, empty unless set, names the subject of a
+     SUBSCRIBE, UNSUBSCRIBE, or PUBLISH message; it's held
+     apart from header and body and sent only when set
+   - seq, 0 unless set, is the sender's per-connection
+     sequence number, stamped by rust_comm; it's also
+     held apart and sent only when set
+   - body holds utf-8 text or arbitrary byte sequence,
+     set_body_bytes and get_body_bytes carry any bytes,
+     set_body_str and get_body_str are utf-8 wrappers
    - stores contents in std::Vec<u8>
+   - Message::builder(), in module builder, constructs a
+     message in one expression, checking it's complete
+   - Display shows a message's type, id, seq and topic
+     when set, body length, and up to PREVIEW_CHARS of
+     its body, for logs, Debug still shows every byte
 */
 
 #![allow(dead_code)]
 
 // use std::fmt::*;
 use rust_traits::*;
+use std::fmt;
+mod builder;
+pub use builder::*;
 use std::str::Utf8Error;
 
 /*---------------------------------------------------------
@@ -24,9 +57,31 @@ use std::str::Utf8Error;
 */
 pub const TYPE_SIZE:usize = 1;
 pub const CONTENT_SIZE:usize = 8;  // max 4096 - 32 - 1 = 4063
-pub const HEADER_SIZE:usize = TYPE_SIZE + CONTENT_SIZE;
+pub const ID_SIZE:usize = 8;
+pub const ID_OFFSET:usize = TYPE_SIZE + CONTENT_SIZE;
+pub const FLAGS_SIZE:usize = 1;
+pub const FLAGS_OFFSET:usize = ID_OFFSET + ID_SIZE;
+pub const HEADER_SIZE:usize = TYPE_SIZE + CONTENT_SIZE + ID_SIZE + FLAGS_SIZE;
+
+/*-- bits of the flags byte --*/
+pub const FLAG_GZIP:u8 = 0x01;  // body is gzip compressed
+pub const FLAG_CRC32:u8 = 0x02; // body followed by its CRC32 on the wire
+pub const FLAG_TOPIC:u8 = 0x04; // topic precedes body on the wire, set by codecs
+pub const FLAG_SEQ:u8 = 0x08;   // body preceded by its sequence number on the wire
+
+/*-- body characters Display shows before eliding the rest --*/
+pub const PREVIEW_CHARS:usize = 32;
+
+/*-- type bytes APP_TYPE_MIN..=255 carry application codes 0..=APP_CODE_MAX --*/
+pub const APP_TYPE_MIN:u8 = 192;
+pub const APP_CODE_MAX:u8 = u8::MAX - APP_TYPE_MIN;
+
+/*-- longest topic, in bytes, a codec will send --*/
+pub const TOPIC_MAX:usize = 255;
 
 #[repr(u8)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MessageType {
     DEFAULT = 0,
     TEXT = 1,
@@ -34,11 +89,76 @@ pub enum MessageType {
     END = 4,
     QUIT = 8,
     FLUSH = 16,
+    PING = 32,
+    PONG = 64,
+    CHUNK_START = 128,
+    CHUNK_CONT = 129,
+    CHUNK_END = 130,
+    AUTH = 131,
+    AUTH_OK = 132,
+    AUTH_FAIL = 133,
+    SUBSCRIBE = 134,
+    UNSUBSCRIBE = 135,
+    PUBLISH = 136,
+    FILE_START = 137,
+    FILE_CHUNK = 138,
+    FILE_END = 139,
+}
+impl MessageType {
+    /*-- type byte for application code, None if code > APP_CODE_MAX --*/
+    pub fn app(code: u8) -> Option<u8> {
+        APP_TYPE_MIN.checked_add(code)
+    }
+    /*-- application code carried by type byte mt, None for library types --*/
+    pub fn app_code(mt: u8) -> Option<u8> {
+        mt.checked_sub(APP_TYPE_MIN)
+    }
+    /*-- library type with type byte mt, None for app codes and unused bytes --*/
+    pub fn from_byte(mt: u8) -> Option<MessageType> {
+        use MessageType::*;
+        let all = [
+            DEFAULT, TEXT, REPLY, END, QUIT, FLUSH, PING, PONG, 
+            CHUNK_START, CHUNK_CONT, CHUNK_END, AUTH, AUTH_OK, AUTH_FAIL,
+            SUBSCRIBE, UNSUBSCRIBE, PUBLISH, FILE_START, FILE_CHUNK, FILE_END,
+        ];
+        IntoIterator::into_iter(all).find(|t| *t as u8 == mt)
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            MessageType::DEFAULT => "DEFAULT",
+            MessageType::TEXT => "TEXT",
+            MessageType::REPLY => "REPLY",
+            MessageType::END => "END",
+            MessageType::QUIT => "QUIT",
+            MessageType::FLUSH => "FLUSH",
+            MessageType::PING => "PING",
+            MessageType::PONG => "PONG",
+            MessageType::CHUNK_START => "CHUNK_START",
+            MessageType::CHUNK_CONT => "CHUNK_CONT",
+            MessageType::CHUNK_END => "CHUNK_END",
+            MessageType::AUTH => "AUTH",
+            MessageType::AUTH_OK => "AUTH_OK",
+            MessageType::AUTH_FAIL => "AUTH_FAIL",
+            MessageType::SUBSCRIBE => "SUBSCRIBE",
+            MessageType::UNSUBSCRIBE => "UNSUBSCRIBE",
+            MessageType::PUBLISH => "PUBLISH",
+            MessageType::FILE_START => "FILE_START",
+            MessageType::FILE_CHUNK => "FILE_CHUNK",
+            MessageType::FILE_END => "FILE_END",
+        }
+    }
+}
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
  #[derive(Debug, Clone, Default)]
 pub struct Message {
     br: Vec<u8>,
+    topic: String,
+    seq: u64,
 } 
 impl Msg for Message {
     /*-------------------------------------------
@@ -48,12 +168,16 @@ impl Msg for Message {
         assert!(sz >= HEADER_SIZE);
         Self {
             br: vec![0; sz],
+            topic: String::new(),
+            seq: 0,
         }
     }
     /*-- load existing heap array with zeros --*/
     fn init(&mut self) {
         let sz = self.len();
         self.br = vec![0;sz];
+        self.topic.clear();
+        self.seq = 0;
     }
     /*-- return message length --*/
     fn len(&self) -> usize {
@@ -64,11 +188,41 @@ impl Msg for Message {
     }
     /*-- set message MsgType --*/
     fn set_type(&mut self, mt:u8) {
-        self.br[0] = mt as u8;
+        self.br[0] = mt;
     }
     fn get_type(&self) -> u8 {
         self.br[0]
     }
+    /*-- correlation id, matches reply to request --*/
+    fn set_id(&mut self, id:u64) {
+        self.set_field(ID_OFFSET, &id.to_be_bytes());
+    }
+    fn get_id(&self) -> u64 {
+        let mut dst = [0u8;8];
+        dst.clone_from_slice(self.get_field(ID_OFFSET, ID_SIZE));
+        u64::from_be_bytes(dst)
+    }
+    /*-- body encoding flags, e.g., FLAG_GZIP --*/
+    fn set_flags(&mut self, flags:u8) {
+        self.br[FLAGS_OFFSET] = flags;
+    }
+    fn get_flags(&self) -> u8 {
+        self.br[FLAGS_OFFSET]
+    }
+    /*-- pub/sub topic, empty when unset --*/
+    fn set_topic(&mut self, topic:&str) {
+        self.topic = topic.to_string();
+    }
+    fn get_topic(&self) -> &str {
+        &self.topic
+    }
+    /*-- sender's sequence number, 0 when unset --*/
+    fn set_seq(&mut self, seq:u64) {
+        self.seq = seq;
+    }
+    fn get_seq(&self) -> u64 {
+        self.seq
+    }
     /*-------------------------------------------
       Set message content from buff and set
       content size to length of buff
@@ -114,9 +268,7 @@ impl Msg for Message {
         &mut self.br[..]
     }
     fn set_bytes(&mut self, buff:&[u8]) {
-        for i in 0..buff.len() {
-            self.br[i] = buff[i];
-        }
+        self.br[..buff.len()].copy_from_slice(buff);
     }
     fn get_ref(&self) -> &Vec<u8> {
         &self.br
@@ -143,26 +295,13 @@ impl Msg for Message {
         }
     }
     fn type_display(&self) -> String {
-        let mut rtn:String = String::from("UNKNOWN");
-        if self.br[0] == MessageType::DEFAULT as u8 {
-            rtn = String::from("DEFAULT");
-        }
-        else if self.br[0] == MessageType::END as u8 {
-            rtn = String::from("END");
-        }
-        else if self.br[0] == MessageType::QUIT as u8 {
-            rtn = String::from("QUIT");
-        }
-        else if self.br[0] == MessageType::REPLY as u8 {
-            rtn = String::from("REPLY");
-        }
-        else if self.br[0] == MessageType::TEXT as u8 {
-            rtn = String::from("TEXT");
-        }
-        else if self.br[0] == MessageType::FLUSH as u8 {
-            rtn = String::from("FLUSH");
+        match MessageType::from_byte(self.br[0]) {
+            Some(mt) => mt.to_string(),
+            None => match MessageType::app_code(self.br[0]) {
+                Some(code) => format!("APP({})", code),
+                None => String::from("UNKNOWN"),
+            },
         }
-        rtn
     }
 }
 impl Message {
@@ -189,6 +328,26 @@ impl Message {
         }
         msg
     }
+    /*-------------------------------------------
+      Replace body with bytes, resizing message
+      to fit, so any length and content, e.g.,
+      interior nulls, is carried intact
+    */
+    pub fn set_body_bytes(&mut self, body: Vec<u8>) {
+        self.br.resize(HEADER_SIZE, 0);
+        self.set_content_size(body.len());
+        self.br.extend_from_slice(&body);
+    }
+    pub fn get_body_bytes(&self) -> &[u8] {
+        self.get_content_bytes()
+    }
+    /*-- utf-8 convenience wrappers for body bytes --*/
+    pub fn set_body_str(&mut self, s: &str) {
+        self.set_body_bytes(s.as_bytes().to_vec());
+    }
+    pub fn get_body_str(&self) -> Result<&str, Utf8Error> {
+        Self::str_from_bytes(self.get_body_bytes())
+    }
     pub fn create_msg_header_only() -> Message {
         let mut msg = Message::new(HEADER_SIZE);
         msg.set_content_size(0);
@@ -219,10 +378,41 @@ impl Message {
         std::str::from_utf8(b)
     }
 }
+/*---------------------------------------------------------
+  one line summary, e.g.,
+    TEXT id=7 len=11 "hello world"
+  - id, seq, and topic only when set
+  - body shown as utf-8, invalid bytes replaced, escaped,
+    and cut at PREVIEW_CHARS, marked by ...
+*/
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.type_display())?;
+        if self.get_id() != 0 {
+            write!(f, " id={}", self.get_id())?;
+        }
+        if self.seq != 0 {
+            write!(f, " seq={}", self.seq)?;
+        }
+        if !self.topic.is_empty() {
+            write!(f, " topic={}", self.topic)?;
+        }
+        let body = self.get_content_bytes();
+        write!(f, " len={}", body.len())?;
+        if body.is_empty() {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(body);
+        let mut chars = text.chars();
+        let preview: String = chars.by_ref().take(PREVIEW_CHARS).collect();
+        let more = if chars.next().is_some() { "..." } else { "" };
+        write!(f, " \"{}\"{}", preview.escape_debug(), more)
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
     #[test]
     fn construction() {
         // let mut msg = Message::new();
@@ -232,4 +422,77 @@ mod tests {
         // let sz = msg.get_body_size();
         // assert_eq!(sz, MSGSIZE);
     }
+    #[test]
+    fn body_bytes_resize_message() {
+        let mut msg = Message::create_msg_str_fit("short");
+        msg.set_type(MessageType::TEXT as u8);
+        let body = vec![0u8, 1, 0, 255, 0];
+        msg.set_body_bytes(body.clone());
+        assert_eq!(msg.get_type(), MessageType::TEXT as u8);
+        assert_eq!(msg.get_body_bytes(), &body[..]);
+        assert_eq!(msg.len(), HEADER_SIZE + body.len());
+
+        let mut empty = Message::default();
+        empty.set_body_str("grows from empty");
+        assert_eq!(empty.get_body_str().unwrap(), "grows from empty");
+        empty.set_body_bytes(Vec::new());
+        assert_eq!(empty.len(), HEADER_SIZE);
+    }
+    #[test]
+    fn id_kept_with_body_changes() {
+        let mut msg = Message::create_msg_str_fit("request");
+        assert_eq!(msg.get_id(), 0);
+        msg.set_id(0x0102_0304_0506_0708);
+        msg.set_body_str("a longer body than before");
+        assert_eq!(msg.get_id(), 0x0102_0304_0506_0708);
+        assert_eq!(msg.get_body_str().unwrap(), "a longer body than before");
+    }
+    #[test]
+    fn flags_kept_with_body_changes() {
+        let mut msg = Message::create_msg_str_fit("body");
+        assert_eq!(msg.get_flags(), 0);
+        msg.set_flags(FLAG_GZIP);
+        msg.set_id(9);
+        msg.set_body_bytes(vec![1, 2, 3]);
+        assert_eq!(msg.get_flags(), FLAG_GZIP);
+        assert_eq!(msg.get_id(), 9);
+    }
+    #[test]
+    fn app_types_are_a_reserved_range() {
+        let mt = MessageType::app(5).unwrap();
+        assert_eq!(MessageType::app_code(mt), Some(5));
+        assert_eq!(MessageType::app(APP_CODE_MAX), Some(u8::MAX));
+        assert_eq!(MessageType::app(APP_CODE_MAX + 1), None);
+        assert_eq!(MessageType::app_code(MessageType::PUBLISH as u8), None);
+        let mut msg = Message::create_msg_str_fit("custom");
+        msg.set_type(mt);
+        assert_eq!(msg.type_display(), "APP(5)");
+    }
+    #[test]
+    fn display_summarizes_message() {
+        assert_eq!(MessageType::QUIT.to_string(), "QUIT");
+        assert_eq!(MessageType::from_byte(MessageType::PONG as u8), Some(MessageType::PONG));
+        assert_eq!(MessageType::from_byte(3), None);
+        let mut msg = Message::create_msg_str_fit("hello\nworld");
+        msg.set_type(MessageType::TEXT as u8);
+        msg.set_id(7);
+        assert_eq!(msg.to_string(), "TEXT id=7 len=11 \"hello\\nworld\"");
+        let mut end = Message::create_msg_header_only();
+        end.set_type(MessageType::END as u8);
+        assert_eq!(end.to_string(), "END len=0");
+        let long = Message::create_msg_str_fit(&"x".repeat(PREVIEW_CHARS + 1));
+        let shown = format!("DEFAULT len={} \"{}\"...", PREVIEW_CHARS + 1, "x".repeat(PREVIEW_CHARS));
+        assert_eq!(long.to_string(), shown);
+    }
+    #[test]
+    fn topic_kept_apart_from_body() {
+        let mut msg = Message::create_msg_str_fit("news");
+        assert_eq!(msg.get_topic(), "");
+        msg.set_topic("weather");
+        msg.set_body_str("rain later");
+        assert_eq!(msg.get_topic(), "weather");
+        assert_eq!(msg.len(), HEADER_SIZE + "rain later".len());
+        msg.init();
+        assert_eq!(msg.get_topic(), "");
+    }
 }
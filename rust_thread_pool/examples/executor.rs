@@ -0,0 +1,41 @@
+/////////////////////////////////////////////////////////////
+// rust_thread_pool::executor.rs - demo general executor   //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   Submits 100 closures to a ThreadPool made with
+   new_executor, each sending its result back over a
+   channel, then collects the results.
+*/
+
+use std::sync::mpsc;
+use rust_thread_pool::ThreadPool;
+
+fn main() {
+
+    print!("\n  Demonstrate ThreadPool as executor");
+    print!("\n ====================================");
+
+    let mut tp = ThreadPool::new_executor(4);
+    let (tx, rx) = mpsc::channel::<(u64, u64)>();
+    for n in 0..100u64 {
+        let tx = tx.clone();
+        tp.execute(move || {
+            let _ = tx.send((n, n * n));
+        });
+    }
+    /*-- stop runs every queued job before joining threads --*/
+    tp.stop();
+    drop(tx);
+
+    let mut results: Vec<(u64, u64)> = rx.iter().collect();
+    results.sort_unstable();
+    let sum: u64 = results.iter().map(|(_, sq)| sq).sum();
+    print!("\n  {} jobs done, first {:?}, last {:?}",
+        results.len(), results.first(), results.last()
+    );
+    print!("\n  sum of squares 0..100 = {}", sum);
+
+    print!("\n\n  That's all Folks!\n");
+}
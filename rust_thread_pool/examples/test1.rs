@@ -25,12 +25,11 @@ pub fn test_message_in_pool(
 ) {
     // let q = String::from("quit");
     let id = thread::current().id();
-    loop {
-        /*-- process message --*/
-        let msg = bq.de_q();
+    /*-- process message --*/
+    while let Some(msg) = bq.de_q() {
         print!("\n  deQed {:<12} : {:?}", msg, id);
         thread::yield_now();
-        if !run.load(Ordering::Relaxed) && bq.len() == 0 {
+        if !run.load(Ordering::Relaxed) && bq.is_empty() {
             break;
         }
         // thread::yield_now();
@@ -50,7 +49,6 @@ pub fn post_message_to_pool() {
         // thread::sleep(_millis);
     }
     tp.stop();
-    tp.wait();
 }
 /*-- simple test of BlockingQueue --*/
 fn test0() {
@@ -65,8 +63,7 @@ fn test0() {
     let handle = thread::spawn(move || {
         print!("\n  child thread started");
         flush();
-        loop {
-            let t = share1.de_q();
+        while let Some(t) = share1.de_q() {
             print!("\n  dequeued {} on child thread", t);
             flush();
             if &t == "quit" {
@@ -79,7 +76,7 @@ fn test0() {
 
     /*-- main thread enqueues messages --*/
     for i in 0..5 {
-        let msg = format!("msg #{}", i.to_string());
+        let msg = format!("msg #{}", i);
         print!("\n  enqueued {:?} on main thread", msg);
         flush();
         share2.en_q(msg);
@@ -6,15 +6,113 @@
 /*
    ThreadPool<M> instances start a specified number of
    threads, each of which executes a processing function.
+
+   stop() closes the input queue, so processing functions
+   see de_q return None once queued work is done, and
+   returns after every thread has exited.
+
+   resize() grows or shrinks the pool while it runs. A
+   processing function that takes work with
+   de_q_while(run) retires as soon as it's idle.
+
+   configured_size(), size(), active_count(), and
+   queue_depth() report pool state for monitoring. A
+   processing function holds the guard from busy() while
+   it works on an item, so active_count() counts it.
+
+   A processing function that panics is restarted on the
+   same thread, so the pool keeps its size. Each panic is
+   counted by panics_recovered() and reported to the hook
+   set with on_panic().
+
+   Threads are named <prefix>-worker-<n>, with prefix
+   "commpool" unless given to new_named().
+
+   new_bounded() caps the input queue, so post waits while
+   it's full.
+
+   new_fair() gives the pool a fair input queue, so idle
+   threads take work in the order they asked for it and
+   work is spread evenly across them.
+
+   new_executor() makes a ThreadPool<Job>, a general
+   executor: execute(job) queues a closure, and each
+   thread runs the closures it takes, so callers needn't
+   write a processing function. A job that panics is
+   recovered as above, later jobs still run.
 */
 #![allow(dead_code)]
 use std::fmt::*;
 use rust_blocking_queue::*;
 use std::thread::*;
 use std::sync::*;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/*-- starts one thread running the processing function --*/
+type Spawner = Box<dyn FnMut(Arc<AtomicBool>) -> JoinHandle<()> + Send>;
+
+/*-- work queued by execute, see new_executor --*/
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
 
+/*-----------------------------------------------------
+  executor's processing function
+  - runs jobs until the queue is closed and empty
+  - a thread retired by resize exits between jobs;
+    stop closes the queue first, so queued jobs still
+    run before threads exit
+*/
+fn run_jobs(bq: &BlockingQueue<Job>, run: &Arc<AtomicBool>) {
+    while let Some(job) = bq.de_q_while(run) {
+        let _busy = busy();
+        job();
+    }
+}
+
+thread_local! {
+    /*-- active count of the pool running this thread, None off pool threads --*/
+    static ACTIVE: std::cell::RefCell<Option<Arc<AtomicUsize>>> = const { std::cell::RefCell::new(None) };
+}
+/*-- one count of the pool's active threads, returned on drop, even when unwinding --*/
 #[derive(Debug)]
+pub struct Busy(Option<Arc<AtomicUsize>>);
+impl Drop for Busy {
+    fn drop(&mut self) {
+        if let Some(active) = &self.0 {
+            active.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+/*-----------------------------------------------------
+  count calling pool thread in active_count until the
+  guard drops
+  - processing functions take one for each item they
+    dequeue, so threads waiting for work aren't counted
+  - does nothing on a thread that isn't a pool's
+*/
+pub fn busy() -> Busy {
+    let active = ACTIVE.with(|a| a.borrow().clone());
+    if let Some(active) = &active {
+        active.fetch_add(1, Ordering::SeqCst);
+    }
+    Busy(active)
+}
+
+/*-- told the panic message when a processing function panics --*/
+pub type PanicHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+/*-- text of a panic payload, if it has any --*/
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    }
+    else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    }
+    else {
+        "unknown panic".to_string()
+    }
+}
+
 pub struct ThreadPool<M> 
 {
     run: Arc<AtomicBool>,
@@ -22,6 +120,23 @@ pub struct ThreadPool<M>
     sobq: Arc<BlockingQueue<M>>,
     thrd: Vec<Option<JoinHandle<()>>>,
     /* see note below about Option */
+    flags: Vec<Arc<AtomicBool>>,  // run signal for each thread
+    live: Arc<AtomicUsize>,       // threads not yet exited
+    active: Arc<AtomicUsize>,     // threads in processing function, or job
+    panics: Arc<AtomicUsize>,     // processing functions restarted
+    hook: Arc<Mutex<Option<PanicHook>>>,
+    spawner: Spawner,
+}
+impl<M> Debug for ThreadPool<M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreadPool")
+         .field("run", &self.run)
+         .field("threads", &self.thrd.len())
+         .field("live", &self.live)
+         .field("active", &self.active)
+         .field("panics", &self.panics)
+         .finish()
+    }
 }
 impl<M> ThreadPool<M> 
 where M: Send + 'static
@@ -32,38 +147,184 @@ where M: Send + 'static
       - pass processing function that accepts
         ThreadPool blocking queue and stopping
         signal
+      - each thread gets its own stopping signal, so 
+        resize can retire some threads and not others
     */
-    pub fn new<F>(nt:u8, f:F) -> ThreadPool<M> 
-    where F: FnOnce(&BlockingQueue<M>, &Arc<AtomicBool>) -> () + Send + 'static + Copy
+    pub fn new<F>(nt:usize, f:F) -> ThreadPool<M> 
+    where F: FnOnce(&BlockingQueue<M>, &Arc<AtomicBool>) + Send + 'static + Copy
+    {
+        Self::new_named(nt, "commpool", f)
+    }
+    /*-- same as new, naming threads <prefix>-worker-<n> --*/
+    pub fn new_named<F>(nt:usize, prefix:&str, f:F) -> ThreadPool<M> 
+    where F: FnOnce(&BlockingQueue<M>, &Arc<AtomicBool>) + Send + 'static + Copy
+    {
+        Self::start(nt, prefix, BlockingQueue::<M>::new(), f)
+    }
+    /*-- same as new, holding at most cap queued messages --*/
+    pub fn new_bounded<F>(nt:usize, cap:usize, f:F) -> ThreadPool<M> 
+    where F: FnOnce(&BlockingQueue<M>, &Arc<AtomicBool>) + Send + 'static + Copy
+    {
+        Self::start(nt, "commpool", BlockingQueue::<M>::with_capacity(cap), f)
+    }
+    /*-- same as new, input queue serving threads in arrival order --*/
+    pub fn new_fair<F>(nt:usize, f:F) -> ThreadPool<M> 
+    where F: FnOnce(&BlockingQueue<M>, &Arc<AtomicBool>) + Send + 'static + Copy
+    {
+        Self::start(nt, "commpool", BlockingQueue::<M>::new().fair(), f)
+    }
+    fn start<F>(nt:usize, prefix:&str, input:BlockingQueue<M>, f:F) -> ThreadPool<M> 
+    where F: FnOnce(&BlockingQueue<M>, &Arc<AtomicBool>) + Send + 'static + Copy
     {
         let run_ref = Arc::new(AtomicBool::new(true));
-        let siqm = Arc::new(BlockingQueue::<M>::new());
+        let siqm = Arc::new(input);
         let soqm = Arc::new(BlockingQueue::<M>::new());
-        let mut vt = Vec::<Option<JoinHandle<()>>>::new();
-        /* start nt threads */
-        for _i in 0..nt {
-            /*----------------------------------------------- 
-              ref sq to master shared queue (siqm) is captured
-              by thread proc closure 
-            */
-            let siq = Arc::clone(&siqm);
-            let run = Arc::clone(&run_ref);
-            let handle = std::thread::spawn( move || { 
-                f(&siq, &run);  // thread_pool_processing
-            });
-            vt.push(Some(handle));
-        }
-        Self { // return newly created threadpool
+        let live = Arc::new(AtomicUsize::new(0));
+        let active = Arc::new(AtomicUsize::new(0));
+        let panics = Arc::new(AtomicUsize::new(0));
+        let hook = Arc::new(Mutex::new(None::<PanicHook>));
+        /*----------------------------------------------- 
+          ref sq to master shared queue (siqm) is captured
+          by thread proc closure 
+        */
+        let siq = Arc::clone(&siqm);
+        let count = Arc::clone(&live);
+        let busy = Arc::clone(&active);
+        let npanics = Arc::clone(&panics);
+        let phook = Arc::clone(&hook);
+        let prefix = prefix.to_string();
+        let mut next_id = 0usize;
+        let spawner: Spawner = Box::new(move |run: Arc<AtomicBool>| {
+            let siq = Arc::clone(&siq);
+            let count = Arc::clone(&count);
+            let busy = Arc::clone(&busy);
+            let npanics = Arc::clone(&npanics);
+            let phook = Arc::clone(&phook);
+            let name = format!("{}-worker-{}", prefix, next_id);
+            next_id += 1;
+            count.fetch_add(1, Ordering::SeqCst);
+            Builder::new().name(name).spawn( move || { 
+                ACTIVE.with(|a| *a.borrow_mut() = Some(busy));
+                /*-- restart processing until it returns normally --*/
+                while let Err(payload) = std::panic::catch_unwind(
+                    std::panic::AssertUnwindSafe(|| f(&siq, &run))
+                ) {
+                    npanics.fetch_add(1, Ordering::SeqCst);
+                    let hook = phook.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                    if let Some(hook) = hook {
+                        hook(&panic_message(&*payload));
+                    }
+                }
+                count.fetch_sub(1, Ordering::SeqCst);
+            })
+            .expect("failed to spawn threadpool thread")
+        });
+        let mut tp = Self { // return newly created threadpool
             run: run_ref,
             sibq: siqm,
             sobq: soqm,
-            thrd: vt, 
+            thrd: Vec::new(), 
+            flags: Vec::new(),
+            live,
+            active,
+            panics,
+            hook,
+            spawner,
+        };
+        /* start nt threads */
+        tp.resize(nt);
+        tp
+    }
+    /*-----------------------------------------------------
+      change number of running threads to nt
+      - grows by starting threads
+      - shrinks by clearing stopping signal of retiring
+        threads, each exits when its processing function
+        next checks the signal, so work in progress is
+        never cut short
+      - then wakes the input queue, so a retiring thread
+        blocked in de_q_while returns None and exits with
+        no further posts; one blocked in de_q waits for
+        its next item
+    */
+    pub fn resize(&mut self, nt: usize) {
+        self.reap();
+        let running: Vec<usize> = (0..self.flags.len())
+            .filter(|i| self.flags[*i].load(Ordering::SeqCst))
+            .collect();
+        if nt > running.len() {
+            for _i in running.len()..nt {
+                let run = Arc::new(AtomicBool::new(true));
+                self.thrd.push(Some((self.spawner)(Arc::clone(&run))));
+                self.flags.push(run);
+            }
+        }
+        else {
+            for i in &running[nt..] {
+                self.flags[*i].store(false, Ordering::SeqCst);
+            }
+            self.sibq.wake_all();
         }
     }
-    /*-- wait for threads to finish --*/
+    /*-- number of times a processing function was restarted --*/
+    pub fn panics_recovered(&self) -> usize {
+        self.panics.load(Ordering::SeqCst)
+    }
+    /*-- set function told of each recovered panic --*/
+    pub fn on_panic<G>(&mut self, g: G)
+    where G: Fn(&str) + Send + Sync + 'static
+    {
+        *self.hook.lock().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(g));
+    }
+    /*-----------------------------------------------------
+      threads started and not yet signalled to stop, that
+      is, as requested by new or the last resize, 0 once
+      stop is called
+    */
+    pub fn configured_size(&self) -> usize {
+        self.flags.iter().filter(|f| f.load(Ordering::Relaxed)).count()
+    }
+    /*-----------------------------------------------------
+      threads busy with an item, counted while they hold
+      the guard busy() returns
+      - an executor's threads count while running a job
+      - a processing function that takes no guard is never
+        counted
+    */
+    pub fn active_count(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+    /*-- number of items posted but not yet taken --*/
+    pub fn queue_depth(&self) -> usize {
+        self.sibq.len()
+    }
+    /*-- threads still running, including retiring threads --*/
+    pub fn size(&self) -> usize {
+        self.live.load(Ordering::SeqCst)
+    }
+    /*-- join retired threads that have exited --*/
+    fn reap(&mut self) {
+        let mut i = 0;
+        while i < self.thrd.len() {
+            let finished = self.thrd[i].as_ref()
+                .map(|h| h.is_finished()).unwrap_or(true);
+            if finished && !self.flags[i].load(Ordering::SeqCst) {
+                if let Some(handle) = self.thrd.remove(i) {
+                    let _ = handle.join();
+                }
+                self.flags.remove(i);
+            }
+            else {
+                i += 1;
+            }
+        }
+    }
+    /*-- wait for threads to finish, already joined are skipped --*/
     pub fn wait(&mut self) {
         for handle in &mut self.thrd {
-            let _ = handle.take().unwrap().join();
+            if let Some(handle) = handle.take() {
+                let _ = handle.join();
+            }
             /*
               This is a hack!
               Without the Option, wrapping threadhandle, can't move threadhandle
@@ -83,26 +344,58 @@ where M: Send + 'static
         self.sibq.en_q(_msg);
     }
     /*-----------------------------------------------------
-      signals threads to terminate
-      - run() must be tested in thread processing function
-        used for threadpool threads.
-      - See test1.rs for an example.
+      stops threads and waits for them to exit
+      - clears run flag, tested by processing functions
+        between items, see test1.rs for an example
+      - closes input queue, waking threads parked in de_q,
+        which returns None once queue is empty
+      - work in progress finishes before its thread exits
     */
     pub fn stop(&mut self) {
-        self.run.store(false, Ordering::Relaxed);
+        /*-- closed first, so executor threads drain the queue --*/
+        self.sibq.close();
+        self.run.store(false, Ordering::SeqCst);
+        for flag in &self.flags {
+            flag.store(false, Ordering::SeqCst);
+        }
+        self.wait();
     }
     /*-- test stopping signal --*/
     pub fn run(&self) -> &Arc<AtomicBool> {
         &self.run
     }
 }
+impl ThreadPool<Job> {
+    /*-----------------------------------------------------
+      construct executor of nt threads, running jobs
+      queued by execute
+      - stop runs every job already queued, then joins
+        the threads
+      - resize, monitoring, and on_panic work as for any
+        ThreadPool
+    */
+    pub fn new_executor(nt:usize) -> ThreadPool<Job> {
+        Self::start(nt, "executor", BlockingQueue::<Job>::new(), run_jobs)
+    }
+    /*-----------------------------------------------------
+      queue job to run on a pool thread
+      - results come back however job sends them, e.g.,
+        over a channel it captures
+      - discarded once the pool is stopped
+    */
+    pub fn execute<J>(&self, job: J)
+    where J: FnOnce() + Send + 'static
+    {
+        self.sibq.en_q(Box::new(job));
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     #[test]
     fn test_new() {
-        let test = |bq:&BlockingQueue<String>| { 
+        let test = |bq:&BlockingQueue<String>, _run:&Arc<AtomicBool>| { 
             let msg = bq.de_q();
             print!("\n  {:?}", msg);
         };
@@ -112,4 +405,177 @@ mod tests {
         tp.post("quit".to_string());
         tp.wait();
     }
+    #[test]
+    fn stop_drains_work_and_joins_threads() {
+        static DONE: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        static EXITED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let work = |bq:&BlockingQueue<u64>, _run:&Arc<AtomicBool>| {
+            while let Some(ms) = bq.de_q() {
+                sleep(std::time::Duration::from_millis(ms));
+                DONE.fetch_add(1, Ordering::SeqCst);
+            }
+            EXITED.fetch_add(1, Ordering::SeqCst);
+        };
+        let mut tp = ThreadPool::<u64>::new(3, work);
+        for _ in 0..9 {
+            tp.post(5);
+        }
+        tp.stop();
+        assert_eq!(DONE.load(Ordering::SeqCst), 9);
+        assert_eq!(EXITED.load(Ordering::SeqCst), 3);
+        assert!(tp.thrd.iter().all(|h| h.is_none()));
+        tp.wait();
+    }
+    #[test]
+    fn panicking_work_is_recovered() {
+        static DONE: AtomicUsize = AtomicUsize::new(0);
+        static LOGGED: AtomicUsize = AtomicUsize::new(0);
+        let work = |bq:&BlockingQueue<u32>, _run:&Arc<AtomicBool>| {
+            while let Some(n) = bq.de_q() {
+                if n == 0 {
+                    panic!("bad work item");
+                }
+                DONE.fetch_add(1, Ordering::SeqCst);
+            }
+        };
+        let mut tp = ThreadPool::<u32>::new(1, work);
+        tp.on_panic(|msg| {
+            assert_eq!(msg, "bad work item");
+            LOGGED.fetch_add(1, Ordering::SeqCst);
+        });
+        tp.post(0);
+        for n in 1..=5 {
+            tp.post(n);
+        }
+        tp.stop();
+        assert_eq!(DONE.load(Ordering::SeqCst), 5);
+        assert_eq!(tp.panics_recovered(), 1);
+        assert_eq!(LOGGED.load(Ordering::SeqCst), 1);
+    }
+    #[test]
+    fn threads_are_named_with_prefix() {
+        static NAMES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let work = |bq:&BlockingQueue<u8>, _run:&Arc<AtomicBool>| {
+            let name = current().name().unwrap_or("").to_string();
+            NAMES.lock().unwrap().push(name);
+            while bq.de_q().is_some() {}
+        };
+        let mut tp = ThreadPool::<u8>::new_named(2, "testpool", work);
+        tp.stop();
+        let mut names = NAMES.lock().unwrap().clone();
+        names.sort();
+        assert_eq!(names, vec!["testpool-worker-0", "testpool-worker-1"]);
+    }
+    #[test]
+    fn active_count_and_queue_depth() {
+        static RELEASE: AtomicBool = AtomicBool::new(false);
+        let wait_until = |done: &dyn Fn() -> bool| {
+            for _ in 0..500 {
+                if done() {
+                    return true;
+                }
+                sleep(std::time::Duration::from_millis(10));
+            }
+            false
+        };
+        /*-- an executor's threads are active only while running jobs --*/
+        let mut tp = ThreadPool::new_executor(2);
+        assert_eq!(tp.configured_size(), 2);
+        assert_eq!(tp.active_count(), 0);
+        let job = || {
+            while !RELEASE.load(Ordering::SeqCst) {
+                sleep(std::time::Duration::from_millis(1));
+            }
+        };
+        tp.execute(job);
+        assert!(wait_until(&|| tp.active_count() == 1));
+        tp.execute(job);
+        tp.execute(job);
+        assert!(wait_until(&|| tp.active_count() == 2));
+        assert_eq!(tp.queue_depth(), 1);
+        RELEASE.store(true, Ordering::SeqCst);
+        assert!(wait_until(&|| tp.active_count() == 0));
+        tp.stop();
+        assert_eq!(tp.queue_depth(), 0);
+        assert_eq!(tp.configured_size(), 0);
+
+        /*-- other threads count while they hold busy's guard --*/
+        static GO: AtomicBool = AtomicBool::new(false);
+        let work = |bq:&BlockingQueue<u8>, _run:&Arc<AtomicBool>| {
+            while bq.de_q().is_some() {
+                let _busy = busy();
+                while !GO.load(Ordering::SeqCst) {
+                    sleep(std::time::Duration::from_millis(1));
+                }
+            }
+        };
+        let mut tp = ThreadPool::<u8>::new(2, work);
+        /*-- idle workers, blocked in de_q, aren't counted --*/
+        assert!(wait_until(&|| tp.sibq.waiting() == 2));
+        assert_eq!(tp.active_count(), 0);
+        tp.post(1);
+        assert!(wait_until(&|| tp.active_count() == 1));
+        GO.store(true, Ordering::SeqCst);
+        assert!(wait_until(&|| tp.active_count() == 0));
+        tp.stop();
+        assert_eq!(tp.active_count(), 0);
+    }
+    #[test]
+    fn executor_runs_every_job() {
+        let mut tp = ThreadPool::new_executor(3);
+        let (tx, rx) = mpsc::channel();
+        for n in 0..50u64 {
+            let tx = tx.clone();
+            tp.execute(move || {
+                if n == 7 {
+                    panic!("bad job");
+                }
+                tx.send(n).unwrap();
+            });
+        }
+        tp.resize(1);
+        tp.stop();
+        drop(tx);
+        let mut done: Vec<u64> = rx.iter().collect();
+        done.sort_unstable();
+        let expected: Vec<u64> = (0..50).filter(|n| *n != 7).collect();
+        assert_eq!(done, expected);
+        assert_eq!(tp.panics_recovered(), 1);
+        assert_eq!(tp.size(), 0);
+    }
+    #[test]
+    fn resize_grows_and_retires_threads() {
+        /*-- blocks, so idle threads retire only if resize wakes them --*/
+        let work = |bq:&BlockingQueue<u64>, run:&Arc<AtomicBool>| {
+            while let Some(ms) = bq.de_q_while(run) {
+                sleep(std::time::Duration::from_millis(ms));
+            }
+        };
+        let wait_for_size = |tp: &ThreadPool<u64>, n: usize| {
+            for _ in 0..500 {
+                if tp.size() == n {
+                    return true;
+                }
+                sleep(std::time::Duration::from_millis(10));
+            }
+            false
+        };
+        let mut tp = ThreadPool::<u64>::new(2, work);
+        assert_eq!(tp.size(), 2);
+        tp.resize(4);
+        assert_eq!(tp.size(), 4);
+
+        /*-- a busy thread finishes its item before retiring --*/
+        tp.post(100);
+        sleep(std::time::Duration::from_millis(20));
+        tp.resize(1);
+        assert!(wait_for_size(&tp, 1));
+        assert_eq!(tp.queue_depth(), 0);
+        assert_eq!(tp.sibq.total_enqueued(), 1);
+        tp.resize(3);
+        assert_eq!(tp.size(), 3);
+        assert_eq!(tp.thrd.len(), 3);
+        tp.stop();
+        assert_eq!(tp.size(), 0);
+    }
 }
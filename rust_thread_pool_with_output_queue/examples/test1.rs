@@ -24,9 +24,8 @@ pub fn test_message_in_pool(
 ) {
     let q = String::from("quit");
     let id = thread::current().id();
-    loop {
-        /*-- process message --*/
-        let msg = tp.de_q();
+    /*-- process message --*/
+    while let Some(msg) = tp.de_q() {
         print!("\n  deQed {:<12} : {:?}", msg, id);
         thread::yield_now();
         /*-- prepare reply --*/
@@ -57,7 +56,7 @@ pub fn post_message_to_pool() {
     loop {
         let msg = tp.get();
         print!("\n  received: {:?}", msg);
-        if msg == "quit".to_string() {
+        if msg == "quit" {
             break;
         }
     }
@@ -128,8 +127,7 @@ fn test0() {
     let handle = thread::spawn(move || {
         print!("\n  child thread started");
         flush();
-        loop {
-            let t = share1.de_q();
+        while let Some(t) = share1.de_q() {
             print!("\n  dequeued {} on child thread", t);
             flush();
             if &t == "quit" {
@@ -142,7 +140,7 @@ fn test0() {
 
     /*-- main thread enqueues messages --*/
     for i in 0..5 {
-        let msg = format!("msg #{}", i.to_string());
+        let msg = format!("msg #{}", i);
         print!("\n  enqueued {:?} on main thread", msg);
         flush();
         share2.en_q(msg);
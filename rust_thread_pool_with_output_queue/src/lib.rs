@@ -33,7 +33,7 @@ where M: Send + 'static
       provide threadpool processing as f:F in new 
     */
     pub fn new<F>(nt:u8, f:F) -> ThreadPool<M> 
-    where F: FnOnce(&BlockingQueue<M>, &BlockingQueue<M>) -> () + Send + 'static + Copy
+    where F: FnOnce(&BlockingQueue<M>, &BlockingQueue<M>) + Send + 'static + Copy
     {
         /* safely share BlockingQueue with Arc */
         let siqm = Arc::new(BlockingQueue::<M>::new());
@@ -84,8 +84,7 @@ where M: Send + 'static
     /*-- return results to caller --*/
     pub fn get(&mut self) -> M 
     where M:Debug + Default {
-        let m:M = self.sobq.de_q();
-        m
+        self.sobq.de_q().unwrap_or_default()
     }
     /*-- return results to caller --*/
     pub fn done(&mut self) -> bool {
@@ -98,7 +97,7 @@ mod tests {
     use super::*;
     #[test]
     fn test_new() {
-        let test = |bq:&BlockingQueue<String>| { 
+        let test = |bq:&BlockingQueue<String>, _out:&BlockingQueue<String>| { 
             let msg = bq.de_q();
             print!("\n  {:?}", msg);
         };
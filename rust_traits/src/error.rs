@@ -0,0 +1,88 @@
+/////////////////////////////////////////////////////////////
+// rust_traits::error.rs - errors reported by rust_comm    //
+//                                                         //
+// RustCommWithThreadPool contributors                     //
+/////////////////////////////////////////////////////////////
+/*
+   CommError:
+   - NotConnected, connection is closed, dead, or shut down
+   - FrameTooLarge, a frame longer than the receiver's, or
+     sender's, limit, rejected before its body is read
+   - ChecksumMismatch, a message flagged FLAG_CRC32 whose
+     body doesn't match its CRC32, e.g., corrupted in
+     transit
+   - InvalidMessage, a message that can't be built as
+     asked, e.g., by Message::builder, saying why
+   - ProtocolMismatch, a peer whose connection preamble
+     names another protocol version, actual, or, when
+     actual is None, isn't a rust_comm preamble at all
+   - Io, wraps the std::io::Error that caused the failure
+
+   ConnectionEvent, from Connector::events:
+   - Connected, the Connector's first connection is up,
+     sent first to a subscriber that finds it connected
+   - Disconnected, the connection failed, its peer
+     stopped answering heartbeats, or it was shut down
+   - Reconnected, reconnect brought the connection back
+   - SequenceGap, with CommConfig::verify_sequence, a
+     message arrived numbered got when expected was next,
+     so messages were lost, repeated, or reordered
+
+   Codecs report errors through std::io::Result, so they
+   wrap a CommError in an io::Error, recovered with
+   io::Error::get_ref and downcast_ref.
+*/
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum CommError {
+    NotConnected,
+    FrameTooLarge { len: usize, max: usize },
+    ChecksumMismatch { expected: u32, actual: u32 },
+    InvalidMessage(String),
+    ProtocolMismatch { expected: u8, actual: Option<u8> },
+    Io(std::io::Error),
+}
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    Connected,
+    Disconnected,
+    Reconnected,
+    SequenceGap { expected: u64, got: u64 },
+}
+
+impl fmt::Display for CommError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommError::NotConnected => write!(f, "not connected"),
+            CommError::FrameTooLarge { len, max } => {
+                write!(f, "frame of {} bytes exceeds limit of {} bytes", len, max)
+            }
+            CommError::ChecksumMismatch { expected, actual } => {
+                write!(f, "checksum mismatch: sent {:08x}, received {:08x}", expected, actual)
+            }
+            CommError::InvalidMessage(why) => write!(f, "invalid message: {}", why),
+            CommError::ProtocolMismatch { expected, actual: Some(actual) } => {
+                write!(f, "protocol mismatch: peer speaks version {}, expected {}", actual, expected)
+            }
+            CommError::ProtocolMismatch { actual: None, .. } => {
+                write!(f, "protocol mismatch: peer sent no rust_comm preamble")
+            }
+            CommError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+impl std::error::Error for CommError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CommError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<std::io::Error> for CommError {
+    fn from(e: std::io::Error) -> CommError {
+        CommError::Io(e)
+    }
+}
@@ -5,23 +5,95 @@
 /////////////////////////////////////////////////////////////
 /*
    Defines traits used for rust_comm:
-   - Logger
+   - Logger, with LogLevel threshold set at run time
    - MsgType
    - Msg
    - Sndr<M>
    - Rcvr<M>
    - Process<M>
+   - StatefulProcess<M>, implemented for Process<M> types
+   - Codec<M>
+   - Transport, implemented for TcpStream
+   and, in module error:
+   - CommError
 */
 
-use std::net::{TcpStream};
-use std::io::{BufReader, BufWriter, Result};
+mod error;
+pub use error::*;
+
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::io::{BufReader, BufWriter, Read, Result, Write};
 use std::str::Utf8Error;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
 // use rust_blocking_queue::*;
 
 // pub const MSG_SIZE:usize = 4096;
 
+/*-- severity of log message, least verbose first --*/
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+}
+impl LogLevel {
+    fn from_u8(level: u8) -> LogLevel {
+        match level {
+            0 => LogLevel::Error,
+            1 => LogLevel::Warn,
+            2 => LogLevel::Info,
+            _ => LogLevel::Debug,
+        }
+    }
+    fn tag(self) -> &'static str {
+        match self {
+            LogLevel::Error => "[error]",
+            LogLevel::Warn => "[warn]",
+            LogLevel::Info => "[info]",
+            LogLevel::Debug => "[debug]",
+        }
+    }
+}
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/*-- messages less severe than level are dropped, default Info --*/
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+pub fn log_level() -> LogLevel {
+    LogLevel::from_u8(LOG_LEVEL.load(Ordering::Relaxed))
+}
+
+/*-----------------------------------------------------
+  Loggers implement write, the leveled functions pass
+  messages at or above log_level() to write, tagged
+  with their level after any leading newlines
+*/
 pub trait Logger : Send {
     fn write(msg: &str);
+    fn log(level: LogLevel, msg: &str) {
+        if level > log_level() {
+            return;
+        }
+        let body = msg.trim_start_matches('\n');
+        let lead = &msg[..msg.len() - body.len()];
+        Self::write(&format!("{}{} {}", lead, level.tag(), body));
+    }
+    fn error(msg: &str) {
+        Self::log(LogLevel::Error, msg);
+    }
+    fn warn(msg: &str) {
+        Self::log(LogLevel::Warn, msg);
+    }
+    fn info(msg: &str) {
+        Self::log(LogLevel::Info, msg);
+    }
+    fn debug(msg: &str) {
+        Self::log(LogLevel::Debug, msg);
+    }
 }
 
 pub trait MsgType : Send + std::fmt::Debug {
@@ -36,6 +108,14 @@ pub trait Msg : Send + std::fmt::Debug {
     fn is_empty(&self) -> bool;
     fn set_type(&mut self, mt:u8);
     fn get_type(&self) -> u8;
+    fn set_id(&mut self, id:u64);
+    fn get_id(&self) -> u64;
+    fn set_flags(&mut self, flags:u8);
+    fn get_flags(&self) -> u8;
+    fn set_topic(&mut self, topic:&str);
+    fn get_topic(&self) -> &str;
+    fn set_seq(&mut self, seq:u64);
+    fn get_seq(&self) -> u64;
     fn set_content_bytes(&mut self, buff: &[u8]);
     fn get_content_bytes(&self) -> &[u8];
     fn set_content_str(&mut self, s: &str);
@@ -50,27 +130,174 @@ pub trait Msg : Send + std::fmt::Debug {
     fn get_mut_ref(&mut self) -> &mut Vec<u8>;
     fn type_display(&self) -> String;
 }
+/*---------------------------------------------------------
+  Transport - byte stream that messages are sent over
+  - clones share one connection, as TcpStream clones do,
+    so one thread can read while another writes
+  - shutdown(Shutdown::Write) signals end of stream to
+    the peer, whose reads then return Ok(0)
+  - local_addr and peer_addr report the connection's two
+    ends, as TcpStream's do
+  - set_read_timeout makes a read with nothing to read
+    fail, WouldBlock or TimedOut, after dur, for every
+    clone, transports without timeouts ignore it
+*/
+pub trait Transport : Read + Write + Send + Sized + std::fmt::Debug + 'static {
+    fn try_clone(&self) -> Result<Self>;
+    fn shutdown(&self, how: Shutdown) -> Result<()>;
+    fn peer_addr(&self) -> Result<SocketAddr>;
+    fn local_addr(&self) -> Result<SocketAddr>;
+    fn set_read_timeout(&self, _dur: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+}
+impl Transport for TcpStream {
+    fn try_clone(&self) -> Result<Self> {
+        TcpStream::try_clone(self)
+    }
+    fn shutdown(&self, how: Shutdown) -> Result<()> {
+        TcpStream::shutdown(self, how)
+    }
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+    fn local_addr(&self) -> Result<SocketAddr> {
+        TcpStream::local_addr(self)
+    }
+    fn set_read_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        TcpStream::set_read_timeout(self, dur)
+    }
+}
+/*---------------------------------------------------------
+  Sndr<M> and Rcvr<M> move messages over a Transport
+  - buffered sends and receives also return the bytes
+    the message took on the wire, framing included, so
+    callers can account traffic without measuring it
+*/
 pub trait Sndr<M> : Send 
 where M: Msg + Clone + Send + Default,
 {
-    fn send_message(msg: &M, stream: &mut TcpStream) -> Result<()>;
-    fn buf_send_message(msg: &M, stream: &mut BufWriter<TcpStream>) -> Result<()>;
+    fn send_message<T: Transport>(msg: &M, stream: &mut T) -> Result<()>;
+    fn buf_send_message<T: Transport>(msg: &M, stream: &mut BufWriter<T>) -> Result<usize>;
+    /*-- write msg, leaving the caller to flush, e.g., after a batch --*/
+    fn buf_write_message<T: Transport>(msg: &M, stream: &mut BufWriter<T>) -> Result<usize> {
+        Self::buf_send_message(msg, stream)
+    }
+    /*-----------------------------------------------------
+      write a message of type msg_type with body, without
+      copying body into an M, leaving the caller to flush
+      - senders that can't are Unsupported
+    */
+    fn buf_write_bytes<T: Transport>(
+        _msg_type: u8, _id: u64, _body: &[u8], _stream: &mut BufWriter<T>
+    ) -> Result<usize> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported, "sender can't write borrowed bodies"
+        ))
+    }
 }
 pub trait Rcvr<M>: Send 
 where M: Msg + Clone + Send + Default,
 {
-    fn recv_message(stream: &mut TcpStream) -> Result<M>;
-    fn buf_recv_message(stream: &mut BufReader<TcpStream>) -> Result<M>;
+    fn recv_message<T: Transport>(stream: &mut T) -> Result<M>;
+    /*-- message and the bytes it took --*/
+    fn buf_recv_message<T: Transport>(stream: &mut BufReader<T>) -> Result<(M, usize)>;
 }
 pub trait Process<M> : Send 
 where M: Msg + Clone + Send + Default,
 {
     fn process_message(m: &mut M);
 }
+/*---------------------------------------------------------
+  StatefulProcess<M> handles messages with per-connection
+  state
+  - Listener makes one P::default() for each session and
+    calls process for each of its messages, so state, e.g.,
+    a message count or auth status, lasts until the
+    connection ends
+  - every Process<M> type with Default is one, keeping no
+    state
+*/
+pub trait StatefulProcess<M> : Send + Default
+where M: Msg + Clone + Send + Default,
+{
+    fn process(&mut self, m: &mut M);
+}
+impl<M, P> StatefulProcess<M> for P
+where 
+    M: Msg + Clone + Send + Default,
+    P: Process<M> + Default
+{
+    fn process(&mut self, m: &mut M) {
+        P::process_message(m);
+    }
+}
+/*---------------------------------------------------------
+  Codec<M> defines wire format of M
+  - encode writes one complete message
+  - decode reads exactly one message, so the reader is
+    left at the start of the next, however many reads it
+    arrives in, e.g., using read_exact, and fails only at
+    end of stream or on an error other than Interrupted
+  - decode_max is decode refusing, with FrameTooLarge,
+    a message whose content, with its topic, would be
+    longer than max, before allocating for it; decode,
+    the default, for formats that can't tell in advance
+  - encode_header returns the bytes written ahead of a
+    message's content, for formats that write content
+    as is after a header, so a borrowed body can be sent
+    without copying; None, the default, for formats that
+    transform content
+*/
+pub trait Codec<M> : Send 
+where M: Msg + Clone + Send + Default,
+{
+    fn encode<W: Write>(msg: &M, writer: &mut W) -> Result<()>;
+    fn decode<R: Read>(reader: &mut R) -> Result<M>;
+    fn decode_max<R: Read>(reader: &mut R, _max: usize) -> Result<M> {
+        Self::decode(reader)
+    }
+    fn encode_header(
+        _msg_type: u8, _id: u64, _flags: u8, _content_len: usize
+    ) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+}
 #[cfg(test)]
 mod tests {
+    use super::*;
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+    #[test]
+    fn log_levels_filter_and_tag() {
+        static OUT: std::sync::Mutex<String> = std::sync::Mutex::new(String::new());
+        struct TestLog;
+        impl Logger for TestLog {
+            fn write(msg: &str) {
+                OUT.lock().unwrap().push_str(msg);
+            }
+        }
+        assert_eq!(log_level(), LogLevel::Info);
+        TestLog::debug("\n  hidden");
+        TestLog::info("\n  shown");
+        TestLog::error("failed");
+        set_log_level(LogLevel::Debug);
+        TestLog::debug("\nvisible");
+        set_log_level(LogLevel::Error);
+        TestLog::warn("\n  hidden");
+        set_log_level(LogLevel::Info);
+        assert_eq!(
+            *OUT.lock().unwrap(),
+            "\n[info]   shown[error] failed\n[debug] visible"
+        );
+    }
+    #[test]
+    fn comm_error_from_io() {
+        let io_err = std::io::Error::new(std::io::ErrorKind::BrokenPipe, "pipe");
+        let err: CommError = io_err.into();
+        assert!(matches!(err, CommError::Io(_)));
+        assert_eq!(CommError::NotConnected.to_string(), "not connected");
+    }
 }